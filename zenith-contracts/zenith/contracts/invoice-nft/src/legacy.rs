@@ -0,0 +1,386 @@
+//! Invoice layouts from earlier schema versions, kept so stored invoices can
+//! still be read and rewritten after an upgrade.
+
+use soroban_sdk::{contracttype, Address, BytesN, Env, Map, String, Symbol, TryFromVal, Val};
+
+use zenith_common::customer;
+
+use crate::{
+    Currency, DataKey, FinancialTerms, Invoice, InvoiceDetails, InvoiceStatus, RiskAssessment, RiskGrade,
+    Timestamps, VerificationLevel, VerificationMethod, SCHEMA_VERSION,
+};
+
+/// Salt for commitments derived on-chain from plaintext stored before version 3
+pub const LEGACY_SALT: [u8; 32] = [0u8; 32];
+
+/// Invoice status before versioning, when approved invoices were listed as open
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub enum InvoiceStatusV0 {
+    Pending,
+    Open,
+    Funded,
+    Completed,
+    Overdue,
+}
+
+impl InvoiceStatusV0 {
+    pub fn upgrade(&self) -> InvoiceStatus {
+        match self {
+            InvoiceStatusV0::Pending => InvoiceStatus::Pending,
+            InvoiceStatusV0::Open => InvoiceStatus::Approved,
+            InvoiceStatusV0::Funded => InvoiceStatus::Funded,
+            InvoiceStatusV0::Completed => InvoiceStatus::Completed,
+            InvoiceStatusV0::Overdue => InvoiceStatus::Overdue,
+        }
+    }
+}
+
+/// Keys of the `Vec<u64>` status lists kept before versioning
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub enum DataKeyV0 {
+    InvoicesByStatus(InvoiceStatusV0),
+}
+
+/// Every status a list could be kept for before versioning
+pub const STATUSES_V0: [InvoiceStatusV0; 5] = [
+    InvoiceStatusV0::Pending,
+    InvoiceStatusV0::Open,
+    InvoiceStatusV0::Funded,
+    InvoiceStatusV0::Completed,
+    InvoiceStatusV0::Overdue,
+];
+
+/// Creator details copied into each invoice before version 4
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct CreatorInfo {
+    pub business_name: String,
+    pub location: String,
+    pub trust_score: u32,
+    pub verification_level: VerificationLevel,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct CreatorInfoV1 {
+    pub business_name: String,
+    pub location: String,
+    pub trust_score: u32,
+    pub verification_level: String, // "verified" | "pending" | "unverified"
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceDetailsV1 {
+    pub amount: i128,
+    pub currency: String, // "USDC" | "EURC"
+    pub due_date: u64,
+    pub customer_name: String,
+    pub customer_email: String,
+    pub description: String,
+    pub tax_rate: Option<u32>,
+    pub early_discount: u32,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct RiskAssessmentV1 {
+    pub risk_score: String, // "A" | "B" | "C" | "D"
+    pub esg_compliant: Option<bool>,
+    pub verification_method: String, // "manual" | "automated" | "integrated"
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct TimestampsV0 {
+    pub created_at: u64,
+    pub funded_at: Option<u64>,
+    pub due_date: u64,
+    pub completed_at: Option<u64>,
+}
+
+/// Invoice as stored before versioning, before amendments and cancellation
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceV0 {
+    pub id: u64,
+    pub title: String,
+    pub symbol: String,
+    pub status: InvoiceStatusV0,
+    pub creator: Address,
+    pub creator_info: CreatorInfoV1,
+    pub invoice_details: InvoiceDetailsV1,
+    pub financial_terms: FinancialTerms,
+    pub risk_assessment: RiskAssessmentV1,
+    pub timestamps: TimestampsV0,
+    pub proof_hash: Option<BytesN<32>>,
+}
+
+/// Invoice as stored by schema version 1, with free-form string fields
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceV1 {
+    pub id: u64,
+    pub title: String,
+    pub symbol: String,
+    pub status: InvoiceStatus,
+    pub creator: Address,
+    pub creator_info: CreatorInfoV1,
+    pub invoice_details: InvoiceDetailsV1,
+    pub financial_terms: FinancialTerms,
+    pub risk_assessment: RiskAssessmentV1,
+    pub timestamps: Timestamps,
+    pub proof_hash: Option<BytesN<32>>,
+    pub amendment_count: u32,
+}
+
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceDetailsV2 {
+    pub amount: i128,
+    pub currency: Currency,
+    pub due_date: u64,
+    pub customer_name: String,
+    pub customer_email: String,
+    pub description: String,
+    pub tax_rate: Option<u32>,
+    pub early_discount: u32,
+}
+
+/// Invoice as stored by schema version 2, with the customer in plaintext
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceV2 {
+    pub id: u64,
+    pub title: String,
+    pub symbol: String,
+    pub status: InvoiceStatus,
+    pub creator: Address,
+    pub creator_info: CreatorInfo,
+    pub invoice_details: InvoiceDetailsV2,
+    pub financial_terms: FinancialTerms,
+    pub risk_assessment: RiskAssessment,
+    pub timestamps: Timestamps,
+    pub proof_hash: Option<BytesN<32>>,
+    pub amendment_count: u32,
+}
+
+/// Invoice as stored by schema version 3, with a copy of the creator's details
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceV3 {
+    pub id: u64,
+    pub title: String,
+    pub symbol: String,
+    pub status: InvoiceStatus,
+    pub creator: Address,
+    pub creator_info: CreatorInfo,
+    pub invoice_details: InvoiceDetails,
+    pub financial_terms: FinancialTerms,
+    pub risk_assessment: RiskAssessment,
+    pub timestamps: Timestamps,
+    pub proof_hash: Option<BytesN<32>>,
+    pub amendment_count: u32,
+}
+
+/// Schema version whose layout a stored invoice uses.
+/// Decoding a nested struct with a different field count traps instead of
+/// failing, so the layout is told apart from its fields before decoding.
+pub fn layout_version(env: &Env, value: &Val) -> u32 {
+    let fields = Map::<Symbol, Val>::try_from_val(env, value).expect("Unreadable invoice");
+    if !fields.contains_key(Symbol::new(env, "creator_info")) {
+        return SCHEMA_VERSION;
+    }
+    if !fields.contains_key(Symbol::new(env, "amendment_count")) {
+        return 0;
+    }
+
+    let details = fields
+        .get(Symbol::new(env, "invoice_details"))
+        .and_then(|details| Map::<Symbol, Val>::try_from_val(env, &details).ok())
+        .expect("Unreadable invoice");
+
+    if !details.contains_key(Symbol::new(env, "customer_name")) {
+        return 3;
+    }
+    match details.get(Symbol::new(env, "currency")) {
+        Some(currency) if String::try_from_val(env, &currency).is_ok() => 1,
+        _ => 2,
+    }
+}
+
+/// Read an invoice, with the creator details it carried, only if it is still stored in an older layout
+pub fn read_legacy_invoice(env: &Env, invoice_id: u64) -> Option<(Invoice, CreatorInfo)> {
+    let value: Val = env.storage().persistent().get(&DataKey::Invoice(invoice_id))?;
+    if layout_version(env, &value) == SCHEMA_VERSION {
+        return None;
+    }
+    Some(legacy_invoice_from_val(env, &value))
+}
+
+/// Convert a stored invoice in an older layout to the current one,
+/// returning the creator details it carried alongside
+pub fn legacy_invoice_from_val(env: &Env, value: &Val) -> (Invoice, CreatorInfo) {
+    let v3 = match layout_version(env, value) {
+        0 => v2_to_v3(env, v1_to_v2(env, v0_to_v1(env, value))),
+        1 => v2_to_v3(env, v1_to_v2(env, InvoiceV1::try_from_val(env, value).expect("Unreadable invoice"))),
+        2 => v2_to_v3(env, InvoiceV2::try_from_val(env, value).expect("Unreadable invoice")),
+        _ => InvoiceV3::try_from_val(env, value).expect("Unreadable invoice"),
+    };
+    v3_to_v4(v3)
+}
+
+/// Split the copied creator details off the invoice, leaving them to the creator's profile
+fn v3_to_v4(old: InvoiceV3) -> (Invoice, CreatorInfo) {
+    let invoice = Invoice {
+        id: old.id,
+        title: old.title,
+        symbol: old.symbol,
+        status: old.status,
+        creator: old.creator,
+        invoice_details: old.invoice_details,
+        financial_terms: old.financial_terms,
+        risk_assessment: old.risk_assessment,
+        timestamps: old.timestamps,
+        proof_hash: old.proof_hash,
+        amendment_count: old.amendment_count,
+    };
+    (invoice, old.creator_info)
+}
+
+/// Replace the plaintext customer with commitments under `LEGACY_SALT`,
+/// so the stored name and email can still be verified after the upgrade.
+/// The debtor id uses the same salt, as the email was already public.
+fn v2_to_v3(env: &Env, old: InvoiceV2) -> InvoiceV3 {
+    let salt = BytesN::from_array(env, &LEGACY_SALT);
+    let customer_commitment = customer::commitment(
+        env,
+        &salt,
+        &salt,
+        &old.invoice_details.customer_name,
+        &old.invoice_details.customer_email,
+        None,
+    );
+
+    InvoiceV3 {
+        id: old.id,
+        title: old.title,
+        symbol: old.symbol,
+        status: old.status,
+        creator: old.creator,
+        creator_info: old.creator_info,
+        invoice_details: InvoiceDetails {
+            amount: old.invoice_details.amount,
+            currency: old.invoice_details.currency,
+            due_date: old.invoice_details.due_date,
+            customer_commitment,
+            description: old.invoice_details.description,
+            tax_rate: old.invoice_details.tax_rate,
+            early_discount: old.invoice_details.early_discount,
+        },
+        financial_terms: old.financial_terms,
+        risk_assessment: old.risk_assessment,
+        timestamps: old.timestamps,
+        proof_hash: old.proof_hash,
+        amendment_count: old.amendment_count,
+    }
+}
+
+/// Type the free-form fields of a version 1 invoice
+fn v1_to_v2(env: &Env, old: InvoiceV1) -> InvoiceV2 {
+    InvoiceV2 {
+        id: old.id,
+        title: old.title,
+        symbol: old.symbol,
+        status: old.status,
+        creator: old.creator,
+        creator_info: CreatorInfo {
+            business_name: old.creator_info.business_name,
+            location: old.creator_info.location,
+            trust_score: old.creator_info.trust_score,
+            verification_level: parse_verification_level(env, &old.creator_info.verification_level),
+        },
+        invoice_details: InvoiceDetailsV2 {
+            amount: old.invoice_details.amount,
+            currency: parse_currency(env, &old.invoice_details.currency),
+            due_date: old.invoice_details.due_date,
+            customer_name: old.invoice_details.customer_name,
+            customer_email: old.invoice_details.customer_email,
+            description: old.invoice_details.description,
+            tax_rate: old.invoice_details.tax_rate,
+            early_discount: old.invoice_details.early_discount,
+        },
+        financial_terms: old.financial_terms,
+        risk_assessment: RiskAssessment {
+            risk_score: RiskGrade::from_label(&old.risk_assessment.risk_score).expect("Invalid risk score"),
+            esg_compliant: old.risk_assessment.esg_compliant,
+            verification_method: parse_verification_method(env, &old.risk_assessment.verification_method),
+        },
+        timestamps: old.timestamps,
+        proof_hash: old.proof_hash,
+        amendment_count: old.amendment_count,
+    }
+}
+
+/// Start the amendment count and cancellation time of a stored unversioned
+/// invoice, listing invoices that were open as approved
+fn v0_to_v1(env: &Env, value: &Val) -> InvoiceV1 {
+    let old = InvoiceV0::try_from_val(env, value).expect("Unreadable invoice");
+
+    InvoiceV1 {
+        id: old.id,
+        title: old.title,
+        symbol: old.symbol,
+        status: old.status.upgrade(),
+        creator: old.creator,
+        creator_info: old.creator_info,
+        invoice_details: old.invoice_details,
+        financial_terms: old.financial_terms,
+        risk_assessment: old.risk_assessment,
+        timestamps: Timestamps {
+            created_at: old.timestamps.created_at,
+            funded_at: old.timestamps.funded_at,
+            due_date: old.timestamps.due_date,
+            completed_at: old.timestamps.completed_at,
+            cancelled_at: None,
+        },
+        proof_hash: old.proof_hash,
+        amendment_count: 0,
+    }
+}
+
+fn parse_currency(env: &Env, label: &String) -> Currency {
+    if *label == String::from_str(env, "USDC") {
+        Currency::Usdc
+    } else if *label == String::from_str(env, "EURC") {
+        Currency::Eurc
+    } else {
+        panic!("Unsupported currency");
+    }
+}
+
+fn parse_verification_level(env: &Env, label: &String) -> VerificationLevel {
+    if *label == String::from_str(env, "verified") {
+        VerificationLevel::Verified
+    } else if *label == String::from_str(env, "pending") {
+        VerificationLevel::Pending
+    } else if *label == String::from_str(env, "unverified") {
+        VerificationLevel::Unverified
+    } else {
+        panic!("Invalid verification level");
+    }
+}
+
+fn parse_verification_method(env: &Env, label: &String) -> VerificationMethod {
+    if *label == String::from_str(env, "manual") {
+        VerificationMethod::Manual
+    } else if *label == String::from_str(env, "automated") {
+        VerificationMethod::Automated
+    } else if *label == String::from_str(env, "integrated") {
+        VerificationMethod::Integrated
+    } else {
+        panic!("Invalid verification method");
+    }
+}
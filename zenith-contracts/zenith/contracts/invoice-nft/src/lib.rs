@@ -1,45 +1,143 @@
 #![no_std]
 use soroban_sdk::{
     contract, contractimpl, contracttype, symbol_short,
-    Address, Env, String, Vec, BytesN
+    Address, Env, String, Symbol, TryFromVal, Val, Vec, BytesN
 };
 
+use zenith_common::ttl::{
+    INSTANCE_BUMP_AMOUNT, INSTANCE_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use zenith_common::math::is_valid_bps;
+use zenith_common::{asset, attestation, customer, fingerprint, index, installment, pricing, recurring, risk, tax, time};
+
+pub use zenith_common::{
+    AssetConfig, Attestation, AttestationClaim, Currency, CustomerCommitment, CustomerField, FinancingRule, Installment, InvoiceAmendment, InvoicePage, InvoiceStatus, InvoiceTerms, PricingConfig, Quote, QuoteParams,
+    RecurringRoll, RecurringSchedule, RiskFactors, RiskGrade, RiskOverride, TaxBasis, TaxBreakdown, VerificationLevel, VerificationMethod,
+};
+
+mod legacy;
+
+/// Storage layout version written by this build; bump it alongside a new `migrate` step
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// Highest score an ESG certification can carry
+pub const MAX_ESG_SCORE: u32 = 100;
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
+    Admin,
+    SchemaVersion,
     InvoiceCounter,
     Invoice(u64),
     InvoicesByCreator(Address),
     InvoicesByStatus(InvoiceStatus),
+    TrustScore(Address), // before version 4, now kept in the creator's profile
+    PricingConfig,
+    CreatorHistory(Address),
+    DebtorHistory(String), // customer email, before version 3
+    RiskOverride(u64), // invoice_id
+    Asset(Currency),
+    ProofHash(BytesN<32>), // invoice registered for a document hash
+    Fingerprint(BytesN<32>), // invoice registered for a normalized fingerprint
+    InvoiceNumber(u64), // creator's own reference, by invoice_id
+    Verifier(Address),
+    FraudFlags(u64), // invoice_id
+    Customer(u64), // invoice_id
+    Acknowledgement(u64), // invoice_id
+    Debtor(BytesN<32>), // debtor history, by debtor_id
+    TaxBasis(u64), // invoice_id, only stored for gross amounts
+    FinancingRule,
+    RecurringCounter,
+    RecurringInvoice(u64), // template_id
+    Installments(u64), // invoice_id, only stored for invoices paid in installments
+    AmountPaid(u64), // invoice_id, only stored once a payment is recorded
+    EsgVerifier(Address),
+    InvoiceEsg(u64), // invoice_id
+    CreatorEsg(Address),
+    Attestations(u64), // invoice_id
+    CreatorAttestations(Address), // conclusive attestations on the creator's invoices
+    CreatorProfile(Address),
+    TrustOracle(Address),
+    Marketplace,
+}
+
+/// A creator's business details and standing, shared by all of their invoices
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct CreatorProfile {
+    pub business_name: String,
+    pub location: String, // city and jurisdiction
+    pub trust_score: u32,
+    pub verification_level: VerificationLevel,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+/// Suspected fraud raised against an invoice by a verifier
+#[derive(Clone, PartialEq, Eq, Debug)]
 #[contracttype]
-pub enum InvoiceStatus {
-    Pending,
-    Open,
-    Funded,
-    Completed,
-    Overdue,
+pub struct FraudFlag {
+    pub verifier: Address,
+    pub reason: String,
+    pub flagged_at: u64,
 }
 
-#[derive(Clone)]
+/// ESG certification a registered verifier signed for an invoice or a creator
+#[derive(Clone, PartialEq, Eq, Debug)]
 #[contracttype]
-pub struct CreatorInfo {
-    pub business_name: String,
-    pub location: String,
-    pub trust_score: u32,
-    pub verification_level: String, // "verified" | "pending" | "unverified"
+pub struct EsgCertification {
+    pub verifier: Address,
+    pub framework: String, // e.g. "GRI", "SASB"
+    pub score: u32, // 0 to MAX_ESG_SCORE
+    pub evidence_hash: BytesN<32>, // hash of the verifier's report
+    pub certified_at: u64,
+    pub expires_at: u64,
+}
+
+/// A conclusive attestation counted toward the creator's verification level
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct CreatorAttestation {
+    pub invoice_id: u64,
+    pub verifier: Address,
+    pub source: Symbol,
+    pub expires_at: u64,
+}
+
+/// Customer's on-chain confirmation that they owe an invoice
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct Acknowledgement {
+    pub customer: Address,
+    pub amount: i128,
+    pub due_date: u64,
+    pub acknowledged_at: u64,
+}
+
+/// Track record of a creator, used for risk grading
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct CreatorHistory {
+    pub invoice_count: u32,
+    pub total_volume: i128,
+    pub completed_count: u32,
+    pub defaulted_count: u32,
+}
+
+/// Track record of a debtor across all creators, used for risk grading
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct DebtorHistory {
+    pub paid_count: u32,
+    pub defaulted_count: u32,
 }
 
 #[derive(Clone)]
 #[contracttype]
 pub struct InvoiceDetails {
     pub amount: i128,
-    pub currency: String, // "USDC" | "EURC"
+    pub currency: Currency,
     pub due_date: u64, // timestamp
-    pub customer_name: String,
-    pub customer_email: String,
+    pub customer_commitment: CustomerCommitment,
     pub description: String,
     pub tax_rate: Option<u32>, // basis points (e.g., 1000 = 10%)
     pub early_discount: u32, // basis points
@@ -58,9 +156,9 @@ pub struct FinancialTerms {
 #[derive(Clone)]
 #[contracttype]
 pub struct RiskAssessment {
-    pub risk_score: String, // "A" | "B" | "C" | "D"
-    pub esg_compliant: Option<bool>,
-    pub verification_method: String, // "manual" | "automated" | "integrated"
+    pub risk_score: RiskGrade,
+    pub esg_compliant: Option<bool>, // whether a registered ESG verifier currently certifies the invoice or its creator
+    pub verification_method: VerificationMethod,
 }
 
 #[derive(Clone)]
@@ -70,26 +168,42 @@ pub struct Timestamps {
     pub funded_at: Option<u64>,
     pub due_date: u64,
     pub completed_at: Option<u64>,
+    pub cancelled_at: Option<u64>,
 }
 
 #[derive(Clone)]
 #[contracttype]
 pub struct InvoiceCreationParams {
     pub title: String,
-    pub business_name: String,
-    pub location: String,
+    pub business_name: String, // only used to set up the creator's profile on their first invoice
+    pub location: String, // only used to set up the creator's profile on their first invoice
     pub amount: i128,
-    pub currency: String,
+    pub currency: Currency,
     pub due_date: u64,
-    pub customer_name: String,
-    pub customer_email: String,
+    pub customer_commitment: CustomerCommitment,
     pub description: String,
     pub tax_rate: Option<u32>,
+    pub tax_basis: TaxBasis, // whether `amount` includes tax
     pub early_discount: u32,
     pub collateral_type: Option<String>,
     pub is_recurring: bool,
-    pub esg_compliant: Option<bool>,
+    pub esg_compliant: Option<bool>, // ignored; only an ESG verifier's certification makes an invoice compliant
     pub proof_hash: Option<BytesN<32>>,
+    pub invoice_number: Option<String>, // creator's own reference, used to detect duplicates
+    pub customer: Option<Address>, // account that can acknowledge the invoice
+}
+
+/// Invoice a creator issues every period; each occurrence is minted from `params`
+#[derive(Clone)]
+#[contracttype]
+pub struct RecurringInvoice {
+    pub id: u64,
+    pub creator: Address,
+    pub params: InvoiceCreationParams, // `due_date` is that of the next occurrence
+    pub schedule: RecurringSchedule,
+    pub occurrences: u32,
+    pub last_invoice_id: Option<u64>,
+    pub active: bool,
 }
 
 #[derive(Clone)]
@@ -99,13 +213,13 @@ pub struct Invoice {
     pub title: String,
     pub symbol: String,
     pub status: InvoiceStatus,
-    pub creator: Address,
-    pub creator_info: CreatorInfo,
+    pub creator: Address, // business details and trust score live in the creator's profile
     pub invoice_details: InvoiceDetails,
     pub financial_terms: FinancialTerms,
     pub risk_assessment: RiskAssessment,
     pub timestamps: Timestamps,
     pub proof_hash: Option<BytesN<32>>, // Hash of uploaded invoice document
+    pub amendment_count: u32,
 }
 
 #[contract]
@@ -114,8 +228,154 @@ pub struct InvoiceNFTContract;
 #[contractimpl]
 impl InvoiceNFTContract {
     /// Initialize the contract
-    pub fn initialize(env: Env) {
-        env.storage().instance().set(&DataKey::InvoiceCounter, &0u64);
+    pub fn initialize(env: Env, admin: Address) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        // Storage written before versioning has invoices but no admin; it keeps
+        // its counter and is left unversioned for the admin to `migrate`
+        if !env.storage().instance().has(&DataKey::InvoiceCounter) {
+            env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+            env.storage().instance().set(&DataKey::InvoiceCounter, &0u64);
+        }
+        Self::extend_instance_ttl(&env);
+    }
+
+    /// Replace the contract code, keeping its storage (admin only)
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish((symbol_short!("upgrade"),), new_wasm_hash);
+    }
+
+    /// Register the marketplace allowed to move invoices through their lifecycle (admin only)
+    pub fn set_marketplace(env: Env, admin: Address, marketplace: Address) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        env.storage().instance().set(&DataKey::Marketplace, &marketplace);
+
+        env.events().publish((symbol_short!("mkt"),), (marketplace,));
+    }
+
+    /// Bring stored data up to the layout this build expects (admin only)
+    pub fn migrate(env: Env, admin: Address) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let from_version = Self::get_schema_version(env.clone());
+        if from_version > SCHEMA_VERSION {
+            panic!("Unsupported schema version");
+        }
+
+        // Each step rewrites the layout left by the previous version. Version 1
+        // adds the amendment count and cancellation time and lists open invoices
+        // as approved. Version 2 types the currency, grade and verification fields, version 3
+        // replaces the customer's name and email with hash commitments, and version 4
+        // moves the creator's details out of each invoice into one profile per creator.
+        // Invoices are converted as they are read and can be rewritten with
+        // `migrate_invoices`, which also sets up missing creator profiles;
+        // email-keyed debtor histories are moved with `migrate_debtor_histories`.
+        // Unversioned storage kept each index as one `Vec<u64>`; the status lists
+        // move into linked indexes here and each creator's list with their invoices.
+        if from_version == 0 {
+            for status in legacy::STATUSES_V0 {
+                index::import_list(
+                    &env,
+                    &legacy::DataKeyV0::InvoicesByStatus(status.clone()),
+                    &DataKey::InvoicesByStatus(status.upgrade()),
+                );
+            }
+        }
+
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        env.events().publish((symbol_short!("migrate"),), (from_version, SCHEMA_VERSION));
+
+        SCHEMA_VERSION
+    }
+
+    /// Rewrite invoices stored in an older layout (admin only), setting up a profile from
+    /// the creator details copied into the invoice for creators who have none yet, and
+    /// moving the creator's unversioned invoice list into its index.
+    /// Returns how many were rewritten; missing or current invoices are skipped.
+    pub fn migrate_invoices(env: Env, admin: Address, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let mut migrated = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            if let Some((invoice, creator_info)) = legacy::read_legacy_invoice(&env, invoice_id) {
+                // Write in place so settled invoices keep their remaining TTL
+                env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
+
+                // Move an unversioned `Vec<u64>` creator list into its linked index
+                let creator_key = DataKey::InvoicesByCreator(invoice.creator.clone());
+                index::import_list(&env, &creator_key, &creator_key);
+
+                if !env.storage().persistent().has(&DataKey::CreatorProfile(invoice.creator.clone())) {
+                    let profile = CreatorProfile {
+                        business_name: creator_info.business_name,
+                        location: creator_info.location,
+                        trust_score: Self::get_trust_score(env.clone(), invoice.creator.clone()),
+                        verification_level: creator_info.verification_level,
+                    };
+                    Self::save_profile(&env, &invoice.creator, &profile);
+                }
+                migrated += 1;
+            }
+        }
+
+        migrated
+    }
+
+    /// Move debtor histories stored under a customer email to their debtor id under
+    /// `LEGACY_SALT`, the one upgraded invoices carry, deleting the plaintext key
+    /// (admin only). Returns how many were moved.
+    pub fn migrate_debtor_histories(env: Env, admin: Address, customer_emails: Vec<String>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let mut migrated = 0u32;
+        for email in customer_emails.iter() {
+            let old_key = DataKey::DebtorHistory(email.clone());
+            let old: DebtorHistory = match env.storage().persistent().get(&old_key) {
+                Some(history) => history,
+                None => continue,
+            };
+
+            // Merge with anything recorded under the debtor id since the upgrade
+            let debtor_id = customer::debtor_id(&env, &BytesN::from_array(&env, &legacy::LEGACY_SALT), &email);
+            let mut history = Self::load_debtor_history(&env, &debtor_id);
+            history.paid_count += old.paid_count;
+            history.defaulted_count += old.defaulted_count;
+            Self::save_debtor_history(&env, &debtor_id, &history);
+
+            env.storage().persistent().remove(&old_key);
+            migrated += 1;
+        }
+
+        migrated
+    }
+
+    /// Get the storage layout version (0 for storage written before versioning)
+    pub fn get_schema_version(env: Env) -> u32 {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::SchemaVersion)
+            .unwrap_or(0)
     }
 
     /// Create a new invoice NFT
@@ -124,8 +384,15 @@ impl InvoiceNFTContract {
         creator: Address,
         params: InvoiceCreationParams,
     ) -> u64 {
+        Self::extend_instance_ttl(&env);
+
         creator.require_auth();
 
+        Self::mint_invoice(env, creator, params)
+    }
+
+    /// Mint an invoice for a creator who has already authorized it
+    fn mint_invoice(env: Env, creator: Address, params: InvoiceCreationParams) -> u64 {
         let current_time = env.ledger().timestamp();
         
         // Validate due date is in the future
@@ -138,6 +405,27 @@ impl InvoiceNFTContract {
             panic!("Amount must be positive");
         }
 
+        // Only registered assets can be invoiced, within their limits
+        Self::check_asset_amount(&env, &params.currency, params.amount);
+
+        Self::check_tax_rate(params.tax_rate);
+        Self::check_early_discount(params.early_discount);
+
+        if params.customer.as_ref() == Some(&creator) {
+            panic!("Customer cannot be the creator");
+        }
+
+        // Reject a document or invoice that is already live under another token
+        let fingerprint = params.invoice_number.as_ref().map(|invoice_number| {
+            fingerprint::invoice_fingerprint(&env, &creator, &params.customer_commitment.debtor_id, params.amount, invoice_number)
+        });
+        if let Some(proof_hash) = &params.proof_hash {
+            Self::check_not_registered(&env, &DataKey::ProofHash(proof_hash.clone()));
+        }
+        if let Some(fingerprint) = &fingerprint {
+            Self::check_not_registered(&env, &DataKey::Fingerprint(fingerprint.clone()));
+        }
+
         // Get and increment invoice counter
         let mut counter: u64 = env.storage().instance()
             .get(&DataKey::InvoiceCounter)
@@ -145,14 +433,42 @@ impl InvoiceNFTContract {
         counter += 1;
         env.storage().instance().set(&DataKey::InvoiceCounter, &counter);
 
-        // Calculate offer amount and discount rate
-        let days_until_due = (params.due_date - current_time) / 86400; // Convert to days
-        let discount_rate = Self::calculate_discount_rate(days_until_due);
-        let offer_amount = params.amount * (10000 - discount_rate as i128) / 10000;
-
         // Generate symbol - simple concatenation since format! is not available
         let symbol = String::from_str(&env, "INV");
 
+        // The first invoice fills in the creator's business details; after that only the admin and trust oracles change them
+        let mut profile = Self::load_profile(&env, &creator);
+        if profile.business_name.is_empty() {
+            profile.business_name = params.business_name;
+            profile.location = params.location;
+            Self::save_profile(&env, &creator, &profile);
+        }
+        let trust_score = profile.trust_score;
+
+        let invoice_details = InvoiceDetails {
+            amount: params.amount,
+            currency: params.currency,
+            due_date: params.due_date,
+            customer_commitment: params.customer_commitment,
+            description: params.description,
+            tax_rate: params.tax_rate,
+            early_discount: params.early_discount,
+        };
+
+        // Grade and price the invoice from the creator's and debtor's track record
+        let factors = Self::risk_factors(
+            &env,
+            &creator,
+            trust_score,
+            &invoice_details,
+            params.proof_hash.is_some(),
+            Self::verification_level(&env, &creator) == VerificationLevel::Verified,
+        );
+        let grade = risk::grade(&factors);
+        let breakdown = tax::breakdown(params.amount, params.tax_rate, params.tax_basis);
+        let financeable_amount = tax::financeable_amount(&breakdown, Self::get_financing_rule(env.clone()));
+        let quote = Self::price(&env, grade, trust_score, financeable_amount, params.due_date);
+
         // Create invoice
         let invoice = Invoice {
             id: counter,
@@ -160,59 +476,64 @@ impl InvoiceNFTContract {
             symbol,
             status: InvoiceStatus::Pending,
             creator: creator.clone(),
-            creator_info: CreatorInfo {
-                business_name: params.business_name,
-                location: params.location,
-                trust_score: 50, // Default trust score for new users
-                verification_level: String::from_str(&env, "pending"),
-            },
-            invoice_details: InvoiceDetails {
-                amount: params.amount,
-                currency: params.currency,
-                due_date: params.due_date,
-                customer_name: params.customer_name,
-                customer_email: params.customer_email,
-                description: params.description,
-                tax_rate: params.tax_rate,
-                early_discount: params.early_discount,
-            },
+            invoice_details,
             financial_terms: FinancialTerms {
-                offer_amount,
-                discount_rate,
-                expected_yield: 1000, // 10% APR default
+                offer_amount: quote.offer_amount,
+                discount_rate: quote.discount_rate,
+                expected_yield: quote.expected_yield,
                 collateral_type: params.collateral_type,
                 is_recurring: params.is_recurring,
             },
             risk_assessment: RiskAssessment {
-                risk_score: String::from_str(&env, "C"), // Default risk score
-                esg_compliant: params.esg_compliant,
-                verification_method: String::from_str(&env, "manual"),
+                risk_score: grade,
+                esg_compliant: None,
+                verification_method: VerificationMethod::Automated,
             },
             timestamps: Timestamps {
                 created_at: current_time,
                 funded_at: None,
                 due_date: params.due_date,
                 completed_at: None,
+                cancelled_at: None,
             },
             proof_hash: params.proof_hash,
+            amendment_count: 0,
         };
 
+        // Register the invoice against its document and fingerprint before storing it,
+        // so saving extends the registry entries along with the invoice
+        if let Some(proof_hash) = &invoice.proof_hash {
+            env.storage().persistent().set(&DataKey::ProofHash(proof_hash.clone()), &counter);
+        }
+        if let Some(invoice_number) = params.invoice_number {
+            env.storage().persistent().set(&DataKey::InvoiceNumber(counter), &invoice_number);
+        }
+        if let Some(fingerprint) = &fingerprint {
+            env.storage().persistent().set(&DataKey::Fingerprint(fingerprint.clone()), &counter);
+        }
+        if let Some(customer) = &params.customer {
+            env.storage().persistent().set(&DataKey::Customer(counter), customer);
+        }
+        if params.tax_basis == TaxBasis::Gross {
+            env.storage().persistent().set(&DataKey::TaxBasis(counter), &params.tax_basis);
+        }
+
         // Store the invoice
-        env.storage().persistent().set(&DataKey::Invoice(counter), &invoice);
+        Self::save_invoice(&env, &invoice);
 
-        // Update creator's invoices list
-        let mut creator_invoices: Vec<u64> = env.storage().persistent()
-            .get(&DataKey::InvoicesByCreator(creator.clone()))
-            .unwrap_or(Vec::new(&env));
-        creator_invoices.push_back(counter);
-        env.storage().persistent().set(&DataKey::InvoicesByCreator(creator.clone()), &creator_invoices);
+        // Count the invoice towards the creator's history only after grading it
+        let mut history = Self::load_creator_history(&env, &creator);
+        history.invoice_count += 1;
+        history.total_volume += params.amount;
+        Self::save_creator_history(&env, &creator, &history);
+
+        env.events().publish((symbol_short!("risk"), counter), (grade, factors));
+
+        // Update creator's invoices index
+        index::push(&env, &DataKey::InvoicesByCreator(creator.clone()), counter);
 
         // Update status-based index
-        let mut pending_invoices: Vec<u64> = env.storage().persistent()
-            .get(&DataKey::InvoicesByStatus(InvoiceStatus::Pending))
-            .unwrap_or(Vec::new(&env));
-        pending_invoices.push_back(counter);
-        env.storage().persistent().set(&DataKey::InvoicesByStatus(InvoiceStatus::Pending), &pending_invoices);
+        index::push(&env, &DataKey::InvoicesByStatus(InvoiceStatus::Pending), counter);
 
         // Emit event
         env.events().publish((symbol_short!("created"),), (counter, creator));
@@ -220,13 +541,146 @@ impl InvoiceNFTContract {
         counter
     }
 
+    /// Set up an invoice that is minted again every period (creator only).
+    /// The creator also approves, through `schedule`, which occurrences the marketplace may submit for them.
+    pub fn create_recurring(env: Env, creator: Address, params: InvoiceCreationParams, schedule: RecurringSchedule) -> u64 {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        if !recurring::is_valid_schedule(&schedule, params.due_date) {
+            panic!("Invalid recurring schedule");
+        }
+
+        // Every occurrence is a separate document, so none can claim the template's
+        if params.proof_hash.is_some() || params.invoice_number.is_some() {
+            panic!("Recurring invoices cannot share a document");
+        }
+
+        // Catch terms that could never be minted now rather than at the first roll
+        if params.amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        Self::check_asset_amount(&env, &params.currency, params.amount);
+        Self::check_tax_rate(params.tax_rate);
+        Self::check_early_discount(params.early_discount);
+        if params.customer.as_ref() == Some(&creator) {
+            panic!("Customer cannot be the creator");
+        }
+
+        let mut counter: u64 = env.storage().instance()
+            .get(&DataKey::RecurringCounter)
+            .unwrap_or(0);
+        counter += 1;
+        env.storage().instance().set(&DataKey::RecurringCounter, &counter);
+
+        let mut params = params;
+        params.is_recurring = true;
+
+        let template = RecurringInvoice {
+            id: counter,
+            creator: creator.clone(),
+            params,
+            schedule,
+            occurrences: 0,
+            last_invoice_id: None,
+            active: true,
+        };
+        Self::save_recurring(&env, &template);
+
+        env.events().publish((symbol_short!("recur"), counter), (creator, template.schedule));
+
+        counter
+    }
+
+    /// Mint the next occurrence of a recurring invoice once it is due to be issued (permissionless,
+    /// except that templates with auto-submit enabled are rolled by the marketplace, which submits
+    /// the occurrence). Periods nobody rolled in time are skipped.
+    pub fn roll_recurring(env: Env, template_id: u64) -> RecurringRoll {
+        Self::extend_instance_ttl(&env);
+
+        let mut template = Self::get_recurring(env.clone(), template_id).expect("Recurring invoice not found");
+
+        if !template.active {
+            panic!("Recurring invoice cancelled");
+        }
+
+        if template.schedule.auto_submit_limit.is_some() {
+            let marketplace: Address = env.storage().instance()
+                .get(&DataKey::Marketplace)
+                .expect("Marketplace not registered");
+            marketplace.require_auth();
+        }
+
+        let current_time = env.ledger().timestamp();
+        let period_days = template.schedule.period_days;
+        let due_date = recurring::next_due_date(template.params.due_date, period_days, current_time);
+
+        if template.occurrences >= template.schedule.max_occurrences || due_date > template.schedule.end_date {
+            panic!("Recurring schedule finished");
+        }
+        if current_time < recurring::issue_date(due_date, period_days) {
+            panic!("Recurring invoice not due yet");
+        }
+
+        let mut params = template.params.clone();
+        params.due_date = due_date;
+        let invoice_id = Self::mint_invoice(env.clone(), template.creator.clone(), params);
+
+        template.occurrences += 1;
+        template.last_invoice_id = Some(invoice_id);
+        template.params.due_date = due_date + time::days_to_seconds(period_days);
+        Self::save_recurring(&env, &template);
+
+        let roll = RecurringRoll {
+            template_id,
+            invoice_id,
+            creator: template.creator,
+            amount: template.params.amount,
+            due_date,
+            auto_submit: recurring::is_auto_submitted(&template.schedule, template.params.amount),
+        };
+
+        env.events().publish((symbol_short!("rolled"), template_id), (invoice_id, due_date, template.occurrences));
+
+        roll
+    }
+
+    /// Stop minting further occurrences of a recurring invoice (creator only)
+    pub fn cancel_recurring(env: Env, template_id: u64, creator: Address) {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let mut template = Self::get_recurring(env.clone(), template_id).expect("Recurring invoice not found");
+
+        if template.creator != creator {
+            panic!("Unauthorized");
+        }
+        if !template.active {
+            panic!("Recurring invoice cancelled");
+        }
+
+        template.active = false;
+        Self::save_recurring(&env, &template);
+
+        env.events().publish((symbol_short!("recur_end"), template_id), (creator, template.occurrences));
+    }
+
+    /// Get a recurring invoice and its progress
+    pub fn get_recurring(env: Env, template_id: u64) -> Option<RecurringInvoice> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::RecurringInvoice(template_id))
+    }
+
     /// Update invoice status (only contract admin or marketplace contract)
     pub fn update_status(env: Env, invoice_id: u64, new_status: InvoiceStatus, caller: Address) {
-        caller.require_auth();
+        Self::extend_instance_ttl(&env);
+
+        Self::require_operator(&env, &caller);
 
-        let mut invoice: Invoice = env.storage().persistent()
-            .get(&DataKey::Invoice(invoice_id))
-            .expect("Invoice not found");
+        let mut invoice = Self::load_invoice(&env, invoice_id);
 
         let old_status = invoice.status.clone();
         invoice.status = new_status.clone();
@@ -244,10 +698,14 @@ impl InvoiceNFTContract {
         }
 
         // Update storage
-        env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
+        Self::save_invoice(&env, &invoice);
 
         // Update status-based indexes
-        Self::update_status_index(&env, invoice_id, old_status, new_status.clone());
+        Self::update_status_index(&env, invoice_id, old_status.clone(), new_status.clone());
+
+        if old_status != new_status {
+            Self::record_outcome(&env, &invoice);
+        }
 
         // Emit event
         env.events().publish((symbol_short!("status"),), (invoice_id, new_status));
@@ -255,45 +713,98 @@ impl InvoiceNFTContract {
 
     /// Get invoice by ID
     pub fn get_invoice(env: Env, invoice_id: u64) -> Option<Invoice> {
-        env.storage().persistent().get(&DataKey::Invoice(invoice_id))
+        Self::extend_instance_ttl(&env);
+        let mut invoice = Self::read_invoice(&env, invoice_id);
+        if let Some(invoice) = &mut invoice {
+            if invoice.status.is_live() {
+                Self::extend_persistent_ttl(&env, &DataKey::Invoice(invoice_id));
+            }
+            invoice.risk_assessment.esg_compliant = Some(Self::esg_certified(&env, invoice));
+            if invoice.risk_assessment.verification_method != VerificationMethod::Manual && Self::is_integrated(&env, invoice) {
+                invoice.risk_assessment.verification_method = VerificationMethod::Integrated;
+            }
+        }
+        invoice
     }
 
-    /// Get invoices by creator
-    pub fn get_invoices_by_creator(env: Env, creator: Address) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&DataKey::InvoicesByCreator(creator))
-            .unwrap_or(Vec::new(&env))
+    /// Extend the TTL of live invoices and their index entries.
+    /// Permissionless so keepers can keep long-running invoices from being archived.
+    pub fn bump(env: Env, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        let mut bumped = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            let invoice = match Self::read_invoice(&env, invoice_id) {
+                Some(invoice) => invoice,
+                None => continue,
+            };
+            if !invoice.status.is_live() {
+                continue;
+            }
+
+            Self::extend_persistent_ttl(&env, &DataKey::Invoice(invoice_id));
+            Self::extend_invoice_entries_ttl(&env, &invoice);
+            index::extend(&env, &DataKey::InvoicesByCreator(invoice.creator.clone()), invoice_id);
+            index::extend(&env, &DataKey::InvoicesByStatus(invoice.status.clone()), invoice_id);
+
+            for key in [
+                DataKey::CreatorProfile(invoice.creator.clone()),
+                DataKey::TrustScore(invoice.creator.clone()),
+                DataKey::CreatorHistory(invoice.creator.clone()),
+                DataKey::CreatorEsg(invoice.creator.clone()),
+                DataKey::CreatorAttestations(invoice.creator),
+            ] {
+                if env.storage().persistent().has(&key) {
+                    Self::extend_persistent_ttl(&env, &key);
+                }
+            }
+            bumped += 1;
+        }
+
+        bumped
     }
 
-    /// Get invoices by status
-    pub fn get_invoices_by_status(env: Env, status: InvoiceStatus) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&DataKey::InvoicesByStatus(status))
-            .unwrap_or(Vec::new(&env))
+    /// Get a page of invoices by creator, starting after `cursor`
+    pub fn get_invoices_by_creator(env: Env, creator: Address, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::InvoicesByCreator(creator), cursor, limit)
+    }
+
+    /// Get a page of invoices by status, starting after `cursor`
+    pub fn get_invoices_by_status(env: Env, status: InvoiceStatus, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::InvoicesByStatus(status), cursor, limit)
+    }
+
+    /// Get the number of invoices with a given status
+    pub fn count_invoices_by_status(env: Env, status: InvoiceStatus) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        index::len(&env, &DataKey::InvoicesByStatus(status))
     }
 
-    /// Mark invoice as paid and burn NFT
-    pub fn mark_as_paid(env: Env, invoice_id: u64, payer: Address) {
-        payer.require_auth();
+    /// Mark invoice as paid and burn NFT (only contract admin or marketplace contract)
+    pub fn mark_as_paid(env: Env, invoice_id: u64, caller: Address) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_operator(&env, &caller);
 
-        let mut invoice: Invoice = env.storage().persistent()
-            .get(&DataKey::Invoice(invoice_id))
-            .expect("Invoice not found");
+        let mut invoice = Self::load_invoice(&env, invoice_id);
 
         // Verify invoice can be paid
-        if invoice.status != InvoiceStatus::Funded && invoice.status != InvoiceStatus::Open {
+        if !matches!(invoice.status, InvoiceStatus::Funded | InvoiceStatus::Approved | InvoiceStatus::PartiallyPaid) {
             panic!("Invoice cannot be paid in current status");
         }
 
-        // Update invoice
-        invoice.status = InvoiceStatus::Completed;
-        invoice.timestamps.completed_at = Some(env.ledger().timestamp());
-
-        // Store updated invoice
-        env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
+        // Pays off whatever is left of an invoice paid in installments
+        let paid_key = DataKey::AmountPaid(invoice_id);
+        if env.storage().persistent().has(&paid_key) {
+            env.storage().persistent().set(&paid_key, &invoice.invoice_details.amount);
+        }
 
-        // Update status indexes
-        Self::update_status_index(&env, invoice_id, InvoiceStatus::Funded, InvoiceStatus::Completed);
+        Self::complete_invoice(&env, &mut invoice);
 
         // Calculate cashback if paid early
         let current_time = env.ledger().timestamp();
@@ -302,68 +813,1582 @@ impl InvoiceNFTContract {
         // Emit payment event
         env.events().publish(
             (symbol_short!("paid"),), 
-            (invoice_id, payer, is_early)
+            (invoice_id, caller, is_early)
         );
     }
 
-    /// Update trust score for a creator
-    pub fn update_trust_score(env: Env, creator: Address, new_score: u32, admin: Address) {
-        admin.require_auth();
+    /// Record a payment towards an invoice's outstanding balance, returning the new status (marketplace only).
+    /// Payers pay through the marketplace, which moves the tokens and settles the pool's share.
+    /// Part payments leave a funded invoice `PartiallyPaid`; an overdue invoice stays
+    /// overdue until the marketplace finds its installments back on schedule.
+    pub fn record_payment(env: Env, invoice_id: u64, payer: Address, amount: i128) -> InvoiceStatus {
+        Self::extend_instance_ttl(&env);
 
-        if new_score > 100 {
-            panic!("Trust score cannot exceed 100");
+        let marketplace: Address = env.storage().instance()
+            .get(&DataKey::Marketplace)
+            .expect("Marketplace not registered");
+        marketplace.require_auth();
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+
+        if !invoice.status.is_outstanding() {
+            panic!("Invoice cannot be paid in current status");
         }
 
-        // This would update the creator's trust score across all their invoices
-        // Implementation would depend on the specific trust score mechanism
-        env.events().publish((symbol_short!("trust"),), (creator, new_score));
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let paid_key = DataKey::AmountPaid(invoice_id);
+        let paid: i128 = env.storage().persistent().get(&paid_key).unwrap_or(0);
+        if amount > invoice.invoice_details.amount - paid {
+            panic!("Payment exceeds outstanding balance");
+        }
+
+        let paid = paid + amount;
+        env.storage().persistent().set(&paid_key, &paid);
+        Self::extend_persistent_ttl(&env, &paid_key);
+
+        let outstanding = invoice.invoice_details.amount - paid;
+        if outstanding == 0 {
+            Self::complete_invoice(&env, &mut invoice);
+        } else if invoice.status == InvoiceStatus::Funded {
+            invoice.status = InvoiceStatus::PartiallyPaid;
+            Self::save_invoice(&env, &invoice);
+            Self::update_status_index(&env, invoice_id, InvoiceStatus::Funded, InvoiceStatus::PartiallyPaid);
+        }
+
+        env.events().publish(
+            (symbol_short!("part_paid"), invoice_id),
+            (payer, amount, outstanding)
+        );
+
+        invoice.status
     }
 
-    /// Get total number of invoices
-    pub fn get_total_invoices(env: Env) -> u64 {
-        env.storage().instance()
-            .get(&DataKey::InvoiceCounter)
+    /// Split an invoice into installments while it is pending review (creator only).
+    /// The installments must add up to the invoice amount, the last falling due on its due date;
+    /// an empty schedule has the invoice paid in one go.
+    pub fn set_installments(env: Env, invoice_id: u64, creator: Address, installments: Vec<Installment>) {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_amendable_invoice(&env, invoice_id, &creator);
+
+        let key = DataKey::Installments(invoice_id);
+        if installments.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            let details = &invoice.invoice_details;
+            if !installment::is_valid_schedule(&installments, details.amount, details.due_date) {
+                panic!("Invalid installment schedule");
+            }
+            env.storage().persistent().set(&key, &installments);
+            Self::extend_persistent_ttl(&env, &key);
+        }
+
+        env.events().publish((symbol_short!("install"), invoice_id), installments.len());
+    }
+
+    /// Get an invoice's installment schedule, empty if it is paid in one go
+    pub fn get_installments(env: Env, invoice_id: u64) -> Vec<Installment> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent()
+            .get(&DataKey::Installments(invoice_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get the total recorded as paid towards an invoice
+    pub fn get_amount_paid(env: Env, invoice_id: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent()
+            .get(&DataKey::AmountPaid(invoice_id))
             .unwrap_or(0)
     }
 
-    /// Calculate discount rate based on days until due
-    fn calculate_discount_rate(days_until_due: u64) -> u32 {
-        // Formula: min(15%, 2% + (days_until_deadline * 0.1%))
-        let base_rate = 200u32; // 2% in basis points
-        let time_component = (days_until_due as u32) * 10; // 0.1% per day in basis points
-        let total_rate = base_rate + time_component;
-        
-        // Cap at 15% (1500 basis points)
-        if total_rate > 1500 {
-            1500
-        } else {
-            total_rate
+    /// Get what is left to pay on an invoice
+    pub fn get_outstanding_balance(env: Env, invoice_id: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        if invoice.status == InvoiceStatus::Completed {
+            return 0;
         }
+        invoice.invoice_details.amount - Self::get_amount_paid(env.clone(), invoice_id)
     }
 
-    /// Helper function to update status-based indexes
-    fn update_status_index(
-        env: &Env, 
-        invoice_id: u64, 
-        old_status: InvoiceStatus, 
-        new_status: InvoiceStatus
-    ) {
-        // Remove from old status index
-        let mut old_list: Vec<u64> = env.storage().persistent()
-            .get(&DataKey::InvoicesByStatus(old_status.clone()))
-            .unwrap_or(Vec::new(env));
-        
-        if let Some(index) = old_list.iter().position(|x| x == invoice_id) {
-            old_list.remove(index as u32);
-            env.storage().persistent().set(&DataKey::InvoicesByStatus(old_status), &old_list);
+    /// Get the next installment to pay, with what remains of it
+    pub fn get_next_installment(env: Env, invoice_id: u64) -> Option<Installment> {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        if invoice.status == InvoiceStatus::Completed {
+            return None;
         }
 
-        // Add to new status index
-        let mut new_list: Vec<u64> = env.storage().persistent()
-            .get(&DataKey::InvoicesByStatus(new_status.clone()))
-            .unwrap_or(Vec::new(env));
-        new_list.push_back(invoice_id);
-        env.storage().persistent().set(&DataKey::InvoicesByStatus(new_status), &new_list);
+        let schedule = installment::or_single(
+            &env,
+            Self::get_installments(env.clone(), invoice_id),
+            invoice.invoice_details.amount,
+            invoice.invoice_details.due_date,
+        );
+        installment::next_unpaid(&schedule, Self::get_amount_paid(env.clone(), invoice_id))
+    }
+
+    /// Amend an invoice while it is pending review (creator only)
+    pub fn amend_invoice(env: Env, invoice_id: u64, creator: Address, amendment: InvoiceAmendment) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        let mut invoice = Self::load_amendable_invoice(&env, invoice_id, &creator);
+
+        let current_time = env.ledger().timestamp();
+        let mut changed_fields: Vec<Symbol> = Vec::new(&env);
+        let old_fingerprint = Self::fingerprint(&env, &invoice);
+        let terms_changed = amendment.amount.is_some() || amendment.due_date.is_some();
+
+        if let Some(title) = amendment.title {
+            invoice.title = title;
+            changed_fields.push_back(Symbol::new(&env, "title"));
+        }
+        if let Some(amount) = amendment.amount {
+            if amount <= 0 {
+                panic!("Amount must be positive");
+            }
+            Self::check_asset_amount(&env, &invoice.invoice_details.currency, amount);
+            invoice.invoice_details.amount = amount;
+            changed_fields.push_back(Symbol::new(&env, "amount"));
+        }
+        if let Some(due_date) = amendment.due_date {
+            if due_date <= current_time {
+                panic!("Due date must be in the future");
+            }
+            invoice.invoice_details.due_date = due_date;
+            invoice.timestamps.due_date = due_date;
+            changed_fields.push_back(Symbol::new(&env, "due_date"));
+        }
+        if let Some(description) = amendment.description {
+            invoice.invoice_details.description = description;
+            changed_fields.push_back(Symbol::new(&env, "description"));
+        }
+        if let Some(tax_rate) = amendment.tax_rate {
+            Self::check_tax_rate(Some(tax_rate));
+            invoice.invoice_details.tax_rate = Some(tax_rate);
+            changed_fields.push_back(Symbol::new(&env, "tax_rate"));
+        }
+        if let Some(early_discount) = amendment.early_discount {
+            Self::check_early_discount(early_discount);
+            invoice.invoice_details.early_discount = early_discount;
+            changed_fields.push_back(Symbol::new(&env, "early_discount"));
+        }
+
+        if changed_fields.is_empty() {
+            panic!("Amendment has no changes");
+        }
+
+        // New terms the installments no longer add up to leave the invoice paid in one go
+        let installments_key = DataKey::Installments(invoice_id);
+        if let Some(installments) = env.storage().persistent().get::<_, Vec<Installment>>(&installments_key) {
+            let details = &invoice.invoice_details;
+            if !installment::is_valid_schedule(&installments, details.amount, details.due_date) {
+                env.storage().persistent().remove(&installments_key);
+                env.events().publish((symbol_short!("install"), invoice_id), 0u32);
+            }
+        }
+
+        // Attestations matched the old amount, so they no longer verify the creator
+        if amendment.amount.is_some() {
+            Self::drop_creator_attestations(&env, &creator, invoice_id, None);
+        }
+
+        Self::finish_amendment(&env, &mut invoice, &creator, old_fingerprint, terms_changed, changed_fields)
+    }
+
+    /// Replace an invoice's customer commitment while it is pending review (creator only).
+    /// The invoice is re-graded against the new debtor and loses any acknowledgement.
+    pub fn amend_customer(env: Env, invoice_id: u64, creator: Address, customer_commitment: CustomerCommitment) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        let mut invoice = Self::load_amendable_invoice(&env, invoice_id, &creator);
+
+        if invoice.invoice_details.customer_commitment == customer_commitment {
+            panic!("Amendment has no changes");
+        }
+
+        let old_fingerprint = Self::fingerprint(&env, &invoice);
+        invoice.invoice_details.customer_commitment = customer_commitment;
+
+        let changed_fields = Vec::from_array(&env, [Symbol::new(&env, "customer")]);
+        Self::finish_amendment(&env, &mut invoice, &creator, old_fingerprint, true, changed_fields)
+    }
+
+    /// Confirm an invoice's amount and due date (customer only).
+    /// The invoice is re-graded with the acknowledgement unless an admin has overridden its grade.
+    pub fn acknowledge_invoice(env: Env, invoice_id: u64, customer: Address, amount: i128, due_date: u64) -> Acknowledgement {
+        Self::extend_instance_ttl(&env);
+
+        customer.require_auth();
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+
+        let expected_customer: Address = env.storage().persistent()
+            .get(&DataKey::Customer(invoice_id))
+            .expect("Invoice has no customer");
+        if customer != expected_customer {
+            panic!("Unauthorized");
+        }
+
+        if invoice.status != InvoiceStatus::Pending {
+            panic!("Invoice can only be acknowledged while pending");
+        }
+
+        // The customer signs the exact terms so a later amendment cannot ride on the acknowledgement
+        if amount != invoice.invoice_details.amount || due_date != invoice.timestamps.due_date {
+            panic!("Acknowledged terms do not match invoice");
+        }
+
+        let key = DataKey::Acknowledgement(invoice_id);
+        if env.storage().persistent().has(&key) {
+            panic!("Invoice already acknowledged");
+        }
+
+        let acknowledgement = Acknowledgement {
+            customer: customer.clone(),
+            amount,
+            due_date,
+            acknowledged_at: env.ledger().timestamp(),
+        };
+        env.storage().persistent().set(&key, &acknowledgement);
+        Self::extend_persistent_ttl(&env, &key);
+
+        Self::regrade_pending(&env, &mut invoice);
+
+        env.events().publish((symbol_short!("ack"), invoice_id), (customer, amount, due_date));
+
+        acknowledgement
+    }
+
+    /// Get the customer who can acknowledge an invoice, if one was named
+    pub fn get_invoice_customer(env: Env, invoice_id: u64) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::Customer(invoice_id))
+    }
+
+    /// Get the customer's acknowledgement of an invoice, if given
+    pub fn get_acknowledgement(env: Env, invoice_id: u64) -> Option<Acknowledgement> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::Acknowledgement(invoice_id))
+    }
+
+    /// Check whether the customer has acknowledged an invoice's current terms
+    pub fn is_acknowledged(env: Env, invoice_id: u64) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().has(&DataKey::Acknowledgement(invoice_id))
+    }
+
+    /// Get whether an invoice's amount is stated net or gross of tax
+    pub fn get_tax_basis(env: Env, invoice_id: u64) -> TaxBasis {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent()
+            .get(&DataKey::TaxBasis(invoice_id))
+            .unwrap_or(TaxBasis::Net)
+    }
+
+    /// Split an invoice's amount into its net, tax and gross portions
+    pub fn get_tax_breakdown(env: Env, invoice_id: u64) -> TaxBreakdown {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        Self::tax_breakdown(&env, &invoice)
+    }
+
+    /// Get the part of an invoice's amount that can be financed under the current rule
+    pub fn get_financeable_amount(env: Env, invoice_id: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        Self::financeable_amount(&env, &invoice)
+    }
+
+    /// Choose whether taxed invoices are financed gross or net of tax (admin only)
+    pub fn set_financing_rule(env: Env, admin: Address, rule: FinancingRule) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        // Existing invoices keep their terms until they are re-priced
+        env.storage().instance().set(&DataKey::FinancingRule, &rule);
+
+        env.events().publish((symbol_short!("fin_rule"),), (rule, admin));
+    }
+
+    /// Get the financing rule, defaulting to financing the gross amount
+    pub fn get_financing_rule(env: Env) -> FinancingRule {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::FinancingRule)
+            .unwrap_or(FinancingRule::Gross)
+    }
+
+    /// Cancel an invoice before it is funded (creator only)
+    pub fn cancel_invoice(env: Env, invoice_id: u64, creator: Address) {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+
+        if invoice.creator != creator {
+            panic!("Unauthorized");
+        }
+
+        if invoice.status != InvoiceStatus::Pending && invoice.status != InvoiceStatus::Approved {
+            panic!("Invoice cannot be cancelled in current status");
+        }
+
+        // Archive the invoice rather than deleting it so its history stays queryable
+        let old_status = invoice.status.clone();
+        invoice.status = InvoiceStatus::Cancelled;
+        invoice.timestamps.cancelled_at = Some(env.ledger().timestamp());
+
+        Self::save_invoice(&env, &invoice);
+
+        Self::update_status_index(&env, invoice_id, old_status, InvoiceStatus::Cancelled);
+
+        env.events().publish((symbol_short!("cancel"),), (invoice_id, creator));
+    }
+
+    /// Register or remove an address allowed to update creator profiles and trust scores (admin only),
+    /// such as the marketplace, which lowers the score of creators who default
+    pub fn set_trust_oracle(env: Env, admin: Address, oracle: Address, enabled: bool) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let key = DataKey::TrustOracle(oracle.clone());
+        if enabled {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+
+        env.events().publish((symbol_short!("oracle"), oracle), enabled);
+    }
+
+    /// Check whether an address may update creator profiles
+    pub fn is_trust_oracle(env: Env, oracle: Address) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().has(&DataKey::TrustOracle(oracle))
+    }
+
+    /// Update trust score for a creator (admin or trust oracle)
+    pub fn update_trust_score(env: Env, creator: Address, new_score: u32, caller: Address) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_profile_editor(&env, &caller);
+
+        if new_score > 100 {
+            panic!("Trust score cannot exceed 100");
+        }
+
+        // Every invoice reads the score from the profile, so existing invoices pick it up too
+        let mut profile = Self::load_profile(&env, &creator);
+        profile.trust_score = new_score;
+        Self::save_profile(&env, &creator, &profile);
+
+        env.events().publish((symbol_short!("trust"),), (creator, new_score));
+    }
+
+    /// Update a creator's business name and location (admin or trust oracle)
+    pub fn update_creator_details(env: Env, caller: Address, creator: Address, business_name: String, location: String) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_profile_editor(&env, &caller);
+
+        if business_name.is_empty() {
+            panic!("Business name required");
+        }
+
+        let mut profile = Self::load_profile(&env, &creator);
+        profile.business_name = business_name.clone();
+        profile.location = location.clone();
+        Self::save_profile(&env, &creator, &profile);
+
+        env.events().publish((symbol_short!("profile"), creator), (business_name, location));
+    }
+
+    /// Set a creator's verification level (admin or trust oracle).
+    /// A creator with a conclusive attestation still counts as verified.
+    pub fn set_verification_level(env: Env, caller: Address, creator: Address, level: VerificationLevel) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_profile_editor(&env, &caller);
+
+        let mut profile = Self::load_profile(&env, &creator);
+        profile.verification_level = level;
+        Self::save_profile(&env, &creator, &profile);
+
+        env.events().publish((symbol_short!("verify"), creator), level);
+    }
+
+    /// Get a creator's profile, with the verification level their attestations currently earn them
+    pub fn get_creator_profile(env: Env, creator: Address) -> Option<CreatorProfile> {
+        Self::extend_instance_ttl(&env);
+
+        let key = DataKey::CreatorProfile(creator.clone());
+        let mut profile: CreatorProfile = env.storage().persistent().get(&key)?;
+        Self::extend_persistent_ttl(&env, &key);
+        profile.verification_level = Self::verification_level(&env, &creator);
+        Some(profile)
+    }
+
+    /// Get the current trust score for a creator
+    pub fn get_trust_score(env: Env, creator: Address) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        if let Some(profile) = env.storage().persistent().get::<_, CreatorProfile>(&DataKey::CreatorProfile(creator.clone())) {
+            return profile.trust_score;
+        }
+
+        // Scores set before version 4 are kept until the creator's profile is written
+        let key = DataKey::TrustScore(creator);
+        match env.storage().persistent().get(&key) {
+            Some(score) => {
+                Self::extend_persistent_ttl(&env, &key);
+                score
+            }
+            None => 50, // Default trust score for new users
+        }
+    }
+
+    /// Replace an invoice's automated risk grade and re-price it (admin only)
+    pub fn override_risk_grade(env: Env, admin: Address, invoice_id: u64, risk_override: RiskOverride) -> Quote {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        if risk_override.reason.is_empty() {
+            panic!("Override reason required");
+        }
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+
+        if invoice.status != InvoiceStatus::Pending && invoice.status != InvoiceStatus::Approved {
+            panic!("Risk grade can only be overridden before funding");
+        }
+
+        let previous_grade = invoice.risk_assessment.risk_score;
+        invoice.risk_assessment.risk_score = risk_override.risk_grade;
+        invoice.risk_assessment.verification_method = VerificationMethod::Manual;
+
+        let trust_score = Self::get_trust_score(env.clone(), invoice.creator.clone());
+        let quote = Self::price(
+            &env,
+            risk_override.risk_grade,
+            trust_score,
+            Self::financeable_amount(&env, &invoice),
+            invoice.invoice_details.due_date,
+        );
+        invoice.financial_terms.discount_rate = quote.discount_rate;
+        invoice.financial_terms.offer_amount = quote.offer_amount;
+        invoice.financial_terms.expected_yield = quote.expected_yield;
+
+        Self::save_invoice(&env, &invoice);
+
+        let key = DataKey::RiskOverride(invoice_id);
+        env.storage().persistent().set(&key, &risk_override);
+        Self::extend_persistent_ttl(&env, &key);
+
+        env.events().publish(
+            (symbol_short!("override"), invoice_id),
+            (previous_grade, risk_override.risk_grade, risk_override.reason, admin)
+        );
+
+        quote
+    }
+
+    /// Get the admin override applied to an invoice's risk grade, if any
+    pub fn get_risk_override(env: Env, invoice_id: u64) -> Option<RiskOverride> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::RiskOverride(invoice_id))
+    }
+
+    /// Get an invoice's current risk grade
+    pub fn get_risk_grade(env: Env, invoice_id: u64) -> RiskGrade {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_invoice(&env, invoice_id).risk_assessment.risk_score
+    }
+
+    /// Get the creator, amount and due date an invoice is submitted on
+    pub fn get_invoice_terms(env: Env, invoice_id: u64) -> InvoiceTerms {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        InvoiceTerms {
+            creator: invoice.creator,
+            amount: invoice.invoice_details.amount,
+            due_date: invoice.invoice_details.due_date,
+        }
+    }
+
+    /// Get the currency an invoice is denominated in
+    pub fn get_invoice_currency(env: Env, invoice_id: u64) -> Currency {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_invoice(&env, invoice_id).invoice_details.currency
+    }
+
+    /// Get the discount, in basis points, the creator offers the customer for paying early
+    pub fn get_early_discount(env: Env, invoice_id: u64) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_invoice(&env, invoice_id).invoice_details.early_discount
+    }
+
+    /// Get the kind of collateral the creator declared for an invoice, if any
+    pub fn get_collateral_type(env: Env, invoice_id: u64) -> Option<String> {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_invoice(&env, invoice_id).financial_terms.collateral_type
+    }
+
+    /// Get a creator's invoicing and repayment history
+    pub fn get_creator_history(env: Env, creator: Address) -> CreatorHistory {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_creator_history(&env, &creator)
+    }
+
+    /// Get a debtor's repayment history
+    pub fn get_debtor_history(env: Env, debtor_id: BytesN<32>) -> DebtorHistory {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_debtor_history(&env, &debtor_id)
+    }
+
+    /// Check a disclosed customer detail and salt against an invoice's commitment.
+    /// Creators share the salt only with parties entitled to see the detail.
+    pub fn verify_customer_commitment(
+        env: Env,
+        invoice_id: u64,
+        field: CustomerField,
+        value: String,
+        salt: BytesN<32>,
+    ) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        let commitment = Self::load_invoice(&env, invoice_id).invoice_details.customer_commitment;
+        let expected = match field {
+            CustomerField::Name => commitment.name_hash,
+            CustomerField::Email => commitment.email_hash,
+        };
+        customer::commit(&env, &salt, &value) == expected
+    }
+
+    /// Replace the pricing grid used for new quotes (admin only)
+    pub fn set_pricing_config(env: Env, admin: Address, config: PricingConfig) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        if !pricing::is_valid_config(&config) {
+            panic!("Invalid pricing config");
+        }
+
+        // Existing invoices keep their terms until they are re-priced
+        env.storage().instance().set(&DataKey::PricingConfig, &config);
+
+        env.events().publish((symbol_short!("pricing"),), admin);
+    }
+
+    /// Get the pricing grid, falling back to the built-in default
+    pub fn get_pricing_config(env: Env) -> PricingConfig {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::PricingConfig)
+            .unwrap_or_else(|| pricing::default_config(&env))
+    }
+
+    /// Quote financial terms for an invoice without storing anything
+    pub fn quote(env: Env, params: QuoteParams) -> Quote {
+        Self::extend_instance_ttl(&env);
+
+        if params.amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let trust_score = Self::get_trust_score(env.clone(), params.creator);
+        Self::price(&env, params.risk_grade, trust_score, params.amount, params.due_date)
+    }
+
+    /// Register or update a supported currency and its token contract (admin only)
+    pub fn set_asset(env: Env, admin: Address, currency: Currency, config: AssetConfig) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        if !asset::is_valid_config(&config) {
+            panic!("Invalid asset config");
+        }
+
+        // Existing invoices are unaffected; new limits apply to new invoices and amendments
+        env.storage().instance().set(&DataKey::Asset(currency.clone()), &config);
+
+        env.events().publish((symbol_short!("asset"), currency), config);
+    }
+
+    /// Stop accepting new invoices in a currency (admin only)
+    pub fn remove_asset(env: Env, admin: Address, currency: Currency) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let key = DataKey::Asset(currency.clone());
+        if !env.storage().instance().has(&key) {
+            panic!("Unsupported currency");
+        }
+        env.storage().instance().remove(&key);
+
+        env.events().publish((symbol_short!("asset_rm"), currency), admin);
+    }
+
+    /// Get the token contract and limits registered for a currency
+    pub fn get_asset(env: Env, currency: Currency) -> Option<AssetConfig> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().get(&DataKey::Asset(currency))
+    }
+
+    /// Get the live invoice registered for a document hash, if any
+    pub fn get_invoice_by_proof_hash(env: Env, proof_hash: BytesN<32>) -> Option<u64> {
+        Self::extend_instance_ttl(&env);
+
+        Self::registered_invoice(&env, &DataKey::ProofHash(proof_hash))
+    }
+
+    /// Get the creator's own reference for an invoice, if one was given
+    pub fn get_invoice_number(env: Env, invoice_id: u64) -> Option<String> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::InvoiceNumber(invoice_id))
+    }
+
+    /// Allow or revoke an address to flag suspected fraud (admin only)
+    pub fn set_verifier(env: Env, admin: Address, verifier: Address, enabled: bool) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let key = DataKey::Verifier(verifier.clone());
+        if enabled {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+
+        env.events().publish((symbol_short!("verifier"), verifier), enabled);
+    }
+
+    /// Check whether an address may flag suspected fraud
+    pub fn is_verifier(env: Env, verifier: Address) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().has(&DataKey::Verifier(verifier))
+    }
+
+    /// Flag an invoice as suspected fraud (verifier or admin).
+    /// Flagged invoices cannot be submitted or funded until the admin clears them.
+    pub fn flag_fraud(env: Env, verifier: Address, invoice_id: u64, reason: String) {
+        Self::extend_instance_ttl(&env);
+
+        verifier.require_auth();
+
+        let admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Contract not initialized");
+        if verifier != admin && !env.storage().instance().has(&DataKey::Verifier(verifier.clone())) {
+            panic!("Unauthorized");
+        }
+
+        if reason.is_empty() {
+            panic!("Flag reason required");
+        }
+
+        // Make sure the invoice exists before recording anything against it
+        Self::load_invoice(&env, invoice_id);
+
+        let key = DataKey::FraudFlags(invoice_id);
+        let mut flags: Vec<FraudFlag> = env.storage().persistent()
+            .get(&key)
+            .unwrap_or(Vec::new(&env));
+        if flags.iter().any(|flag| flag.verifier == verifier) {
+            panic!("Invoice already flagged by verifier");
+        }
+        flags.push_back(FraudFlag {
+            verifier: verifier.clone(),
+            reason: reason.clone(),
+            flagged_at: env.ledger().timestamp(),
+        });
+        env.storage().persistent().set(&key, &flags);
+        Self::extend_persistent_ttl(&env, &key);
+
+        env.events().publish((symbol_short!("fraud"), invoice_id), (verifier, reason));
+    }
+
+    /// Clear every fraud flag on an invoice after review (admin only)
+    pub fn clear_fraud_flags(env: Env, admin: Address, invoice_id: u64, reason: String) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        if reason.is_empty() {
+            panic!("Clear reason required");
+        }
+
+        let key = DataKey::FraudFlags(invoice_id);
+        if !env.storage().persistent().has(&key) {
+            panic!("Invoice not flagged");
+        }
+        env.storage().persistent().remove(&key);
+
+        env.events().publish((symbol_short!("fraud_clr"), invoice_id), (admin, reason));
+    }
+
+    /// Get the fraud flags raised against an invoice
+    pub fn get_fraud_flags(env: Env, invoice_id: u64) -> Vec<FraudFlag> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent()
+            .get(&DataKey::FraudFlags(invoice_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Check whether an invoice has uncleared fraud flags
+    pub fn is_flagged(env: Env, invoice_id: u64) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().has(&DataKey::FraudFlags(invoice_id))
+    }
+
+    /// Record a verifier's claim that an invoice matches a record in an external system (verifier only).
+    /// A later claim by the same verifier from the same source replaces the earlier one.
+    /// A pending invoice is re-graded, since a conclusive claim verifies its creator.
+    pub fn attest_invoice(env: Env, verifier: Address, invoice_id: u64, claim: AttestationClaim) -> Attestation {
+        Self::extend_instance_ttl(&env);
+
+        verifier.require_auth();
+
+        if !env.storage().instance().has(&DataKey::Verifier(verifier.clone())) {
+            panic!("Unauthorized");
+        }
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+        if !invoice.status.is_live() {
+            panic!("Invoice cannot be attested in current status");
+        }
+
+        if claim.external_id.is_empty() {
+            panic!("External id required");
+        }
+
+        if claim.matched_amount <= 0 {
+            panic!("Invalid matched amount");
+        }
+
+        if !is_valid_bps(claim.confidence) {
+            panic!("Invalid confidence");
+        }
+
+        let current_time = env.ledger().timestamp();
+        if claim.expires_at <= current_time {
+            panic!("Attestation already expired");
+        }
+
+        let AttestationClaim { source, external_id, matched_amount, confidence, expires_at } = claim;
+        let attestation = Attestation {
+            verifier: verifier.clone(),
+            source: source.clone(),
+            external_id,
+            matched_amount,
+            confidence,
+            attested_at: current_time,
+            expires_at,
+            revoked: false,
+        };
+
+        let key = DataKey::Attestations(invoice_id);
+        let mut attestations: Vec<Attestation> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+        match attestations.iter().position(|a| a.verifier == verifier && a.source == source) {
+            Some(position) => attestations.set(position as u32, attestation.clone()),
+            None => {
+                if attestations.len() >= attestation::MAX_ATTESTATIONS {
+                    panic!("Too many attestations");
+                }
+                attestations.push_back(attestation.clone());
+            }
+        }
+        env.storage().persistent().set(&key, &attestations);
+        Self::extend_persistent_ttl(&env, &key);
+
+        let creator = invoice.creator.clone();
+        Self::drop_creator_attestations(&env, &creator, invoice_id, Some((&verifier, &source)));
+        if attestation::is_conclusive(&attestation, invoice.invoice_details.amount) {
+            let key = DataKey::CreatorAttestations(creator);
+            let mut counted: Vec<CreatorAttestation> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+            counted.push_back(CreatorAttestation { invoice_id, verifier: verifier.clone(), source: source.clone(), expires_at });
+            env.storage().persistent().set(&key, &counted);
+            Self::extend_persistent_ttl(&env, &key);
+        }
+
+        Self::regrade_pending(&env, &mut invoice);
+
+        env.events().publish(
+            (symbol_short!("attest"), invoice_id),
+            (verifier, source, matched_amount, confidence, expires_at)
+        );
+
+        attestation
+    }
+
+    /// Revoke an attestation (its verifier or admin). It stays on record but no longer counts.
+    pub fn revoke_attestation(env: Env, caller: Address, invoice_id: u64, verifier: Address, source: Symbol) {
+        Self::extend_instance_ttl(&env);
+
+        caller.require_auth();
+
+        let admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Contract not initialized");
+        if caller != verifier && caller != admin {
+            panic!("Unauthorized");
+        }
+
+        let key = DataKey::Attestations(invoice_id);
+        let mut attestations: Vec<Attestation> = env.storage().persistent().get(&key).unwrap_or(Vec::new(&env));
+        let position = attestations.iter()
+            .position(|a| a.verifier == verifier && a.source == source && !a.revoked)
+            .expect("Attestation not found") as u32;
+        let mut attestation = attestations.get_unchecked(position);
+        attestation.revoked = true;
+        attestations.set(position, attestation);
+        env.storage().persistent().set(&key, &attestations);
+        Self::extend_persistent_ttl(&env, &key);
+
+        let mut invoice = Self::load_invoice(&env, invoice_id);
+        let creator = invoice.creator.clone();
+        Self::drop_creator_attestations(&env, &creator, invoice_id, Some((&verifier, &source)));
+        Self::regrade_pending(&env, &mut invoice);
+
+        env.events().publish((symbol_short!("attest_rm"), invoice_id), (verifier, source, caller));
+    }
+
+    /// Get every attestation made about an invoice, including expired and revoked ones
+    pub fn get_attestations(env: Env, invoice_id: u64) -> Vec<Attestation> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent()
+            .get(&DataKey::Attestations(invoice_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get a creator's verification level: verified while a registered verifier's
+    /// current attestation confirms one of their invoices in full, their profile's level otherwise
+    pub fn get_verification_level(env: Env, creator: Address) -> VerificationLevel {
+        Self::extend_instance_ttl(&env);
+
+        Self::verification_level(&env, &creator)
+    }
+
+    /// Register or remove an address allowed to certify invoices and creators as ESG-compliant (admin only).
+    /// Certifications from a removed verifier no longer count.
+    pub fn set_esg_verifier(env: Env, admin: Address, verifier: Address, enabled: bool) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let key = DataKey::EsgVerifier(verifier.clone());
+        if enabled {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+
+        env.events().publish((symbol_short!("esg_ver"), verifier), enabled);
+    }
+
+    /// Check whether an address may issue ESG certifications
+    pub fn is_esg_verifier(env: Env, verifier: Address) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().has(&DataKey::EsgVerifier(verifier))
+    }
+
+    /// Certify an open invoice as ESG-compliant until `expires_at` (ESG verifier only),
+    /// replacing any earlier certification
+    pub fn certify_invoice_esg(
+        env: Env,
+        verifier: Address,
+        invoice_id: u64,
+        framework: String,
+        score: u32,
+        evidence_hash: BytesN<32>,
+        expires_at: u64,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        let certification = Self::esg_certification(&env, verifier, framework, score, evidence_hash, expires_at);
+
+        if !Self::load_invoice(&env, invoice_id).status.is_live() {
+            panic!("Invoice cannot be certified in current status");
+        }
+
+        let key = DataKey::InvoiceEsg(invoice_id);
+        env.storage().persistent().set(&key, &certification);
+        Self::extend_persistent_ttl(&env, &key);
+
+        env.events().publish(
+            (symbol_short!("esg_cert"), invoice_id),
+            (certification.verifier, certification.framework, score, expires_at)
+        );
+    }
+
+    /// Certify a creator as ESG-compliant until `expires_at` (ESG verifier only), covering
+    /// all of their invoices and replacing any earlier certification
+    pub fn certify_creator_esg(
+        env: Env,
+        verifier: Address,
+        creator: Address,
+        framework: String,
+        score: u32,
+        evidence_hash: BytesN<32>,
+        expires_at: u64,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        let certification = Self::esg_certification(&env, verifier, framework, score, evidence_hash, expires_at);
+
+        let key = DataKey::CreatorEsg(creator.clone());
+        env.storage().persistent().set(&key, &certification);
+        Self::extend_persistent_ttl(&env, &key);
+
+        env.events().publish(
+            (symbol_short!("esg_cred"), creator),
+            (certification.verifier, certification.framework, score, expires_at)
+        );
+    }
+
+    /// Get the ESG certification attached to an invoice, whether or not it is still valid
+    pub fn get_invoice_esg(env: Env, invoice_id: u64) -> Option<EsgCertification> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::InvoiceEsg(invoice_id))
+    }
+
+    /// Get the ESG certification attached to a creator, whether or not it is still valid
+    pub fn get_creator_esg(env: Env, creator: Address) -> Option<EsgCertification> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::CreatorEsg(creator))
+    }
+
+    /// Check whether an invoice, or its creator, holds an unexpired certification from a registered ESG verifier
+    pub fn is_esg_certified(env: Env, invoice_id: u64) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        let invoice = Self::load_invoice(&env, invoice_id);
+        Self::esg_certified(&env, &invoice)
+    }
+
+    /// Get the ESG-certified invoices among a page of `limit` invoices in an open status.
+    /// Pages can hold fewer than `limit` invoices, or none; keep reading until `next_cursor` is `None`.
+    pub fn get_esg_invoices(env: Env, status: InvoiceStatus, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        if !status.is_live() {
+            panic!("Invoice status is not open");
+        }
+
+        let page = index::page(&env, &DataKey::InvoicesByStatus(status), cursor, limit);
+        let mut invoice_ids = Vec::new(&env);
+        for invoice_id in page.invoice_ids.iter() {
+            if Self::esg_certified(&env, &Self::load_invoice(&env, invoice_id)) {
+                invoice_ids.push_back(invoice_id);
+            }
+        }
+
+        InvoicePage { invoice_ids, next_cursor: page.next_cursor }
+    }
+
+    /// Get total number of invoices
+    pub fn get_total_invoices(env: Env) -> u64 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::InvoiceCounter)
+            .unwrap_or(0)
+    }
+
+    fn require_admin(env: &Env, admin: &Address) {
+        admin.require_auth();
+
+        let stored_admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Contract not initialized");
+
+        if *admin != stored_admin {
+            panic!("Unauthorized");
+        }
+    }
+
+    /// Require a registered currency and an amount inside its limits
+    fn check_asset_amount(env: &Env, currency: &Currency, amount: i128) {
+        let config: AssetConfig = env.storage().instance()
+            .get(&DataKey::Asset(currency.clone()))
+            .expect("Unsupported currency");
+
+        if !asset::is_within_limits(&config, amount) {
+            panic!("Amount outside asset limits");
+        }
+    }
+
+    /// Get the invoice registered under a proof hash or fingerprint while it is still live.
+    /// Settled invoices release their entries so a later invoice may reuse them.
+    fn registered_invoice(env: &Env, key: &DataKey) -> Option<u64> {
+        let invoice_id: u64 = env.storage().persistent().get(key)?;
+        match Self::read_invoice(env, invoice_id) {
+            Some(invoice) if invoice.status.is_live() => Some(invoice_id),
+            _ => None,
+        }
+    }
+
+    fn check_not_registered(env: &Env, key: &DataKey) {
+        if Self::registered_invoice(env, key).is_some() {
+            panic!("Duplicate invoice");
+        }
+    }
+
+    /// Fingerprint of an invoice's current terms, if the creator gave an invoice number
+    fn fingerprint(env: &Env, invoice: &Invoice) -> Option<BytesN<32>> {
+        let invoice_number: String = env.storage().persistent().get(&DataKey::InvoiceNumber(invoice.id))?;
+        Some(fingerprint::invoice_fingerprint(
+            env,
+            &invoice.creator,
+            &invoice.invoice_details.customer_commitment.debtor_id,
+            invoice.invoice_details.amount,
+            &invoice_number,
+        ))
+    }
+
+    /// Keep the entries stored alongside an invoice alive for as long as the invoice
+    fn extend_invoice_entries_ttl(env: &Env, invoice: &Invoice) {
+        if let Some(proof_hash) = &invoice.proof_hash {
+            let key = DataKey::ProofHash(proof_hash.clone());
+            if env.storage().persistent().has(&key) {
+                Self::extend_persistent_ttl(env, &key);
+            }
+        }
+
+        let number_key = DataKey::InvoiceNumber(invoice.id);
+        if env.storage().persistent().has(&number_key) {
+            Self::extend_persistent_ttl(env, &number_key);
+            if let Some(fingerprint) = Self::fingerprint(env, invoice) {
+                let key = DataKey::Fingerprint(fingerprint);
+                if env.storage().persistent().has(&key) {
+                    Self::extend_persistent_ttl(env, &key);
+                }
+            }
+        }
+
+        for key in [
+            DataKey::FraudFlags(invoice.id),
+            DataKey::Customer(invoice.id),
+            DataKey::Acknowledgement(invoice.id),
+            DataKey::TaxBasis(invoice.id),
+            DataKey::Installments(invoice.id),
+            DataKey::AmountPaid(invoice.id),
+            DataKey::InvoiceEsg(invoice.id),
+            DataKey::Attestations(invoice.id),
+        ] {
+            if env.storage().persistent().has(&key) {
+                Self::extend_persistent_ttl(env, &key);
+            }
+        }
+    }
+
+    /// Load an invoice its creator may still amend
+    fn load_amendable_invoice(env: &Env, invoice_id: u64, creator: &Address) -> Invoice {
+        creator.require_auth();
+
+        let invoice = Self::load_invoice(env, invoice_id);
+
+        if invoice.creator != *creator {
+            panic!("Unauthorized");
+        }
+
+        if invoice.status != InvoiceStatus::Pending {
+            panic!("Invoice can only be amended while pending");
+        }
+
+        invoice
+    }
+
+    /// Re-register, re-grade, re-price and store an amended invoice
+    fn finish_amendment(
+        env: &Env,
+        invoice: &mut Invoice,
+        creator: &Address,
+        old_fingerprint: Option<BytesN<32>>,
+        drops_acknowledgement: bool,
+        changed_fields: Vec<Symbol>,
+    ) -> u32 {
+        let invoice_id = invoice.id;
+
+        // A new amount or customer moves the invoice to a new fingerprint
+        if let Some(old_fingerprint) = old_fingerprint {
+            let new_fingerprint = Self::fingerprint(env, invoice).expect("Invoice number not found");
+            if new_fingerprint != old_fingerprint {
+                let new_key = DataKey::Fingerprint(new_fingerprint);
+                Self::check_not_registered(env, &new_key);
+                env.storage().persistent().set(&new_key, &invoice_id);
+                env.storage().persistent().remove(&DataKey::Fingerprint(old_fingerprint));
+            }
+        }
+
+        // The customer acknowledged the old terms or debtor, not the amended ones
+        let acknowledgement_key = DataKey::Acknowledgement(invoice_id);
+        if drops_acknowledgement && env.storage().persistent().has(&acknowledgement_key) {
+            env.storage().persistent().remove(&acknowledgement_key);
+            env.events().publish((symbol_short!("ack_rm"), invoice_id), creator.clone());
+        }
+
+        // Amended invoices are re-graded and re-priced automatically, dropping any earlier override
+        let trust_score = Self::get_trust_score(env.clone(), creator.clone());
+        let factors = Self::invoice_risk_factors(env, invoice, trust_score);
+        let grade = risk::grade(&factors);
+        invoice.risk_assessment.risk_score = grade;
+        invoice.risk_assessment.verification_method = VerificationMethod::Automated;
+        env.storage().persistent().remove(&DataKey::RiskOverride(invoice_id));
+
+        let quote = Self::price(env, grade, trust_score, Self::financeable_amount(env, invoice), invoice.invoice_details.due_date);
+        invoice.financial_terms.discount_rate = quote.discount_rate;
+        invoice.financial_terms.offer_amount = quote.offer_amount;
+        invoice.financial_terms.expected_yield = quote.expected_yield;
+
+        invoice.amendment_count += 1;
+
+        Self::save_invoice(env, invoice);
+
+        env.events().publish((symbol_short!("risk"), invoice_id), (grade, factors));
+        env.events().publish(
+            (symbol_short!("amended"),),
+            (invoice_id, invoice.amendment_count, changed_fields)
+        );
+
+        invoice.amendment_count
+    }
+
+    /// Gather the inputs for grading an invoice
+    fn risk_factors(
+        env: &Env,
+        creator: &Address,
+        trust_score: u32,
+        details: &InvoiceDetails,
+        has_proof: bool,
+        is_verified: bool,
+    ) -> RiskFactors {
+        let history = Self::load_creator_history(env, creator);
+        let debtor = Self::load_debtor_history(env, &details.customer_commitment.debtor_id);
+
+        let average_amount = if history.invoice_count > 0 {
+            history.total_volume / history.invoice_count as i128
+        } else {
+            0
+        };
+
+        RiskFactors {
+            trust_score,
+            completed_invoices: history.completed_count,
+            defaulted_invoices: history.defaulted_count,
+            days_until_due: time::days_between(env.ledger().timestamp(), details.due_date),
+            amount: details.amount,
+            average_amount,
+            debtor_paid: debtor.paid_count,
+            debtor_defaulted: debtor.defaulted_count,
+            has_proof,
+            is_verified,
+            debtor_acknowledged: false, // only a minted invoice can be acknowledged
+        }
+    }
+
+    /// Gather the inputs for re-grading a minted invoice
+    fn invoice_risk_factors(env: &Env, invoice: &Invoice, trust_score: u32) -> RiskFactors {
+        let mut factors = Self::risk_factors(
+            env,
+            &invoice.creator,
+            trust_score,
+            &invoice.invoice_details,
+            invoice.proof_hash.is_some(),
+            Self::verification_level(env, &invoice.creator) == VerificationLevel::Verified,
+        );
+        factors.debtor_acknowledged = env.storage().persistent().has(&DataKey::Acknowledgement(invoice.id));
+        factors
+    }
+
+    /// Re-grade and re-price a pending invoice, unless an admin has overridden its grade
+    fn regrade_pending(env: &Env, invoice: &mut Invoice) {
+        if invoice.status != InvoiceStatus::Pending || env.storage().persistent().has(&DataKey::RiskOverride(invoice.id)) {
+            return;
+        }
+
+        let trust_score = Self::get_trust_score(env.clone(), invoice.creator.clone());
+        let factors = Self::invoice_risk_factors(env, invoice, trust_score);
+        let grade = risk::grade(&factors);
+        invoice.risk_assessment.risk_score = grade;
+
+        let quote = Self::price(env, grade, trust_score, Self::financeable_amount(env, invoice), invoice.invoice_details.due_date);
+        invoice.financial_terms.discount_rate = quote.discount_rate;
+        invoice.financial_terms.offer_amount = quote.offer_amount;
+        invoice.financial_terms.expected_yield = quote.expected_yield;
+
+        Self::save_invoice(env, invoice);
+
+        env.events().publish((symbol_short!("risk"), invoice.id), (grade, factors));
+    }
+
+    /// Whether an attestation is current and its verifier still registered
+    fn is_valid_attestation(env: &Env, verifier: &Address, expires_at: u64, revoked: bool) -> bool {
+        !revoked
+            && expires_at > env.ledger().timestamp()
+            && env.storage().instance().has(&DataKey::Verifier(verifier.clone()))
+    }
+
+    /// Whether a valid attestation confirms the invoice in full
+    fn is_integrated(env: &Env, invoice: &Invoice) -> bool {
+        let attestations: Vec<Attestation> = env.storage().persistent()
+            .get(&DataKey::Attestations(invoice.id))
+            .unwrap_or(Vec::new(env));
+        attestations.iter().any(|a| {
+            attestation::is_conclusive(&a, invoice.invoice_details.amount)
+                && Self::is_valid_attestation(env, &a.verifier, a.expires_at, a.revoked)
+        })
+    }
+
+    fn verification_level(env: &Env, creator: &Address) -> VerificationLevel {
+        let counted: Vec<CreatorAttestation> = env.storage().persistent()
+            .get(&DataKey::CreatorAttestations(creator.clone()))
+            .unwrap_or(Vec::new(env));
+        if counted.iter().any(|a| Self::is_valid_attestation(env, &a.verifier, a.expires_at, false)) {
+            return VerificationLevel::Verified;
+        }
+
+        env.storage().persistent()
+            .get::<_, CreatorProfile>(&DataKey::CreatorProfile(creator.clone()))
+            .map_or(VerificationLevel::Pending, |profile| profile.verification_level)
+    }
+
+    /// Require the admin or the registered marketplace, since status changes feed creator and debtor histories
+    fn require_operator(env: &Env, caller: &Address) {
+        caller.require_auth();
+
+        if !Self::is_operator(env, caller) {
+            panic!("Unauthorized");
+        }
+    }
+
+    /// Check whether caller is the contract admin or the registered marketplace
+    fn is_operator(env: &Env, caller: &Address) -> bool {
+        let admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Contract not initialized");
+        if *caller == admin {
+            return true;
+        }
+
+        let marketplace: Option<Address> = env.storage().instance().get(&DataKey::Marketplace);
+        marketplace == Some(caller.clone())
+    }
+
+    /// Require the admin or a registered trust oracle
+    fn require_profile_editor(env: &Env, caller: &Address) {
+        caller.require_auth();
+
+        let admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Contract not initialized");
+        if *caller != admin && !env.storage().instance().has(&DataKey::TrustOracle(caller.clone())) {
+            panic!("Unauthorized");
+        }
+    }
+
+    /// Load a creator's stored profile, or a blank one carrying any pre-version 4 trust score
+    fn load_profile(env: &Env, creator: &Address) -> CreatorProfile {
+        env.storage().persistent()
+            .get(&DataKey::CreatorProfile(creator.clone()))
+            .unwrap_or_else(|| CreatorProfile {
+                business_name: String::from_str(env, ""),
+                location: String::from_str(env, ""),
+                trust_score: Self::get_trust_score(env.clone(), creator.clone()),
+                verification_level: VerificationLevel::Pending,
+            })
+    }
+
+    /// Store a creator's profile, retiring the trust score kept before version 4
+    fn save_profile(env: &Env, creator: &Address, profile: &CreatorProfile) {
+        let key = DataKey::CreatorProfile(creator.clone());
+        env.storage().persistent().set(&key, profile);
+        Self::extend_persistent_ttl(env, &key);
+
+        let trust_key = DataKey::TrustScore(creator.clone());
+        if env.storage().persistent().has(&trust_key) {
+            env.storage().persistent().remove(&trust_key);
+        }
+    }
+
+    /// Stop counting an invoice's attestations toward its creator, either all of them or
+    /// the one by a verifier from a source, pruning expired entries along the way
+    fn drop_creator_attestations(env: &Env, creator: &Address, invoice_id: u64, only: Option<(&Address, &Symbol)>) {
+        let key = DataKey::CreatorAttestations(creator.clone());
+        let Some(counted) = env.storage().persistent().get::<_, Vec<CreatorAttestation>>(&key) else {
+            return;
+        };
+
+        let current_time = env.ledger().timestamp();
+        let mut kept = Vec::new(env);
+        for a in counted.iter() {
+            let dropped = a.invoice_id == invoice_id
+                && only.is_none_or(|(verifier, source)| a.verifier == *verifier && a.source == *source);
+            if !dropped && a.expires_at > current_time {
+                kept.push_back(a);
+            }
+        }
+
+        if kept.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &kept);
+            Self::extend_persistent_ttl(env, &key);
+        }
+    }
+
+    /// Build a certification signed by a registered ESG verifier
+    fn esg_certification(
+        env: &Env,
+        verifier: Address,
+        framework: String,
+        score: u32,
+        evidence_hash: BytesN<32>,
+        expires_at: u64,
+    ) -> EsgCertification {
+        verifier.require_auth();
+
+        if !env.storage().instance().has(&DataKey::EsgVerifier(verifier.clone())) {
+            panic!("Unauthorized");
+        }
+
+        if framework.is_empty() {
+            panic!("ESG framework required");
+        }
+
+        if score > MAX_ESG_SCORE {
+            panic!("Invalid ESG score");
+        }
+
+        let current_time = env.ledger().timestamp();
+        if expires_at <= current_time {
+            panic!("Certification already expired");
+        }
+
+        EsgCertification { verifier, framework, score, evidence_hash, certified_at: current_time, expires_at }
+    }
+
+    /// Whether a certification is unexpired and its verifier still registered
+    fn is_valid_esg(env: &Env, certification: Option<EsgCertification>) -> bool {
+        certification.is_some_and(|certification| {
+            certification.expires_at > env.ledger().timestamp()
+                && env.storage().instance().has(&DataKey::EsgVerifier(certification.verifier))
+        })
+    }
+
+    fn esg_certified(env: &Env, invoice: &Invoice) -> bool {
+        Self::is_valid_esg(env, env.storage().persistent().get(&DataKey::InvoiceEsg(invoice.id)))
+            || Self::is_valid_esg(env, env.storage().persistent().get(&DataKey::CreatorEsg(invoice.creator.clone())))
+    }
+
+    /// Mark an invoice completed and record the repayment
+    fn complete_invoice(env: &Env, invoice: &mut Invoice) {
+        let old_status = invoice.status.clone();
+        invoice.status = InvoiceStatus::Completed;
+        invoice.timestamps.completed_at = Some(env.ledger().timestamp());
+
+        Self::save_invoice(env, invoice);
+        Self::update_status_index(env, invoice.id, old_status, InvoiceStatus::Completed);
+        Self::record_outcome(env, invoice);
+    }
+
+    /// Record a repayment or default in the creator's and debtor's history
+    fn record_outcome(env: &Env, invoice: &Invoice) {
+        let mut history = Self::load_creator_history(env, &invoice.creator);
+        let debtor_id = &invoice.invoice_details.customer_commitment.debtor_id;
+        let mut debtor = Self::load_debtor_history(env, debtor_id);
+
+        match invoice.status {
+            InvoiceStatus::Completed => {
+                history.completed_count += 1;
+                debtor.paid_count += 1;
+            }
+            InvoiceStatus::Defaulted => {
+                history.defaulted_count += 1;
+                debtor.defaulted_count += 1;
+            }
+            _ => return,
+        }
+
+        Self::save_creator_history(env, &invoice.creator, &history);
+
+        Self::save_debtor_history(env, debtor_id, &debtor);
+    }
+
+    fn load_creator_history(env: &Env, creator: &Address) -> CreatorHistory {
+        let key = DataKey::CreatorHistory(creator.clone());
+        match env.storage().persistent().get(&key) {
+            Some(history) => {
+                Self::extend_persistent_ttl(env, &key);
+                history
+            }
+            None => CreatorHistory { invoice_count: 0, total_volume: 0, completed_count: 0, defaulted_count: 0 },
+        }
+    }
+
+    fn save_creator_history(env: &Env, creator: &Address, history: &CreatorHistory) {
+        let key = DataKey::CreatorHistory(creator.clone());
+        env.storage().persistent().set(&key, history);
+        Self::extend_persistent_ttl(env, &key);
+    }
+
+    fn load_debtor_history(env: &Env, debtor_id: &BytesN<32>) -> DebtorHistory {
+        let key = DataKey::Debtor(debtor_id.clone());
+        match env.storage().persistent().get(&key) {
+            Some(history) => {
+                Self::extend_persistent_ttl(env, &key);
+                history
+            }
+            None => DebtorHistory { paid_count: 0, defaulted_count: 0 },
+        }
+    }
+
+    fn save_debtor_history(env: &Env, debtor_id: &BytesN<32>, history: &DebtorHistory) {
+        let key = DataKey::Debtor(debtor_id.clone());
+        env.storage().persistent().set(&key, history);
+        Self::extend_persistent_ttl(env, &key);
+    }
+
+    fn check_tax_rate(tax_rate: Option<u32>) {
+        if let Some(tax_rate) = tax_rate {
+            if !tax::is_valid_rate(tax_rate) {
+                panic!("Invalid tax rate");
+            }
+        }
+    }
+
+    /// Store a recurring invoice, keeping it alive only while it can still roll
+    fn save_recurring(env: &Env, template: &RecurringInvoice) {
+        let key = DataKey::RecurringInvoice(template.id);
+        env.storage().persistent().set(&key, template);
+        if template.active {
+            Self::extend_persistent_ttl(env, &key);
+        }
+    }
+
+    fn check_early_discount(early_discount: u32) {
+        if !is_valid_bps(early_discount) {
+            panic!("Invalid early discount");
+        }
+    }
+
+    fn tax_breakdown(env: &Env, invoice: &Invoice) -> TaxBreakdown {
+        let basis = env.storage().persistent()
+            .get(&DataKey::TaxBasis(invoice.id))
+            .unwrap_or(TaxBasis::Net);
+        tax::breakdown(invoice.invoice_details.amount, invoice.invoice_details.tax_rate, basis)
+    }
+
+    /// Part of an invoice's amount that pools advance against, which is what it is priced on
+    fn financeable_amount(env: &Env, invoice: &Invoice) -> i128 {
+        let rule = Self::get_financing_rule(env.clone());
+        tax::financeable_amount(&Self::tax_breakdown(env, invoice), rule)
+    }
+
+    /// Price an invoice against the configured grid
+    fn price(env: &Env, grade: RiskGrade, trust_score: u32, amount: i128, due_date: u64) -> Quote {
+        let current_time = env.ledger().timestamp();
+        if due_date <= current_time {
+            panic!("Due date must be in the future");
+        }
+
+        let config = Self::get_pricing_config(env.clone());
+        let days_until_due = time::days_between(current_time, due_date);
+        pricing::quote(&config, grade, trust_score, amount, days_until_due)
+    }
+
+    fn extend_instance_ttl(env: &Env) {
+        env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    fn extend_persistent_ttl(env: &Env, key: &DataKey) {
+        env.storage().persistent().extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+    }
+
+    /// Read an invoice in the current layout, converting one left in an older layout
+    fn read_invoice(env: &Env, invoice_id: u64) -> Option<Invoice> {
+        let value: Val = env.storage().persistent().get(&DataKey::Invoice(invoice_id))?;
+        if legacy::layout_version(env, &value) == SCHEMA_VERSION {
+            Some(Invoice::try_from_val(env, &value).expect("Unreadable invoice"))
+        } else {
+            Some(legacy::legacy_invoice_from_val(env, &value).0)
+        }
+    }
+
+    /// Load an invoice, extending its TTL while it is still live
+    fn load_invoice(env: &Env, invoice_id: u64) -> Invoice {
+        let key = DataKey::Invoice(invoice_id);
+        let invoice = Self::read_invoice(env, invoice_id).expect("Invoice not found");
+        if invoice.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+        invoice
+    }
+
+    /// Store an invoice; settled invoices keep their remaining TTL and are left to expire
+    fn save_invoice(env: &Env, invoice: &Invoice) {
+        let key = DataKey::Invoice(invoice.id);
+        env.storage().persistent().set(&key, invoice);
+        if invoice.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+            Self::extend_invoice_entries_ttl(env, invoice);
+        }
+    }
+
+    /// Helper function to update status-based indexes
+    fn update_status_index(
+        env: &Env, 
+        invoice_id: u64, 
+        old_status: InvoiceStatus, 
+        new_status: InvoiceStatus
+    ) {
+        // Remove from old status index
+        index::remove(env, &DataKey::InvoicesByStatus(old_status), invoice_id);
+
+        // Add to new status index
+        index::push(env, &DataKey::InvoicesByStatus(new_status), invoice_id);
     }
 }
 
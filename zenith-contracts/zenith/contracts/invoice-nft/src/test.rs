@@ -1,17 +1,75 @@
 #![cfg(test)]
 
 use super::*;
-use soroban_sdk::{testutils::{Address as _, Ledger}, Address, Env, String, BytesN};
+use soroban_sdk::{
+    symbol_short, testutils::{storage::Persistent as _, Address as _, Ledger, MockAuth, MockAuthInvoke}, Address, Env, IntoVal, String,
+};
+use zenith_common::customer;
+use zenith_common::ttl::DAY_IN_LEDGERS;
+
+/// Salt a creator keeps off-chain for the test customers
+const TEST_SALT: [u8; 32] = [1u8; 32];
+
+/// Salt the test customers give the creators who invoice them
+const TEST_DEBTOR_SALT: [u8; 32] = [2u8; 32];
+
+/// Commit to a customer the way a creator does off-chain
+fn customer_commitment(env: &Env, name: &str, email: &str) -> CustomerCommitment {
+    let salt = BytesN::from_array(env, &TEST_SALT);
+    let debtor_salt = BytesN::from_array(env, &TEST_DEBTOR_SALT);
+    customer::commitment(env, &salt, &debtor_salt, &String::from_str(env, name), &String::from_str(env, email), None)
+}
+
+/// Debtor id of a test customer
+fn test_debtor_id(env: &Env, email: &str) -> BytesN<32> {
+    customer::debtor_id(env, &BytesN::from_array(env, &TEST_DEBTOR_SALT), &String::from_str(env, email))
+}
+
+/// Register USDC as an invoice asset
+fn register_usdc(env: &Env, client: &InvoiceNFTContractClient, admin: &Address) {
+    let config = AssetConfig {
+        token: Address::generate(env),
+        decimals: 7,
+        min_amount: 1_0000000,
+        max_amount: 1000000_0000000,
+    };
+    client.set_asset(admin, &Currency::Usdc, &config);
+}
+
+/// Initialize the contract with a throwaway admin and register USDC, returning the admin
+fn initialize_with_usdc(env: &Env, client: &InvoiceNFTContractClient) -> Address {
+    let admin = Address::generate(env);
+    client.initialize(&admin);
+    register_usdc(env, client, &admin);
+    admin
+}
 
 #[test]
 fn test_initialize_contract() {
     let env = Env::default();
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
 
-    client.initialize();
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
     
     assert_eq!(client.get_total_invoices(), 0);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+}
+
+#[test]
+#[should_panic(expected = "Already initialized")]
+fn test_initialize_twice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+    client.initialize(&Address::generate(&env));
 }
 
 #[test]
@@ -19,10 +77,10 @@ fn test_create_invoice() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
     let params = InvoiceCreationParams {
@@ -30,17 +88,19 @@ fn test_create_invoice() {
         business_name: String::from_str(&env, "TechStudio LLC"),
         location: String::from_str(&env, "San Francisco, CA"),
         amount: 5000_0000000i128, // 5000 USDC (7 decimal places)
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 45, // 45 days from now
-        customer_name: String::from_str(&env, "Enterprise Corp"),
-        customer_email: String::from_str(&env, "finance@enterprise.com"),
+        customer_commitment: customer_commitment(&env, "Enterprise Corp", "finance@enterprise.com"),
         description: String::from_str(&env, "Custom web application development"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 300u32, // 3% in basis points
         collateral_type: None,
         is_recurring: false,
         esg_compliant: Some(true),
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id = client.create_invoice(&creator, &params);
@@ -55,8 +115,14 @@ fn test_create_invoice() {
     assert_eq!(invoice.creator, creator);
     assert_eq!(invoice.invoice_details.amount, params.amount);
     assert_eq!(invoice.status, InvoiceStatus::Pending);
-    assert_eq!(invoice.creator_info.business_name, params.business_name);
-    assert_eq!(invoice.creator_info.trust_score, 50); // Default trust score
+
+    // The first invoice sets up the creator's profile
+    let profile = client.get_creator_profile(&creator).unwrap();
+    assert_eq!(profile.business_name, params.business_name);
+    assert_eq!(profile.trust_score, 50); // Default trust score
+    assert_eq!(profile.verification_level, VerificationLevel::Pending);
+    // The creator's own ESG claim is not trusted
+    assert_eq!(invoice.risk_assessment.esg_compliant, Some(false));
 }
 
 #[test]
@@ -64,10 +130,10 @@ fn test_create_multiple_invoices() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
     
@@ -77,17 +143,19 @@ fn test_create_multiple_invoices() {
         business_name: String::from_str(&env, "Business 1"),
         location: String::from_str(&env, "Location 1"),
         amount: 1000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 30,
-        customer_name: String::from_str(&env, "Customer 1"),
-        customer_email: String::from_str(&env, "customer1@example.com"),
+        customer_commitment: customer_commitment(&env, "Customer 1", "customer1@example.com"),
         description: String::from_str(&env, "Description 1"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id_1 = client.create_invoice(&creator, &params1);
@@ -98,17 +166,19 @@ fn test_create_multiple_invoices() {
         business_name: String::from_str(&env, "Business 2"),
         location: String::from_str(&env, "Location 2"),
         amount: 2000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 60,
-        customer_name: String::from_str(&env, "Customer 2"),
-        customer_email: String::from_str(&env, "customer2@example.com"),
+        customer_commitment: customer_commitment(&env, "Customer 2", "customer2@example.com"),
         description: String::from_str(&env, "Description 2"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 300u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id_2 = client.create_invoice(&creator, &params2);
@@ -118,7 +188,7 @@ fn test_create_multiple_invoices() {
     assert_eq!(client.get_total_invoices(), 2);
 
     // Check creator's invoices
-    let creator_invoices = client.get_invoices_by_creator(&creator);
+    let creator_invoices = client.get_invoices_by_creator(&creator, &None, &10u32).invoice_ids;
     assert_eq!(creator_invoices.len(), 2);
 }
 
@@ -127,39 +197,40 @@ fn test_update_invoice_status() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    let admin = initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
-    let admin = Address::generate(&env);
     
     let params = InvoiceCreationParams {
         title: String::from_str(&env, "Test Invoice"),
         business_name: String::from_str(&env, "Test Business"),
         location: String::from_str(&env, "Test Location"),
         amount: 1000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 30,
-        customer_name: String::from_str(&env, "Test Customer"),
-        customer_email: String::from_str(&env, "test@example.com"),
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
         description: String::from_str(&env, "Test Description"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id = client.create_invoice(&creator, &params);
 
-    // Update status to Open
-    client.update_status(&invoice_id, &InvoiceStatus::Open, &admin);
+    // Update status to Approved
+    client.update_status(&invoice_id, &InvoiceStatus::Approved, &admin);
     
     let invoice = client.get_invoice(&invoice_id).unwrap();
-    assert_eq!(invoice.status, InvoiceStatus::Open);
+    assert_eq!(invoice.status, InvoiceStatus::Approved);
 
     // Update status to Funded
     client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
@@ -169,36 +240,88 @@ fn test_update_invoice_status() {
     assert!(invoice.timestamps.funded_at.is_some());
 }
 
+#[test]
+fn test_update_status_by_marketplace() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    let marketplace = Address::generate(&env);
+    client.set_marketplace(&admin, &marketplace);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &marketplace);
+    client.mark_as_paid(&invoice_id, &marketplace);
+
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().status, InvoiceStatus::Completed);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_update_status_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    // Creators cannot complete their own invoices to build up a track record
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Completed, &creator);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_mark_as_paid_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+    client.mark_as_paid(&invoice_id, &Address::generate(&env));
+}
+
 #[test]
 fn test_mark_invoice_as_paid() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    let admin = initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
-    let payer = Address::generate(&env);
-    let admin = Address::generate(&env);
     
     let params = InvoiceCreationParams {
         title: String::from_str(&env, "Test Invoice"),
         business_name: String::from_str(&env, "Test Business"),
         location: String::from_str(&env, "Test Location"),
         amount: 1000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 30,
-        customer_name: String::from_str(&env, "Test Customer"),
-        customer_email: String::from_str(&env, "test@example.com"),
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
         description: String::from_str(&env, "Test Description"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id = client.create_invoice(&creator, &params);
@@ -207,7 +330,7 @@ fn test_mark_invoice_as_paid() {
     client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
     
     // Mark as paid
-    client.mark_as_paid(&invoice_id, &payer);
+    client.mark_as_paid(&invoice_id, &admin);
     
     let invoice = client.get_invoice(&invoice_id).unwrap();
     assert_eq!(invoice.status, InvoiceStatus::Completed);
@@ -219,13 +342,12 @@ fn test_get_invoices_by_status() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    let admin = initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
-    let admin = Address::generate(&env);
     
     // Create multiple invoices
     let params1 = InvoiceCreationParams {
@@ -233,17 +355,19 @@ fn test_get_invoices_by_status() {
         business_name: String::from_str(&env, "Business 1"),
         location: String::from_str(&env, "Location 1"),
         amount: 1000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 30,
-        customer_name: String::from_str(&env, "Customer 1"),
-        customer_email: String::from_str(&env, "customer1@example.com"),
+        customer_commitment: customer_commitment(&env, "Customer 1", "customer1@example.com"),
         description: String::from_str(&env, "Description 1"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id_1 = client.create_invoice(&creator, &params1);
@@ -253,31 +377,33 @@ fn test_get_invoices_by_status() {
         business_name: String::from_str(&env, "Business 2"),
         location: String::from_str(&env, "Location 2"),
         amount: 2000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 60,
-        customer_name: String::from_str(&env, "Customer 2"),
-        customer_email: String::from_str(&env, "customer2@example.com"),
+        customer_commitment: customer_commitment(&env, "Customer 2", "customer2@example.com"),
         description: String::from_str(&env, "Description 2"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 300u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     let invoice_id_2 = client.create_invoice(&creator, &params2);
 
-    // Update one invoice to Open status
-    client.update_status(&invoice_id_1, &InvoiceStatus::Open, &admin);
+    // Update one invoice to Approved status
+    client.update_status(&invoice_id_1, &InvoiceStatus::Approved, &admin);
 
     // Check pending invoices
-    let pending_invoices = client.get_invoices_by_status(&InvoiceStatus::Pending);
+    let pending_invoices = client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &10u32).invoice_ids;
     assert_eq!(pending_invoices.len(), 1);
     assert_eq!(pending_invoices.get(0).unwrap(), invoice_id_2);
 
     // Check open invoices
-    let open_invoices = client.get_invoices_by_status(&InvoiceStatus::Open);
+    let open_invoices = client.get_invoices_by_status(&InvoiceStatus::Approved, &None, &10u32).invoice_ids;
     assert_eq!(open_invoices.len(), 1);
     assert_eq!(open_invoices.get(0).unwrap(), invoice_id_1);
 }
@@ -288,28 +414,34 @@ fn test_create_invoice_with_past_due_date() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    initialize_with_usdc(&env, &client);
     
+    env.ledger().with_mut(|li| {
+        li.timestamp = 86400 * 10;
+    });
+
     let creator = Address::generate(&env);
     let params = InvoiceCreationParams {
         title: String::from_str(&env, "Invalid Invoice"),
         business_name: String::from_str(&env, "Test Business"),
         location: String::from_str(&env, "Test Location"),
         amount: 1000_0000000i128,
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() - 86400, // Yesterday
-        customer_name: String::from_str(&env, "Test Customer"),
-        customer_email: String::from_str(&env, "test@example.com"),
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
         description: String::from_str(&env, "Test Description"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     client.create_invoice(&creator, &params);
@@ -321,10 +453,10 @@ fn test_create_invoice_with_negative_amount() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register_contract(None, InvoiceNFTContract);
+    let contract_id = env.register(InvoiceNFTContract, ());
     let client = InvoiceNFTContractClient::new(&env, &contract_id);
     
-    client.initialize();
+    initialize_with_usdc(&env, &client);
     
     let creator = Address::generate(&env);
     let params = InvoiceCreationParams {
@@ -332,18 +464,2844 @@ fn test_create_invoice_with_negative_amount() {
         business_name: String::from_str(&env, "Test Business"),
         location: String::from_str(&env, "Test Location"),
         amount: -1000_0000000i128, // Negative amount
-        currency: String::from_str(&env, "USDC"),
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    client.create_invoice(&creator, &params);
+}
+
+#[test]
+fn test_update_trust_score() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+
+    assert_eq!(client.get_trust_score(&creator), 50); // Default trust score
+
+    client.update_trust_score(&creator, &80u32, &admin);
+    assert_eq!(client.get_trust_score(&creator), 80);
+
+    // The first invoice keeps the score set before it
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
         due_date: env.ledger().timestamp() + 86400 * 30,
-        customer_name: String::from_str(&env, "Test Customer"),
-        customer_email: String::from_str(&env, "test@example.com"),
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
         description: String::from_str(&env, "Test Description"),
         tax_rate: None,
+        tax_basis: TaxBasis::Net,
         early_discount: 200u32,
         collateral_type: None,
         is_recurring: false,
         esg_compliant: None,
         proof_hash: None,
+        invoice_number: None,
+        customer: None,
     };
 
     client.create_invoice(&creator, &params);
+    let profile = client.get_creator_profile(&creator).unwrap();
+    assert_eq!(profile.trust_score, 80);
+    assert_eq!(profile.business_name, params.business_name);
+
+    // A trust oracle's update reaches the profile every invoice reads
+    let oracle = Address::generate(&env);
+    client.set_trust_oracle(&admin, &oracle, &true);
+    assert!(client.is_trust_oracle(&oracle));
+    client.update_trust_score(&creator, &60u32, &oracle);
+    assert_eq!(client.get_creator_profile(&creator).unwrap().trust_score, 60);
+
+    // Minting again leaves the profile to the admin and oracles
+    client.create_invoice(&creator, &InvoiceCreationParams { business_name: String::from_str(&env, "Renamed"), ..params });
+    assert_eq!(client.get_creator_profile(&creator).unwrap().business_name, String::from_str(&env, "Test Business"));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_update_trust_score_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    client.update_trust_score(&Address::generate(&env), &80u32, &Address::generate(&env));
+}
+
+#[test]
+fn test_update_creator_profile() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let business_name = String::from_str(&env, "Test Business GmbH");
+    let location = String::from_str(&env, "Munich, DE");
+    client.update_creator_details(&admin, &creator, &business_name, &location);
+    client.set_verification_level(&admin, &creator, &VerificationLevel::Verified);
+
+    assert_eq!(
+        client.get_creator_profile(&creator),
+        Some(CreatorProfile { business_name, location, trust_score: 50, verification_level: VerificationLevel::Verified })
+    );
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Verified);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_update_creator_details_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    client.create_invoice(&creator, &ttl_test_params(&env));
+
+    // Creators cannot edit their own profile
+    client.update_creator_details(&creator, &creator, &String::from_str(&env, "Renamed"), &String::from_str(&env, "Anywhere"));
+}
+
+#[test]
+fn test_cancel_invoice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    let invoice_id = client.create_invoice(&creator, &params);
+
+    client.cancel_invoice(&invoice_id, &creator);
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.status, InvoiceStatus::Cancelled);
+    assert!(invoice.timestamps.cancelled_at.is_some());
+
+    assert_eq!(client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &10u32).invoice_ids.len(), 0);
+    assert_eq!(client.get_invoices_by_status(&InvoiceStatus::Cancelled, &None, &10u32).invoice_ids.get(0).unwrap(), invoice_id);
+}
+
+#[test]
+#[should_panic(expected = "Invoice cannot be cancelled in current status")]
+fn test_cancel_funded_invoice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    client.cancel_invoice(&invoice_id, &creator);
+}
+
+#[test]
+fn test_amend_invoice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    let invoice_id = client.create_invoice(&creator, &params);
+    let original = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(original.financial_terms.discount_rate, 500); // 2% + 30 * 0.1%
+
+    // Move the due date out to 60 days
+    let new_due_date = env.ledger().timestamp() + 86400 * 60;
+    let amendment = InvoiceAmendment {
+        title: None,
+        amount: None,
+        due_date: Some(new_due_date),
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+
+    let amendment_count = client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert_eq!(amendment_count, 1);
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.invoice_details.due_date, new_due_date);
+    assert_eq!(invoice.timestamps.due_date, new_due_date);
+    assert_eq!(invoice.financial_terms.discount_rate, 800); // 2% + 60 * 0.1%
+    assert_eq!(invoice.financial_terms.offer_amount, 1000_0000000i128 * 9200 / 10000);
+    assert_eq!(invoice.amendment_count, 1);
+}
+
+#[test]
+#[should_panic(expected = "Invoice can only be amended while pending")]
+fn test_amend_open_invoice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.update_status(&invoice_id, &InvoiceStatus::Approved, &admin);
+
+    let amendment = InvoiceAmendment {
+        title: Some(String::from_str(&env, "Renamed")),
+        amount: None,
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+}
+
+#[test]
+fn test_get_invoices_by_status_pagination() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = InvoiceCreationParams {
+        title: String::from_str(&env, "Test Invoice"),
+        business_name: String::from_str(&env, "Test Business"),
+        location: String::from_str(&env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(&env, "Test Customer", "test@example.com"),
+        description: String::from_str(&env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    };
+
+    for _ in 0..5 {
+        client.create_invoice(&creator, &params);
+    }
+
+    // Removing an invoice from the middle keeps the rest of the index linked
+    client.cancel_invoice(&3u64, &creator);
+    assert_eq!(client.count_invoices_by_status(&InvoiceStatus::Pending), 4);
+
+    let first_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &2u32);
+    assert_eq!(first_page.invoice_ids, Vec::from_array(&env, [1u64, 2u64]));
+    assert_eq!(first_page.next_cursor, Some(2));
+
+    let second_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &first_page.next_cursor, &2u32);
+    assert_eq!(second_page.invoice_ids, Vec::from_array(&env, [4u64, 5u64]));
+    assert_eq!(second_page.next_cursor, None);
+
+    let creator_page = client.get_invoices_by_creator(&creator, &Some(4u64), &10u32);
+    assert_eq!(creator_page.invoice_ids, Vec::from_array(&env, [5u64]));
+    assert_eq!(creator_page.next_cursor, None);
+}
+
+#[test]
+fn test_get_invoices_by_status_cursor_removed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    for _ in 0..3 {
+        client.create_invoice(&creator, &ttl_test_params(&env));
+    }
+
+    let first_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &2u32);
+    assert_eq!(first_page.next_cursor, Some(2));
+
+    // The cursor's invoice leaves the index between pages
+    client.cancel_invoice(&2u64, &creator);
+
+    // The next page resumes from the invoice that followed it
+    let second_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &first_page.next_cursor, &2u32);
+    assert_eq!(second_page.invoice_ids, Vec::from_array(&env, [3u64]));
+    assert_eq!(second_page.next_cursor, None);
+}
+
+#[test]
+fn test_get_invoices_by_status_cursor_removed_at_tail() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    for _ in 0..3 {
+        client.create_invoice(&creator, &ttl_test_params(&env));
+    }
+
+    let first_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &2u32);
+    assert_eq!(first_page.next_cursor, Some(2));
+
+    // The cursor's invoice and everything after it leave the index
+    client.cancel_invoice(&2u64, &creator);
+    client.cancel_invoice(&3u64, &creator);
+
+    let empty_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &first_page.next_cursor, &2u32);
+    assert!(empty_page.invoice_ids.is_empty());
+    assert_eq!(empty_page.next_cursor, None);
+
+    // Invoices added later are still read from the same cursor
+    let new_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    let second_page = client.get_invoices_by_status(&InvoiceStatus::Pending, &first_page.next_cursor, &2u32);
+    assert_eq!(second_page.invoice_ids, Vec::from_array(&env, [new_id]));
+}
+
+#[test]
+#[should_panic(expected = "Invalid cursor")]
+fn test_get_invoices_by_status_unknown_cursor() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    client.create_invoice(&creator, &ttl_test_params(&env));
+
+    client.get_invoices_by_status(&InvoiceStatus::Pending, &Some(7u64), &2u32);
+}
+
+#[test]
+#[should_panic(expected = "Invalid page limit")]
+fn test_get_invoices_by_status_invalid_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+
+    client.get_invoices_by_status(&InvoiceStatus::Pending, &None, &0u32);
+}
+
+fn ttl_test_params(env: &Env) -> InvoiceCreationParams {
+    InvoiceCreationParams {
+        title: String::from_str(env, "Test Invoice"),
+        business_name: String::from_str(env, "Test Business"),
+        location: String::from_str(env, "Test Location"),
+        amount: 1000_0000000i128,
+        currency: Currency::Usdc,
+        due_date: env.ledger().timestamp() + 86400 * 30,
+        customer_commitment: customer_commitment(env, "Test Customer", "test@example.com"),
+        description: String::from_str(env, "Test Description"),
+        tax_rate: None,
+        tax_basis: TaxBasis::Net,
+        early_discount: 200u32,
+        collateral_type: None,
+        is_recurring: false,
+        esg_compliant: None,
+        proof_hash: None,
+        invoice_number: None,
+        customer: None,
+    }
+}
+
+fn invoice_ttl(env: &Env, contract_id: &Address, invoice_id: u64) -> u32 {
+    env.as_contract(contract_id, || {
+        env.storage().persistent().get_ttl(&DataKey::Invoice(invoice_id))
+    })
+}
+
+#[test]
+fn test_live_invoice_survives_ledger_advance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    assert_eq!(invoice_ttl(&env, &contract_id, invoice_id), PERSISTENT_BUMP_AMOUNT);
+
+    // Step well past the original TTL, reading the invoice and its index along the way
+    for _ in 0..7 {
+        env.ledger().with_mut(|li| li.sequence_number += 20 * DAY_IN_LEDGERS);
+        let invoice = client.get_invoice(&invoice_id).unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Pending);
+        let page = client.get_invoices_by_creator(&creator, &None, &10u32);
+        assert_eq!(page.invoice_ids, Vec::from_array(&env, [invoice_id]));
+    }
+
+    assert!(invoice_ttl(&env, &contract_id, invoice_id) > PERSISTENT_LIFETIME_THRESHOLD);
+    assert_eq!(client.get_total_invoices(), 1);
+}
+
+#[test]
+fn test_bump_skips_settled_invoices() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let live_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    let cancelled_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    client.cancel_invoice(&cancelled_id, &creator);
+
+    env.ledger().with_mut(|li| li.sequence_number += 20 * DAY_IN_LEDGERS);
+
+    // Unknown ids are ignored and cancelled invoices are left to expire
+    let ids = Vec::from_array(&env, [live_id, cancelled_id, 99u64]);
+    assert_eq!(client.bump(&ids), 1);
+
+    assert_eq!(invoice_ttl(&env, &contract_id, live_id), PERSISTENT_BUMP_AMOUNT);
+    assert_eq!(
+        invoice_ttl(&env, &contract_id, cancelled_id),
+        PERSISTENT_BUMP_AMOUNT - 20 * DAY_IN_LEDGERS
+    );
+}
+
+/// Release build of this contract from the current sources, see `scripts/build-test-fixtures.sh`
+const INVOICE_NFT_WASM: &[u8] = include_bytes!("../testdata/invoice_nft.wasm");
+
+/// Upload the release build. It is not run through wasm-opt, so uploading it is metered
+/// well above what the deployed contract costs and would exhaust the default budget.
+fn upload_v4_wasm(env: &Env) -> BytesN<32> {
+    env.cost_estimate().budget().reset_unlimited();
+    let wasm_hash = env.deployer().upload_contract_wasm(INVOICE_NFT_WASM);
+    env.cost_estimate().budget().reset_default();
+    wasm_hash
+}
+
+#[test]
+fn test_upgrade_keeps_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let wasm_hash = upload_v4_wasm(&env);
+    client.upgrade(&admin, &wasm_hash);
+
+    // Calls are now served by the uploaded wasm against the existing storage
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.creator, creator);
+    assert_eq!(invoice.status, InvoiceStatus::Pending);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    let next_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    assert_eq!(next_id, invoice_id + 1);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_upgrade_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+
+    let wasm_hash = upload_v4_wasm(&env);
+    client.upgrade(&Address::generate(&env), &wasm_hash);
+}
+
+#[test]
+fn test_migrate_stamps_unversioned_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+
+    // Simulate storage written before the schema version was tracked
+    env.as_contract(&contract_id, || {
+        env.storage().instance().remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(client.get_schema_version(), 0);
+
+    assert_eq!(client.migrate(&admin), SCHEMA_VERSION);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    // Running it again is a no-op
+    assert_eq!(client.migrate(&admin), SCHEMA_VERSION);
+}
+
+#[test]
+fn test_migrate_moves_unversioned_status_lists() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+
+    // Simulate unversioned storage, where each status list was one `Vec<u64>`
+    env.as_contract(&contract_id, || {
+        env.storage().instance().remove(&DataKey::SchemaVersion);
+        let open_key = legacy::DataKeyV0::InvoicesByStatus(legacy::InvoiceStatusV0::Open);
+        env.storage().persistent().set(&open_key, &Vec::from_array(&env, [4u64, 7u64]));
+        let funded_key = legacy::DataKeyV0::InvoicesByStatus(legacy::InvoiceStatusV0::Funded);
+        env.storage().persistent().set(&funded_key, &Vec::from_array(&env, [2u64]));
+    });
+
+    client.migrate(&admin);
+
+    // Open invoices were the ones cleared for funding
+    let approved = client.get_invoices_by_status(&InvoiceStatus::Approved, &None, &10u32);
+    assert_eq!(approved.invoice_ids, Vec::from_array(&env, [4u64, 7u64]));
+    assert_eq!(client.count_invoices_by_status(&InvoiceStatus::Funded), 1);
+
+    env.as_contract(&contract_id, || {
+        let open_key = legacy::DataKeyV0::InvoicesByStatus(legacy::InvoiceStatusV0::Open);
+        assert!(!env.storage().persistent().has(&open_key));
+    });
+}
+
+/// Overwrite a stored invoice with its unversioned encoding, listed as open
+fn store_v0_invoice(env: &Env, contract_id: &Address, invoice: &Invoice) {
+    let old = legacy::InvoiceV0 {
+        id: invoice.id,
+        title: invoice.title.clone(),
+        symbol: invoice.symbol.clone(),
+        status: legacy::InvoiceStatusV0::Open,
+        creator: invoice.creator.clone(),
+        creator_info: legacy::CreatorInfoV1 {
+            business_name: String::from_str(env, "Test Business"),
+            location: String::from_str(env, "Test Location"),
+            trust_score: 50,
+            verification_level: String::from_str(env, "verified"),
+        },
+        invoice_details: legacy::InvoiceDetailsV1 {
+            amount: invoice.invoice_details.amount,
+            currency: String::from_str(env, "USDC"),
+            due_date: invoice.invoice_details.due_date,
+            customer_name: String::from_str(env, "Test Customer"),
+            customer_email: String::from_str(env, "test@example.com"),
+            description: invoice.invoice_details.description.clone(),
+            tax_rate: invoice.invoice_details.tax_rate,
+            early_discount: invoice.invoice_details.early_discount,
+        },
+        financial_terms: invoice.financial_terms.clone(),
+        risk_assessment: legacy::RiskAssessmentV1 {
+            risk_score: String::from_str(env, "C"),
+            esg_compliant: invoice.risk_assessment.esg_compliant,
+            verification_method: String::from_str(env, "manual"),
+        },
+        timestamps: legacy::TimestampsV0 {
+            created_at: invoice.timestamps.created_at,
+            funded_at: None,
+            due_date: invoice.timestamps.due_date,
+            completed_at: None,
+        },
+        proof_hash: invoice.proof_hash.clone(),
+    };
+    env.as_contract(contract_id, || {
+        env.storage().persistent().set(&DataKey::Invoice(invoice.id), &old);
+    });
+}
+
+#[test]
+fn test_get_invoice_reads_v0_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v0_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.status, InvoiceStatus::Approved);
+    assert_eq!(invoice.amendment_count, 0);
+    assert_eq!(invoice.timestamps.cancelled_at, None);
+    assert_eq!(invoice.invoice_details.currency, Currency::Usdc);
+    assert_eq!(invoice.risk_assessment.risk_score, RiskGrade::C);
+}
+
+#[test]
+fn test_migrate_invoices_rewrites_v0_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v0_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    assert_eq!(client.migrate_invoices(&admin, &Vec::from_array(&env, [invoice_id])), 1);
+
+    env.as_contract(&contract_id, || {
+        let stored: Invoice = env.storage().persistent().get(&DataKey::Invoice(invoice_id)).unwrap();
+        assert_eq!(stored.status, InvoiceStatus::Approved);
+        assert_eq!(stored.risk_assessment.verification_method, VerificationMethod::Manual);
+    });
+}
+
+#[test]
+fn test_initialize_keeps_unversioned_invoices() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v0_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    // Simulate unversioned storage, which had invoices but no admin
+    env.as_contract(&contract_id, || {
+        env.storage().instance().remove(&DataKey::Admin);
+        env.storage().instance().remove(&DataKey::SchemaVersion);
+    });
+
+    client.initialize(&admin);
+    assert_eq!(client.get_schema_version(), 0);
+    assert_eq!(client.get_total_invoices(), 1);
+
+    client.migrate(&admin);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().status, InvoiceStatus::Approved);
+}
+
+/// Overwrite a stored invoice with its schema version 1 encoding
+fn store_v1_invoice(env: &Env, contract_id: &Address, invoice: &Invoice) {
+    let old = legacy::InvoiceV1 {
+        id: invoice.id,
+        title: invoice.title.clone(),
+        symbol: invoice.symbol.clone(),
+        status: invoice.status.clone(),
+        creator: invoice.creator.clone(),
+        creator_info: legacy::CreatorInfoV1 {
+            business_name: String::from_str(env, "Test Business"),
+            location: String::from_str(env, "Test Location"),
+            trust_score: 50,
+            verification_level: String::from_str(env, "pending"),
+        },
+        invoice_details: legacy::InvoiceDetailsV1 {
+            amount: invoice.invoice_details.amount,
+            currency: String::from_str(env, "EURC"),
+            due_date: invoice.invoice_details.due_date,
+            customer_name: String::from_str(env, "Test Customer"),
+            customer_email: String::from_str(env, "test@example.com"),
+            description: invoice.invoice_details.description.clone(),
+            tax_rate: invoice.invoice_details.tax_rate,
+            early_discount: invoice.invoice_details.early_discount,
+        },
+        financial_terms: invoice.financial_terms.clone(),
+        risk_assessment: legacy::RiskAssessmentV1 {
+            risk_score: String::from_str(env, "B"),
+            esg_compliant: invoice.risk_assessment.esg_compliant,
+            verification_method: String::from_str(env, "integrated"),
+        },
+        timestamps: invoice.timestamps.clone(),
+        proof_hash: invoice.proof_hash.clone(),
+        amendment_count: invoice.amendment_count,
+    };
+    env.as_contract(contract_id, || {
+        env.storage().persistent().set(&DataKey::Invoice(invoice.id), &old);
+    });
+}
+
+#[test]
+fn test_get_invoice_reads_v1_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v1_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.creator, creator);
+    assert_eq!(invoice.invoice_details.currency, Currency::Eurc);
+    assert_eq!(invoice.risk_assessment.risk_score, RiskGrade::B);
+    assert_eq!(invoice.risk_assessment.verification_method, VerificationMethod::Integrated);
+}
+
+#[test]
+fn test_migrate_invoices_rewrites_v1_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let old_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    let current_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v1_invoice(&env, &contract_id, &client.get_invoice(&old_id).unwrap());
+
+    // Only the invoice still in the old layout is rewritten; unknown ids are skipped
+    let ids = Vec::from_array(&env, [old_id, current_id, 99]);
+    assert_eq!(client.migrate_invoices(&admin, &ids), 1);
+
+    env.as_contract(&contract_id, || {
+        let stored: Invoice = env.storage().persistent().get(&DataKey::Invoice(old_id)).unwrap();
+        assert_eq!(stored.risk_assessment.risk_score, RiskGrade::B);
+        assert_eq!(stored.invoice_details.currency, Currency::Eurc);
+    });
+
+    // Running it again is a no-op
+    assert_eq!(client.migrate_invoices(&admin, &ids), 0);
+}
+
+#[test]
+fn test_migrate_invoices_moves_unversioned_creator_list() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let old_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v1_invoice(&env, &contract_id, &client.get_invoice(&old_id).unwrap());
+
+    // Simulate the creator's invoices kept as one `Vec<u64>`
+    let creator_key = DataKey::InvoicesByCreator(creator.clone());
+    env.as_contract(&contract_id, || {
+        index::remove(&env, &creator_key, old_id);
+        env.storage().persistent().set(&creator_key, &Vec::from_array(&env, [old_id]));
+    });
+    assert_eq!(client.get_invoices_by_creator(&creator, &None, &10u32).invoice_ids.len(), 0);
+
+    client.migrate_invoices(&admin, &Vec::from_array(&env, [old_id]));
+
+    let page = client.get_invoices_by_creator(&creator, &None, &10u32);
+    assert_eq!(page.invoice_ids, Vec::from_array(&env, [old_id]));
+    env.as_contract(&contract_id, || {
+        assert!(!env.storage().persistent().has(&creator_key));
+    });
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_migrate_invoices_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+
+    client.migrate_invoices(&Address::generate(&env), &Vec::new(&env));
+}
+
+#[test]
+fn test_create_invoice_prices_from_grid() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let trusted = Address::generate(&env);
+    client.update_trust_score(&trusted, &90u32, &admin);
+
+    // Grade C, 30-day bucket: 5%, less 2% for a trust score of 90
+    let invoice = client.get_invoice(&client.create_invoice(&creator, &ttl_test_params(&env))).unwrap();
+    let trusted_invoice = client.get_invoice(&client.create_invoice(&trusted, &ttl_test_params(&env))).unwrap();
+
+    assert_eq!(invoice.financial_terms.discount_rate, 500);
+    assert_eq!(invoice.financial_terms.offer_amount, 950_0000000);
+    assert_eq!(invoice.financial_terms.expected_yield, 500 * 365 / 30);
+    assert_eq!(trusted_invoice.financial_terms.discount_rate, 300);
+    assert_eq!(trusted_invoice.financial_terms.offer_amount, 970_0000000);
+}
+
+#[test]
+fn test_quote_matches_minted_terms() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let quote = client.quote(&QuoteParams {
+        creator: creator.clone(),
+        amount: params.amount,
+        due_date: params.due_date,
+        risk_grade: RiskGrade::C,
+    });
+
+    // Quoting is read-only
+    assert_eq!(client.get_total_invoices(), 0);
+
+    let invoice = client.get_invoice(&client.create_invoice(&creator, &params)).unwrap();
+    assert_eq!(quote.discount_rate, invoice.financial_terms.discount_rate);
+    assert_eq!(quote.offer_amount, invoice.financial_terms.offer_amount);
+    assert_eq!(quote.expected_yield, invoice.financial_terms.expected_yield);
+
+    // Riskier grades get a deeper discount
+    let grade_d = client.quote(&QuoteParams {
+        creator,
+        amount: params.amount,
+        due_date: params.due_date,
+        risk_grade: RiskGrade::D,
+    });
+    assert_eq!(grade_d.discount_rate, 700);
+}
+
+#[test]
+fn test_set_pricing_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let before = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let mut config = client.get_pricing_config();
+    config.grade_rates.set(2, soroban_sdk::vec![&env, 250, 800, 1100, 1500, 1500]);
+    client.set_pricing_config(&admin, &config);
+    assert_eq!(client.get_pricing_config(), config);
+
+    // New invoices use the new grid; existing invoices keep their terms
+    let after = client.create_invoice(&creator, &ttl_test_params(&env));
+    assert_eq!(client.get_invoice(&before).unwrap().financial_terms.discount_rate, 500);
+    assert_eq!(client.get_invoice(&after).unwrap().financial_terms.discount_rate, 250);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_pricing_config_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+
+    let config = client.get_pricing_config();
+    client.set_pricing_config(&Address::generate(&env), &config);
+}
+
+#[test]
+#[should_panic(expected = "Invalid pricing config")]
+fn test_set_pricing_config_invalid() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+
+    let mut config = client.get_pricing_config();
+    config.tenor_buckets = soroban_sdk::vec![&env, 90, 60, 30, 180, 365];
+    client.set_pricing_config(&admin, &config);
+}
+
+#[test]
+fn test_create_invoice_grades_automatically() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    // A new creator with no extras lands in grade C
+    let creator = Address::generate(&env);
+    let invoice = client.get_invoice(&client.create_invoice(&creator, &ttl_test_params(&env))).unwrap();
+    assert_eq!(invoice.risk_assessment.risk_score, RiskGrade::C);
+    assert_eq!(invoice.risk_assessment.verification_method, VerificationMethod::Automated);
+
+    // Trust, collateral and a supporting document lift the grade
+    let trusted = Address::generate(&env);
+    client.update_trust_score(&trusted, &90u32, &admin);
+    let mut params = ttl_test_params(&env);
+    params.collateral_type = Some(String::from_str(&env, "receivable"));
+    params.proof_hash = Some(BytesN::from_array(&env, &[7u8; 32]));
+    let invoice_id = client.create_invoice(&trusted, &params);
+    assert_eq!(client.get_risk_grade(&invoice_id), RiskGrade::B);
+
+    // Low trust drops to D
+    let risky = Address::generate(&env);
+    client.update_trust_score(&risky, &20u32, &admin);
+    let invoice_id = client.create_invoice(&risky, &ttl_test_params(&env));
+    assert_eq!(client.get_risk_grade(&invoice_id), RiskGrade::D);
+}
+
+#[test]
+fn test_outcomes_update_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let debtor = test_debtor_id(&env, "test@example.com");
+
+    let paid = client.create_invoice(&creator, &ttl_test_params(&env));
+    let defaulted = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    client.update_status(&paid, &InvoiceStatus::Funded, &admin);
+    client.mark_as_paid(&paid, &admin);
+    client.update_status(&defaulted, &InvoiceStatus::Defaulted, &admin);
+
+    let history = client.get_creator_history(&creator);
+    assert_eq!(history.invoice_count, 2);
+    assert_eq!(history.total_volume, 2000_0000000i128);
+    assert_eq!(history.completed_count, 1);
+    assert_eq!(history.defaulted_count, 1);
+
+    let debtor_history = client.get_debtor_history(&debtor);
+    assert_eq!(debtor_history.paid_count, 1);
+    assert_eq!(debtor_history.defaulted_count, 1);
+
+    // The default outweighs the repayment for the next invoice
+    let next = client.create_invoice(&creator, &ttl_test_params(&env));
+    assert_eq!(client.get_risk_grade(&next), RiskGrade::D);
+}
+
+#[test]
+fn test_override_risk_grade() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    assert_eq!(client.get_risk_override(&invoice_id), None);
+
+    let risk_override = RiskOverride {
+        risk_grade: RiskGrade::A,
+        reason: String::from_str(&env, "Debtor is investment grade"),
+    };
+    let quote = client.override_risk_grade(&admin, &invoice_id, &risk_override);
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.risk_assessment.risk_score, RiskGrade::A);
+    assert_eq!(invoice.risk_assessment.verification_method, VerificationMethod::Manual);
+    assert_eq!(invoice.financial_terms.discount_rate, 300);
+    assert_eq!(quote.discount_rate, 300);
+    assert_eq!(client.get_risk_override(&invoice_id), Some(risk_override));
+
+    // Amending sends the invoice back to automated grading
+    let amendment = InvoiceAmendment {
+        title: Some(String::from_str(&env, "Amended")),
+        amount: None,
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert_eq!(client.get_risk_grade(&invoice_id), RiskGrade::C);
+    assert_eq!(client.get_risk_override(&invoice_id), None);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_override_risk_grade_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let risk_override = RiskOverride { risk_grade: RiskGrade::A, reason: String::from_str(&env, "Looks fine") };
+    client.override_risk_grade(&creator, &invoice_id, &risk_override);
+}
+
+#[test]
+#[should_panic(expected = "Risk grade can only be overridden before funding")]
+fn test_override_risk_grade_after_funding() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    let risk_override = RiskOverride { risk_grade: RiskGrade::A, reason: String::from_str(&env, "Looks fine") };
+    client.override_risk_grade(&admin, &invoice_id, &risk_override);
+}
+
+#[test]
+fn test_set_asset() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    assert_eq!(client.get_asset(&Currency::Usdc), None);
+
+    // Local anchor assets are registered by asset code
+    let ngnc = Currency::Anchor(symbol_short!("NGNC"));
+    let config = AssetConfig {
+        token: Address::generate(&env),
+        decimals: 7,
+        min_amount: 50000_0000000,
+        max_amount: 5000000_0000000,
+    };
+    client.set_asset(&admin, &ngnc, &config);
+    assert_eq!(client.get_asset(&ngnc), Some(config));
+
+    let mut params = ttl_test_params(&env);
+    params.currency = ngnc.clone();
+    params.amount = 1000000_0000000;
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+    assert_eq!(client.get_invoice_currency(&invoice_id), ngnc);
+
+    // Delisting stops new invoices but leaves existing ones intact
+    client.remove_asset(&admin, &ngnc);
+    assert_eq!(client.get_asset(&ngnc), None);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().invoice_details.currency, ngnc);
+}
+
+#[test]
+#[should_panic(expected = "Invalid asset config")]
+fn test_set_asset_invalid() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+
+    let config = AssetConfig {
+        token: Address::generate(&env),
+        decimals: 7,
+        min_amount: 1000_0000000,
+        max_amount: 100_0000000, // below the minimum
+    };
+    client.set_asset(&admin, &Currency::Eurc, &config);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_asset_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    register_usdc(&env, &client, &Address::generate(&env));
+}
+
+#[test]
+#[should_panic(expected = "Unsupported currency")]
+fn test_create_invoice_unregistered_currency() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.currency = Currency::Eurc;
+    client.create_invoice(&Address::generate(&env), &params);
+}
+
+#[test]
+#[should_panic(expected = "Amount outside asset limits")]
+fn test_create_invoice_outside_asset_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.amount = 2000000_0000000; // above the 1,000,000 USDC limit
+    client.create_invoice(&Address::generate(&env), &params);
+}
+
+#[test]
+#[should_panic(expected = "Amount outside asset limits")]
+fn test_amend_invoice_outside_asset_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let amendment = InvoiceAmendment {
+        title: None,
+        amount: Some(5000000), // 0.5 USDC, below the minimum
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+}
+
+#[test]
+fn test_proof_hash_reusable_after_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let proof_hash = BytesN::from_array(&env, &[7u8; 32]);
+    let mut params = ttl_test_params(&env);
+    params.proof_hash = Some(proof_hash.clone());
+
+    let invoice_id = client.create_invoice(&creator, &params);
+    assert_eq!(client.get_invoice_by_proof_hash(&proof_hash), Some(invoice_id));
+
+    // Once the first invoice is withdrawn the document can be minted again
+    client.cancel_invoice(&invoice_id, &creator);
+    assert_eq!(client.get_invoice_by_proof_hash(&proof_hash), None);
+
+    let reissued_id = client.create_invoice(&creator, &params);
+    assert_eq!(client.get_invoice_by_proof_hash(&proof_hash), Some(reissued_id));
+}
+
+#[test]
+#[should_panic(expected = "Duplicate invoice")]
+fn test_duplicate_proof_hash_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.proof_hash = Some(BytesN::from_array(&env, &[7u8; 32]));
+    client.create_invoice(&Address::generate(&env), &params);
+
+    // The same document cannot be financed by anyone else while the first is live
+    client.create_invoice(&Address::generate(&env), &params);
+}
+
+#[test]
+#[should_panic(expected = "Duplicate invoice")]
+fn test_duplicate_fingerprint_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.invoice_number = Some(String::from_str(&env, "INV-0042"));
+    let invoice_id = client.create_invoice(&creator, &params);
+    assert_eq!(client.get_invoice_number(&invoice_id), Some(String::from_str(&env, "INV-0042")));
+
+    // A different number for the same customer and amount is a different invoice
+    params.invoice_number = Some(String::from_str(&env, "INV-0043"));
+    client.create_invoice(&creator, &params);
+
+    // Changing case and spacing does not disguise a resubmission
+    params.invoice_number = Some(String::from_str(&env, "inv-0042 "));
+    params.customer_commitment = customer_commitment(&env, "Test Customer", "Test@Example.com");
+    client.create_invoice(&creator, &params);
+}
+
+#[test]
+#[should_panic(expected = "Duplicate invoice")]
+fn test_amend_into_duplicate_fingerprint_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.invoice_number = Some(String::from_str(&env, "INV-0042"));
+    client.create_invoice(&creator, &params);
+
+    params.amount += 1_0000000;
+    let second_id = client.create_invoice(&creator, &params);
+
+    // Amending the second invoice back to the first one's amount recreates the duplicate
+    let amendment = InvoiceAmendment {
+        title: None,
+        amount: Some(params.amount - 1_0000000),
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&second_id, &creator, &amendment);
+}
+
+#[test]
+fn test_flag_and_clear_fraud() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+    assert!(client.is_verifier(&verifier));
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    assert!(!client.is_flagged(&invoice_id));
+
+    let reason = String::from_str(&env, "Customer denies the invoice");
+    client.flag_fraud(&verifier, &invoice_id, &reason);
+    client.flag_fraud(&admin, &invoice_id, &String::from_str(&env, "Confirmed with customer"));
+
+    let flags = client.get_fraud_flags(&invoice_id);
+    assert_eq!(flags.len(), 2);
+    assert_eq!(
+        flags.get(0).unwrap(),
+        FraudFlag { verifier: verifier.clone(), reason, flagged_at: env.ledger().timestamp() }
+    );
+    assert!(client.is_flagged(&invoice_id));
+
+    client.clear_fraud_flags(&admin, &invoice_id, &String::from_str(&env, "Customer confirmed after review"));
+    assert!(!client.is_flagged(&invoice_id));
+    assert_eq!(client.get_fraud_flags(&invoice_id).len(), 0);
+
+    // Revoked verifiers can no longer flag
+    client.set_verifier(&admin, &verifier, &false);
+    assert!(!client.is_verifier(&verifier));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_flag_fraud_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.flag_fraud(&Address::generate(&env), &invoice_id, &String::from_str(&env, "Suspicious"));
+}
+
+#[test]
+#[should_panic(expected = "Invoice already flagged by verifier")]
+fn test_flag_fraud_twice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.flag_fraud(&admin, &invoice_id, &String::from_str(&env, "Suspicious"));
+    client.flag_fraud(&admin, &invoice_id, &String::from_str(&env, "Still suspicious"));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_verifier_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    client.set_verifier(&Address::generate(&env), &Address::generate(&env), &true);
+}
+
+#[test]
+fn test_acknowledge_invoice_improves_grade() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    client.update_trust_score(&creator, &80u32, &admin);
+
+    let customer = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(customer.clone());
+    let invoice_id = client.create_invoice(&creator, &params);
+    assert_eq!(client.get_invoice_customer(&invoice_id), Some(customer.clone()));
+    assert!(!client.is_acknowledged(&invoice_id));
+
+    let before = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(before.risk_assessment.risk_score, RiskGrade::C);
+
+    let acknowledgement = client.acknowledge_invoice(&invoice_id, &customer, &params.amount, &params.due_date);
+    assert_eq!(
+        acknowledgement,
+        Acknowledgement {
+            customer,
+            amount: params.amount,
+            due_date: params.due_date,
+            acknowledged_at: env.ledger().timestamp(),
+        }
+    );
+    assert_eq!(client.get_acknowledgement(&invoice_id), Some(acknowledgement));
+
+    // The confirmed debt moves the invoice up a grade and lowers its discount
+    let after = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(after.risk_assessment.risk_score, RiskGrade::B);
+    assert!(after.financial_terms.discount_rate < before.financial_terms.discount_rate);
+    assert!(after.financial_terms.offer_amount > before.financial_terms.offer_amount);
+}
+
+#[test]
+fn test_amend_drops_acknowledgement() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let customer = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(customer.clone());
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.acknowledge_invoice(&invoice_id, &customer, &params.amount, &params.due_date);
+
+    // Cosmetic changes keep the acknowledgement
+    let mut amendment = InvoiceAmendment {
+        title: Some(String::from_str(&env, "Renamed")),
+        amount: None,
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert!(client.is_acknowledged(&invoice_id));
+
+    // New terms need a new acknowledgement
+    amendment.title = None;
+    amendment.amount = Some(params.amount * 2);
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert!(!client.is_acknowledged(&invoice_id));
+
+    client.acknowledge_invoice(&invoice_id, &customer, &(params.amount * 2), &params.due_date);
+    assert!(client.is_acknowledged(&invoice_id));
+}
+
+#[test]
+#[should_panic(expected = "Acknowledged terms do not match invoice")]
+fn test_acknowledge_invoice_wrong_terms() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let customer = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(customer.clone());
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+
+    client.acknowledge_invoice(&invoice_id, &customer, &(params.amount - 1), &params.due_date);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_acknowledge_invoice_wrong_customer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(Address::generate(&env));
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+
+    client.acknowledge_invoice(&invoice_id, &Address::generate(&env), &params.amount, &params.due_date);
+}
+
+#[test]
+#[should_panic(expected = "Invoice has no customer")]
+fn test_acknowledge_invoice_without_customer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+
+    client.acknowledge_invoice(&invoice_id, &Address::generate(&env), &params.amount, &params.due_date);
+}
+
+#[test]
+#[should_panic(expected = "Invoice already acknowledged")]
+fn test_acknowledge_invoice_twice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let customer = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(customer.clone());
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+
+    client.acknowledge_invoice(&invoice_id, &customer, &params.amount, &params.due_date);
+    client.acknowledge_invoice(&invoice_id, &customer, &params.amount, &params.due_date);
+}
+
+#[test]
+#[should_panic(expected = "Customer cannot be the creator")]
+fn test_create_invoice_self_customer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(creator.clone());
+    client.create_invoice(&creator, &params);
+}
+
+#[test]
+fn test_verify_customer_commitment() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    let salt = BytesN::from_array(&env, &TEST_SALT);
+    let name = String::from_str(&env, "Test Customer");
+    let email = String::from_str(&env, "test@example.com");
+
+    // Only hashes are stored; the disclosed values and salt prove the match
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.invoice_details.customer_commitment, customer_commitment(&env, "Test Customer", "test@example.com"));
+    assert!(client.verify_customer_commitment(&invoice_id, &CustomerField::Name, &name, &salt));
+    assert!(client.verify_customer_commitment(&invoice_id, &CustomerField::Email, &email, &salt));
+
+    assert!(!client.verify_customer_commitment(&invoice_id, &CustomerField::Name, &email, &salt));
+    assert!(!client.verify_customer_commitment(&invoice_id, &CustomerField::Email, &String::from_str(&env, "other@example.com"), &salt));
+    assert!(!client.verify_customer_commitment(&invoice_id, &CustomerField::Email, &email, &BytesN::from_array(&env, &[2u8; 32])));
+}
+
+#[test]
+fn test_amend_customer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let customer_account = Address::generate(&env);
+    let mut params = ttl_test_params(&env);
+    params.customer = Some(customer_account.clone());
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.acknowledge_invoice(&invoice_id, &customer_account, &params.amount, &params.due_date);
+
+    let commitment = customer_commitment(&env, "Test Customer", "billing@example.com");
+    assert_eq!(client.amend_customer(&invoice_id, &creator, &commitment), 1);
+
+    let invoice = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(invoice.invoice_details.customer_commitment, commitment);
+    assert_eq!(invoice.amendment_count, 1);
+
+    // The acknowledgement was for the old customer details
+    assert!(!client.is_acknowledged(&invoice_id));
+}
+
+#[test]
+#[should_panic(expected = "Amendment has no changes")]
+fn test_amend_customer_unchanged() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.amend_customer(&invoice_id, &creator, &params.customer_commitment);
+}
+
+/// Creator details as invoices carried them before version 4
+fn test_creator_info(env: &Env) -> legacy::CreatorInfo {
+    legacy::CreatorInfo {
+        business_name: String::from_str(env, "Test Business"),
+        location: String::from_str(env, "Test Location"),
+        trust_score: 50,
+        verification_level: VerificationLevel::Verified,
+    }
+}
+
+/// Overwrite a stored invoice with its schema version 2 encoding
+fn store_v2_invoice(env: &Env, contract_id: &Address, invoice: &Invoice) {
+    let old = legacy::InvoiceV2 {
+        id: invoice.id,
+        title: invoice.title.clone(),
+        symbol: invoice.symbol.clone(),
+        status: invoice.status.clone(),
+        creator: invoice.creator.clone(),
+        creator_info: test_creator_info(env),
+        invoice_details: legacy::InvoiceDetailsV2 {
+            amount: invoice.invoice_details.amount,
+            currency: invoice.invoice_details.currency.clone(),
+            due_date: invoice.invoice_details.due_date,
+            customer_name: String::from_str(env, "Test Customer"),
+            customer_email: String::from_str(env, "Test@Example.com"),
+            description: invoice.invoice_details.description.clone(),
+            tax_rate: invoice.invoice_details.tax_rate,
+            early_discount: invoice.invoice_details.early_discount,
+        },
+        financial_terms: invoice.financial_terms.clone(),
+        risk_assessment: invoice.risk_assessment.clone(),
+        timestamps: invoice.timestamps.clone(),
+        proof_hash: invoice.proof_hash.clone(),
+        amendment_count: invoice.amendment_count,
+    };
+    env.as_contract(contract_id, || {
+        env.storage().persistent().set(&DataKey::Invoice(invoice.id), &old);
+    });
+}
+
+#[test]
+fn test_migrate_invoices_replaces_v2_plaintext() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    store_v2_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    // Old invoices read with commitments under the legacy salt
+    let legacy_salt = BytesN::from_array(&env, &legacy::LEGACY_SALT);
+    let email = String::from_str(&env, "Test@Example.com");
+    assert!(client.verify_customer_commitment(&invoice_id, &CustomerField::Email, &email, &legacy_salt));
+    assert_eq!(
+        client.get_invoice(&invoice_id).unwrap().invoice_details.customer_commitment.debtor_id,
+        customer::debtor_id(&env, &legacy_salt, &String::from_str(&env, "test@example.com"))
+    );
+
+    // Rewriting drops the plaintext from current storage
+    let ids = Vec::from_array(&env, [invoice_id]);
+    assert_eq!(client.migrate_invoices(&admin, &ids), 1);
+    env.as_contract(&contract_id, || {
+        let stored: Invoice = env.storage().persistent().get(&DataKey::Invoice(invoice_id)).unwrap();
+        assert_eq!(stored.invoice_details.customer_commitment.encrypted_data, None);
+    });
+    assert!(client.verify_customer_commitment(&invoice_id, &CustomerField::Email, &email, &legacy_salt));
+    assert_eq!(client.migrate_invoices(&admin, &ids), 0);
+}
+
+#[test]
+fn test_migrate_debtor_histories() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    // A history recorded under the plaintext email before the upgrade
+    let email = String::from_str(&env, "test@example.com");
+    env.as_contract(&contract_id, || {
+        let history = DebtorHistory { paid_count: 2, defaulted_count: 1 };
+        env.storage().persistent().set(&DataKey::DebtorHistory(email.clone()), &history);
+    });
+
+    // And one repayment recorded since on an upgraded invoice to the same debtor
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    store_v2_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+    client.mark_as_paid(&invoice_id, &admin);
+
+    let emails = Vec::from_array(&env, [email.clone(), String::from_str(&env, "unknown@example.com")]);
+    assert_eq!(client.migrate_debtor_histories(&admin, &emails), 1);
+
+    let debtor_id = customer::debtor_id(&env, &BytesN::from_array(&env, &legacy::LEGACY_SALT), &email);
+    assert_eq!(client.get_debtor_history(&debtor_id), DebtorHistory { paid_count: 3, defaulted_count: 1 });
+    env.as_contract(&contract_id, || {
+        assert!(!env.storage().persistent().has(&DataKey::DebtorHistory(email.clone())));
+    });
+
+    assert_eq!(client.migrate_debtor_histories(&admin, &emails), 0);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_migrate_debtor_histories_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let emails = Vec::from_array(&env, [String::from_str(&env, "test@example.com")]);
+    client.migrate_debtor_histories(&Address::generate(&env), &emails);
+}
+
+#[test]
+fn test_tax_breakdown_and_financing_rule() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    // 1100 USDC including 10% tax
+    let mut params = ttl_test_params(&env);
+    params.amount = 1100_0000000;
+    params.tax_rate = Some(1000);
+    params.tax_basis = TaxBasis::Gross;
+    let gross_id = client.create_invoice(&Address::generate(&env), &params);
+
+    let breakdown = TaxBreakdown { net_amount: 1000_0000000, tax_amount: 100_0000000, gross_amount: 1100_0000000 };
+    assert_eq!(client.get_tax_basis(&gross_id), TaxBasis::Gross);
+    assert_eq!(client.get_tax_breakdown(&gross_id), breakdown);
+
+    // Gross amounts are financed by default, grade C at 5%
+    assert_eq!(client.get_financing_rule(), FinancingRule::Gross);
+    assert_eq!(client.get_financeable_amount(&gross_id), 1100_0000000);
+    assert_eq!(client.get_invoice(&gross_id).unwrap().financial_terms.offer_amount, 1045_0000000);
+
+    // Holding back the tax prices new invoices on their net amount
+    client.set_financing_rule(&admin, &FinancingRule::Net);
+    assert_eq!(client.get_financeable_amount(&gross_id), 1000_0000000);
+    assert_eq!(client.get_invoice(&gross_id).unwrap().financial_terms.offer_amount, 1045_0000000);
+
+    // The same invoice stated net of tax splits the same way
+    params.amount = 1000_0000000;
+    params.tax_basis = TaxBasis::Net;
+    let net_id = client.create_invoice(&Address::generate(&env), &params);
+    assert_eq!(client.get_tax_basis(&net_id), TaxBasis::Net);
+    assert_eq!(client.get_tax_breakdown(&net_id), breakdown);
+    assert_eq!(client.get_invoice(&net_id).unwrap().financial_terms.offer_amount, 950_0000000);
+}
+
+#[test]
+#[should_panic(expected = "Invalid tax rate")]
+fn test_create_invoice_invalid_tax_rate() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.tax_rate = Some(tax::MAX_TAX_RATE + 1);
+    client.create_invoice(&Address::generate(&env), &params);
+}
+
+#[test]
+#[should_panic(expected = "Invalid tax rate")]
+fn test_amend_invoice_invalid_tax_rate() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+
+    let amendment = InvoiceAmendment {
+        title: None,
+        amount: None,
+        due_date: None,
+        description: None,
+        tax_rate: Some(tax::MAX_TAX_RATE + 1),
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_financing_rule_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    client.initialize(&Address::generate(&env));
+    client.set_financing_rule(&Address::generate(&env), &FinancingRule::Net);
+}
+
+#[test]
+#[should_panic(expected = "Invalid early discount")]
+fn test_create_invoice_invalid_early_discount() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.early_discount = 10001;
+    client.create_invoice(&Address::generate(&env), &params);
+}
+
+/// Monthly schedule for the test invoice, three occurrences at most
+fn monthly_schedule(env: &Env, auto_submit_limit: Option<i128>) -> RecurringSchedule {
+    RecurringSchedule {
+        period_days: 30,
+        end_date: env.ledger().timestamp() + 86400 * 365,
+        max_occurrences: 3,
+        auto_submit_limit,
+    }
+}
+
+fn advance_days(env: &Env, days: u64) {
+    env.ledger().with_mut(|ledger| ledger.timestamp += 86400 * days);
+}
+
+#[test]
+fn test_roll_recurring() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let template_id = client.create_recurring(&creator, &params, &monthly_schedule(&env, Some(1000_0000000)));
+    assert_eq!(client.get_total_invoices(), 0);
+
+    // The first occurrence is issued a period before it falls due
+    let first = client.roll_recurring(&template_id);
+    assert_eq!(first.invoice_id, 1);
+    assert_eq!(first.creator, creator);
+    assert_eq!(first.due_date, params.due_date);
+    assert!(first.auto_submit);
+
+    let invoice = client.get_invoice(&first.invoice_id).unwrap();
+    assert!(invoice.financial_terms.is_recurring);
+    assert_eq!(invoice.invoice_details.amount, params.amount);
+    assert_eq!(invoice.invoice_details.early_discount, params.early_discount);
+
+    advance_days(&env, 30);
+    let second = client.roll_recurring(&template_id);
+    assert_eq!(second.due_date, params.due_date + 86400 * 30);
+
+    advance_days(&env, 30);
+    client.roll_recurring(&template_id);
+
+    let template = client.get_recurring(&template_id).unwrap();
+    assert_eq!(template.occurrences, 3);
+    assert_eq!(template.last_invoice_id, Some(3));
+    assert_eq!(client.get_invoices_by_creator(&creator, &None, &10u32).invoice_ids.len(), 3);
+}
+
+#[test]
+fn test_roll_recurring_skips_missed_periods() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let params = ttl_test_params(&env);
+    let template_id = client.create_recurring(&Address::generate(&env), &params, &monthly_schedule(&env, None));
+
+    // Nobody rolled the first two periods; the next one is due 90 days after creation
+    advance_days(&env, 70);
+    let roll = client.roll_recurring(&template_id);
+    assert_eq!(roll.due_date, params.due_date + 86400 * 60);
+    assert!(!roll.auto_submit);
+    assert_eq!(client.get_recurring(&template_id).unwrap().occurrences, 1);
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_roll_recurring_auto_submit_requires_marketplace() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let schedule = monthly_schedule(&env, Some(1000_0000000));
+    let template_id = client.create_recurring(&Address::generate(&env), &ttl_test_params(&env), &schedule);
+
+    // Rolled directly, the occurrence would be minted without being submitted
+    env.mock_auths(&[]);
+    client.roll_recurring(&template_id);
+}
+
+#[test]
+#[should_panic(expected = "Recurring invoice not due yet")]
+fn test_roll_recurring_not_due() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let template_id = client.create_recurring(&Address::generate(&env), &ttl_test_params(&env), &monthly_schedule(&env, None));
+    client.roll_recurring(&template_id);
+    client.roll_recurring(&template_id);
+}
+
+#[test]
+#[should_panic(expected = "Recurring schedule finished")]
+fn test_roll_recurring_after_max_occurrences() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut schedule = monthly_schedule(&env, None);
+    schedule.max_occurrences = 1;
+    let template_id = client.create_recurring(&Address::generate(&env), &ttl_test_params(&env), &schedule);
+    client.roll_recurring(&template_id);
+
+    advance_days(&env, 30);
+    client.roll_recurring(&template_id);
+}
+
+#[test]
+#[should_panic(expected = "Recurring invoice cancelled")]
+fn test_cancel_recurring() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let template_id = client.create_recurring(&creator, &ttl_test_params(&env), &monthly_schedule(&env, None));
+    client.cancel_recurring(&template_id, &creator);
+    assert!(!client.get_recurring(&template_id).unwrap().active);
+
+    client.roll_recurring(&template_id);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_cancel_recurring_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let template_id = client.create_recurring(&Address::generate(&env), &ttl_test_params(&env), &monthly_schedule(&env, None));
+    client.cancel_recurring(&template_id, &Address::generate(&env));
+}
+
+#[test]
+#[should_panic(expected = "Invalid recurring schedule")]
+fn test_create_recurring_invalid_schedule() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut schedule = monthly_schedule(&env, None);
+    schedule.end_date = env.ledger().timestamp();
+    client.create_recurring(&Address::generate(&env), &ttl_test_params(&env), &schedule);
+}
+
+#[test]
+#[should_panic(expected = "Recurring invoices cannot share a document")]
+fn test_create_recurring_with_proof_hash() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let mut params = ttl_test_params(&env);
+    params.proof_hash = Some(BytesN::from_array(&env, &[7u8; 32]));
+    client.create_recurring(&Address::generate(&env), &params, &monthly_schedule(&env, None));
+}
+
+/// Split a 1000 USDC invoice into 400 and 600, the last due with the invoice
+fn two_installments(env: &Env, params: &InvoiceCreationParams) -> Vec<Installment> {
+    Vec::from_array(env, [
+        Installment { amount: 400_0000000, due_date: params.due_date - 86400 * 15 },
+        Installment { amount: 600_0000000, due_date: params.due_date },
+    ])
+}
+
+#[test]
+fn test_installment_payments() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let creator = Address::generate(&env);
+    let payer = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+
+    let installments = two_installments(&env, &params);
+    client.set_installments(&invoice_id, &creator, &installments);
+    assert_eq!(client.get_installments(&invoice_id), installments);
+    assert_eq!(client.get_next_installment(&invoice_id), installments.get(0));
+
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    // Part of the first installment leaves the rest of it next
+    let status = client.record_payment(&invoice_id, &payer, &250_0000000i128);
+    assert_eq!(status, InvoiceStatus::PartiallyPaid);
+    assert_eq!(client.get_amount_paid(&invoice_id), 250_0000000);
+    assert_eq!(client.get_outstanding_balance(&invoice_id), 750_0000000);
+    assert_eq!(
+        client.get_next_installment(&invoice_id),
+        Some(Installment { amount: 150_0000000, due_date: installments.get_unchecked(0).due_date })
+    );
+    assert_eq!(client.count_invoices_by_status(&InvoiceStatus::PartiallyPaid), 1);
+
+    client.record_payment(&invoice_id, &payer, &150_0000000i128);
+    assert_eq!(client.get_next_installment(&invoice_id), installments.get(1));
+
+    // The last payment completes the invoice and counts towards the creator's history
+    let status = client.record_payment(&invoice_id, &payer, &600_0000000i128);
+    assert_eq!(status, InvoiceStatus::Completed);
+    assert_eq!(client.get_outstanding_balance(&invoice_id), 0);
+    assert_eq!(client.get_next_installment(&invoice_id), None);
+    assert_eq!(client.get_creator_history(&creator).completed_count, 1);
+    assert!(client.get_invoice(&invoice_id).unwrap().timestamps.completed_at.is_some());
+}
+
+#[test]
+fn test_mark_partially_paid_invoice_as_paid() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let creator = Address::generate(&env);
+    let payer = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    // Invoices without a schedule can still be paid in parts
+    client.record_payment(&invoice_id, &payer, &100_0000000i128);
+    client.mark_as_paid(&invoice_id, &admin);
+
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().status, InvoiceStatus::Completed);
+    assert_eq!(client.get_amount_paid(&invoice_id), 1000_0000000);
+}
+
+#[test]
+#[should_panic(expected = "Payment exceeds outstanding balance")]
+fn test_record_payment_above_balance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let creator = Address::generate(&env);
+    let payer = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    client.record_payment(&invoice_id, &payer, &600_0000000i128);
+    client.record_payment(&invoice_id, &payer, &600_0000000i128);
+}
+
+#[test]
+#[should_panic(expected = "Invoice cannot be paid in current status")]
+fn test_record_payment_before_funding() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.record_payment(&invoice_id, &Address::generate(&env), &100_0000000i128);
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_record_payment_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+    client.set_marketplace(&admin, &Address::generate(&env));
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+
+    // Only the payer signs; recording a payment needs the marketplace that collected it
+    let payer = Address::generate(&env);
+    let amount = 100_0000000i128;
+    env.mock_auths(&[MockAuth {
+        address: &payer,
+        invoke: &MockAuthInvoke {
+            contract: &contract_id,
+            fn_name: "record_payment",
+            args: (invoice_id, payer.clone(), amount).into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    client.record_payment(&invoice_id, &payer, &amount);
+}
+
+#[test]
+#[should_panic(expected = "Marketplace not registered")]
+fn test_record_payment_without_marketplace() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = initialize_with_usdc(&env, &client);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.update_status(&invoice_id, &InvoiceStatus::Funded, &admin);
+    client.record_payment(&invoice_id, &Address::generate(&env), &100_0000000i128);
+}
+
+#[test]
+#[should_panic(expected = "Invalid installment schedule")]
+fn test_set_installments_not_adding_up() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+
+    let mut installments = two_installments(&env, &params);
+    installments.set(1, Installment { amount: 500_0000000, due_date: params.due_date });
+    client.set_installments(&invoice_id, &creator, &installments);
+}
+
+#[test]
+fn test_amend_amount_drops_installments() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+    client.set_installments(&invoice_id, &creator, &two_installments(&env, &params));
+
+    // A new title leaves the schedule alone, a new amount does not add up to it
+    let amendment = InvoiceAmendment {
+        title: Some(String::from_str(&env, "Renamed")),
+        amount: None,
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert_eq!(client.get_installments(&invoice_id).len(), 2);
+
+    let amendment = InvoiceAmendment { title: None, amount: Some(1200_0000000i128), ..amendment };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+    assert!(client.get_installments(&invoice_id).is_empty());
+    assert_eq!(
+        client.get_next_installment(&invoice_id),
+        Some(Installment { amount: 1200_0000000, due_date: params.due_date })
+    );
+}
+
+fn certify_esg(env: &Env, client: &InvoiceNFTContractClient, verifier: &Address, invoice_id: u64, expires_at: u64) {
+    client.certify_invoice_esg(
+        verifier,
+        &invoice_id,
+        &String::from_str(env, "GRI"),
+        &80,
+        &BytesN::from_array(env, &[7; 32]),
+        &expires_at,
+    );
+}
+
+#[test]
+fn test_certify_invoice_esg() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_esg_verifier(&admin, &verifier, &true);
+    assert!(client.is_esg_verifier(&verifier));
+
+    let certified_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    let other_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    let expires_at = env.ledger().timestamp() + 86400 * 365;
+    certify_esg(&env, &client, &verifier, certified_id, expires_at);
+
+    let certification = client.get_invoice_esg(&certified_id).unwrap();
+    assert_eq!(certification.verifier, verifier);
+    assert_eq!(certification.score, 80);
+    assert_eq!(certification.expires_at, expires_at);
+    assert!(client.is_esg_certified(&certified_id));
+    assert!(!client.is_esg_certified(&other_id));
+    assert_eq!(client.get_invoice(&certified_id).unwrap().risk_assessment.esg_compliant, Some(true));
+
+    let page = client.get_esg_invoices(&InvoiceStatus::Pending, &None, &10);
+    assert_eq!(page.invoice_ids, Vec::from_array(&env, [certified_id]));
+    assert_eq!(page.next_cursor, None);
+
+    // Removing the verifier withdraws its certifications
+    client.set_esg_verifier(&admin, &verifier, &false);
+    assert!(!client.is_esg_certified(&certified_id));
+    assert!(client.get_esg_invoices(&InvoiceStatus::Pending, &None, &10).invoice_ids.is_empty());
+}
+
+#[test]
+fn test_creator_esg_covers_invoices_until_expiry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_esg_verifier(&admin, &verifier, &true);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    let expires_at = env.ledger().timestamp() + 86400 * 10;
+    client.certify_creator_esg(
+        &verifier,
+        &creator,
+        &String::from_str(&env, "SASB"),
+        &65,
+        &BytesN::from_array(&env, &[9; 32]),
+        &expires_at,
+    );
+
+    assert_eq!(client.get_creator_esg(&creator).unwrap().score, 65);
+    assert!(client.get_invoice_esg(&invoice_id).is_none());
+    assert!(client.is_esg_certified(&invoice_id));
+
+    env.ledger().with_mut(|li| li.timestamp = expires_at);
+    assert!(!client.is_esg_certified(&invoice_id));
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().risk_assessment.esg_compliant, Some(false));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_certify_invoice_esg_unregistered_verifier() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    let expires_at = env.ledger().timestamp() + 86400;
+    certify_esg(&env, &client, &Address::generate(&env), invoice_id, expires_at);
+}
+
+#[test]
+#[should_panic(expected = "Invalid ESG score")]
+fn test_certify_invoice_esg_invalid_score() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_esg_verifier(&admin, &verifier, &true);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    client.certify_invoice_esg(
+        &verifier,
+        &invoice_id,
+        &String::from_str(&env, "GRI"),
+        &(MAX_ESG_SCORE + 1),
+        &BytesN::from_array(&env, &[7; 32]),
+        &(env.ledger().timestamp() + 86400),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Certification already expired")]
+fn test_certify_invoice_esg_expired() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_esg_verifier(&admin, &verifier, &true);
+
+    let invoice_id = client.create_invoice(&Address::generate(&env), &ttl_test_params(&env));
+    certify_esg(&env, &client, &verifier, invoice_id, env.ledger().timestamp());
+}
+
+#[test]
+#[should_panic(expected = "Invoice status is not open")]
+fn test_get_esg_invoices_settled_status() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    client.get_esg_invoices(&InvoiceStatus::Completed, &None, &10);
+}
+
+fn attest(env: &Env, client: &InvoiceNFTContractClient, verifier: &Address, invoice_id: u64, matched_amount: i128, confidence: u32) -> Attestation {
+    let claim = AttestationClaim {
+        source: symbol_short!("stripe"),
+        external_id: String::from_str(env, "in_1001"),
+        matched_amount,
+        confidence,
+        expires_at: env.ledger().timestamp() + 86400 * 90,
+    };
+    client.attest_invoice(verifier, &invoice_id, &claim)
+}
+
+#[test]
+fn test_attestation_integrates_invoice_and_verifies_creator() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let creator = Address::generate(&env);
+    client.update_trust_score(&creator, &80u32, &admin);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+
+    let before = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(before.risk_assessment.verification_method, VerificationMethod::Automated);
+    assert_eq!(client.get_creator_profile(&creator).unwrap().verification_level, VerificationLevel::Pending);
+    assert_eq!(before.risk_assessment.risk_score, RiskGrade::C);
+
+    // A partial match at high confidence verifies nothing
+    attest(&env, &client, &verifier, invoice_id, 600_0000000, 9500);
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Pending);
+
+    // The same verifier and source replace their earlier claim
+    let attestation = attest(&env, &client, &verifier, invoice_id, params.amount, 9500);
+    assert_eq!(client.get_attestations(&invoice_id), Vec::from_array(&env, [attestation]));
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Verified);
+
+    // The verified creator's invoice is re-graded
+    let after = client.get_invoice(&invoice_id).unwrap();
+    assert_eq!(after.risk_assessment.verification_method, VerificationMethod::Integrated);
+    assert_eq!(after.risk_assessment.risk_score, RiskGrade::B);
+    assert!(after.financial_terms.discount_rate < before.financial_terms.discount_rate);
+
+    // Later invoices start out verified
+    let next_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    let next = client.get_invoice(&next_id).unwrap();
+    assert_eq!(next.risk_assessment.verification_method, VerificationMethod::Automated);
+
+    // Removing the verifier withdraws its attestations
+    client.set_verifier(&admin, &verifier, &false);
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Pending);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().risk_assessment.verification_method, VerificationMethod::Automated);
+}
+
+#[test]
+fn test_attestation_expires() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+    let attestation = attest(&env, &client, &verifier, invoice_id, params.amount, 9000);
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Verified);
+
+    env.ledger().with_mut(|li| li.timestamp = attestation.expires_at);
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Pending);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().risk_assessment.verification_method, VerificationMethod::Automated);
+}
+
+#[test]
+fn test_revoke_attestation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+    attest(&env, &client, &verifier, invoice_id, params.amount, 9000);
+
+    client.revoke_attestation(&verifier, &invoice_id, &verifier, &symbol_short!("stripe"));
+
+    // The revoked claim stays on record but no longer counts
+    assert!(client.get_attestations(&invoice_id).get_unchecked(0).revoked);
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Pending);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().risk_assessment.verification_method, VerificationMethod::Automated);
+}
+
+#[test]
+fn test_amend_amount_drops_attested_verification() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let creator = Address::generate(&env);
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&creator, &params);
+    attest(&env, &client, &verifier, invoice_id, params.amount, 9000);
+
+    let amendment = InvoiceAmendment {
+        title: None,
+        amount: Some(1200_0000000i128),
+        due_date: None,
+        description: None,
+        tax_rate: None,
+        early_discount: None,
+    };
+    client.amend_invoice(&invoice_id, &creator, &amendment);
+
+    assert_eq!(client.get_verification_level(&creator), VerificationLevel::Pending);
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().risk_assessment.verification_method, VerificationMethod::Automated);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_attest_invoice_unregistered_verifier() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    initialize_with_usdc(&env, &client);
+
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+    attest(&env, &client, &Address::generate(&env), invoice_id, params.amount, 9000);
+}
+
+#[test]
+#[should_panic(expected = "Invalid confidence")]
+fn test_attest_invoice_invalid_confidence() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+    attest(&env, &client, &verifier, invoice_id, params.amount, 10001);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_revoke_attestation_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let verifier = Address::generate(&env);
+    client.set_verifier(&admin, &verifier, &true);
+
+    let params = ttl_test_params(&env);
+    let invoice_id = client.create_invoice(&Address::generate(&env), &params);
+    attest(&env, &client, &verifier, invoice_id, params.amount, 9000);
+
+    client.revoke_attestation(&Address::generate(&env), &invoice_id, &verifier, &symbol_short!("stripe"));
+}
+
+/// Overwrite a stored invoice with its schema version 3 encoding, which carried the creator's details
+fn store_v3_invoice(env: &Env, contract_id: &Address, invoice: &Invoice) {
+    let old = legacy::InvoiceV3 {
+        id: invoice.id,
+        title: invoice.title.clone(),
+        symbol: invoice.symbol.clone(),
+        status: invoice.status.clone(),
+        creator: invoice.creator.clone(),
+        creator_info: test_creator_info(env),
+        invoice_details: invoice.invoice_details.clone(),
+        financial_terms: invoice.financial_terms.clone(),
+        risk_assessment: invoice.risk_assessment.clone(),
+        timestamps: invoice.timestamps.clone(),
+        proof_hash: invoice.proof_hash.clone(),
+        amendment_count: invoice.amendment_count,
+    };
+    env.as_contract(contract_id, || {
+        env.storage().persistent().set(&DataKey::Invoice(invoice.id), &old);
+    });
+}
+
+#[test]
+fn test_migrate_invoices_sets_up_v3_creator_profile() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register(InvoiceNFTContract, ());
+    let client = InvoiceNFTContractClient::new(&env, &contract_id);
+
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    register_usdc(&env, &client, &admin);
+
+    let creator = Address::generate(&env);
+    let invoice_id = client.create_invoice(&creator, &ttl_test_params(&env));
+    store_v3_invoice(&env, &contract_id, &client.get_invoice(&invoice_id).unwrap());
+
+    // Simulate a creator whose trust score was stored before profiles existed
+    env.as_contract(&contract_id, || {
+        env.storage().persistent().remove(&DataKey::CreatorProfile(creator.clone()));
+        env.storage().persistent().set(&DataKey::TrustScore(creator.clone()), &70u32);
+    });
+    assert_eq!(client.get_invoice(&invoice_id).unwrap().creator, creator);
+    assert_eq!(client.get_creator_profile(&creator), None);
+    assert_eq!(client.get_trust_score(&creator), 70);
+
+    assert_eq!(client.migrate_invoices(&admin, &Vec::from_array(&env, [invoice_id])), 1);
+
+    let info = test_creator_info(&env);
+    assert_eq!(
+        client.get_creator_profile(&creator),
+        Some(CreatorProfile {
+            business_name: info.business_name,
+            location: info.location,
+            trust_score: 70,
+            verification_level: VerificationLevel::Verified,
+        })
+    );
+    env.as_contract(&contract_id, || {
+        assert!(!env.storage().persistent().has(&DataKey::TrustScore(creator.clone())));
+    });
 }
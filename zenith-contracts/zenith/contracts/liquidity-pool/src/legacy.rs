@@ -0,0 +1,41 @@
+//! Funding layouts from earlier schema versions, kept so stored fundings can
+//! still be read and rewritten after an upgrade.
+
+use soroban_sdk::{contracttype, Env, Map, Symbol, TryFromVal, Val};
+
+use crate::{DataKey, InvoiceFunding};
+
+/// Funding as stored before versioning, before defaults were recorded
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceFundingV0 {
+    pub invoice_id: u64,
+    pub funded_amount: i128,
+    pub funding_date: u64,
+    pub expected_return: i128,
+    pub return_date: Option<u64>,
+    pub actual_return: Option<i128>,
+}
+
+/// Read a stored funding in any layout, with whether it was in the unversioned one.
+/// Decoding a struct with a different field count traps instead of failing,
+/// so the layout is told apart from its fields before decoding.
+pub fn read_funding(env: &Env, invoice_id: u64) -> Option<(InvoiceFunding, bool)> {
+    let value: Val = env.storage().persistent().get(&DataKey::InvoiceFunding(invoice_id))?;
+    let fields = Map::<Symbol, Val>::try_from_val(env, &value).expect("Unreadable funding");
+    if fields.contains_key(Symbol::new(env, "defaulted")) {
+        return Some((InvoiceFunding::try_from_val(env, &value).expect("Unreadable funding"), false));
+    }
+
+    let old = InvoiceFundingV0::try_from_val(env, &value).expect("Unreadable funding");
+    let funding = InvoiceFunding {
+        invoice_id: old.invoice_id,
+        funded_amount: old.funded_amount,
+        funding_date: old.funding_date,
+        expected_return: old.expected_return,
+        return_date: old.return_date,
+        actual_return: old.actual_return,
+        defaulted: false,
+    };
+    Some((funding, true))
+}
@@ -1,8 +1,15 @@
 #![no_std]
 use soroban_sdk::{
     contract, contractimpl, contracttype, symbol_short,
-    Address, Env, String, Vec, Map
+    Address, BytesN, Env, IntoVal, Val, Vec
 };
+use zenith_common::math::{apply_bps, mul_div, Rounding};
+use zenith_common::ttl::{
+    INSTANCE_BUMP_AMOUNT, INSTANCE_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use zenith_common::{pricing, time};
+
+mod legacy;
 
 #[derive(Clone)]
 #[contracttype]
@@ -15,9 +22,14 @@ pub enum DataKey {
     PoolSettings,
     InvoiceFunding(u64), // invoice_id
     YieldReserve,
+    Marketplace,
+    TotalLosses,
+    SchemaVersion,
+    Asset,
+    Repaid(u64), // invoice_id, return received so far from installments
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 #[contracttype]
 pub enum StakeStatus {
     Active,
@@ -26,6 +38,13 @@ pub enum StakeStatus {
     EarlyWithdrawn,
 }
 
+impl StakeStatus {
+    /// Whether the position still holds liquidity; closed positions are left to expire
+    pub fn is_live(&self) -> bool {
+        *self == StakeStatus::Active
+    }
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub struct PoolSettings {
@@ -61,6 +80,15 @@ pub struct InvoiceFunding {
     pub expected_return: i128,
     pub return_date: Option<u64>,
     pub actual_return: Option<i128>,
+    pub defaulted: bool,
+}
+
+/// A page of stake positions plus the cursor to pass for the next page
+#[derive(Clone)]
+#[contracttype]
+pub struct StakePage {
+    pub positions: Vec<StakePosition>,
+    pub next_cursor: Option<u64>,
 }
 
 #[derive(Clone)]
@@ -71,20 +99,32 @@ pub struct PoolStats {
     pub total_funded_invoices: u64,
     pub total_yield_distributed: i128,
     pub pool_utilization: u32, // percentage
+    pub total_losses: i128,
 }
 
+/// Maximum number of positions returned in a single page
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Storage layout version written by this build; bump it alongside a new `migrate` step
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[contract]
 pub struct LiquidityPoolContract;
 
 #[contractimpl]
 impl LiquidityPoolContract {
-    /// Initialize the liquidity pool contract
+    /// Initialize the liquidity pool contract for a single token
     pub fn initialize(
         env: Env,
         admin: Address,
+        asset: Address,
         base_apr: u32,
         min_stake_amount: i128,
     ) {
+        if env.storage().instance().has(&DataKey::PoolSettings) {
+            panic!("Already initialized");
+        }
+
         admin.require_auth();
 
         let settings = PoolSettings {
@@ -97,11 +137,121 @@ impl LiquidityPoolContract {
         };
 
         env.storage().instance().set(&DataKey::PoolSettings, &settings);
+        env.storage().instance().set(&DataKey::Asset, &asset);
         env.storage().instance().set(&DataKey::TotalLiquidity, &0i128);
         env.storage().instance().set(&DataKey::TotalStakers, &0u32);
         env.storage().instance().set(&DataKey::YieldReserve, &0i128);
+        env.storage().instance().set(&DataKey::TotalLosses, &0i128);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Self::extend_instance_ttl(&env);
+
+        env.events().publish((symbol_short!("init"),), (admin, asset, base_apr));
+    }
+
+    /// Register the marketplace contract allowed to fund invoices and report defaults (admin only)
+    pub fn set_marketplace(env: Env, admin: Address, marketplace: Address) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        env.storage().instance().set(&DataKey::Marketplace, &marketplace);
+
+        env.events().publish((symbol_short!("mkt"),), (marketplace,));
+    }
+
+    /// Replace the contract code, keeping its storage (admin only)
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish((symbol_short!("upgrade"),), new_wasm_hash);
+    }
+
+    /// Bring stored data up to the layout this build expects (admin only)
+    pub fn migrate(env: Env, admin: Address) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        let from_version = Self::get_schema_version(env.clone());
+        if from_version > SCHEMA_VERSION {
+            panic!("Unsupported schema version");
+        }
+
+        // Each step rewrites the layout left by the previous version. Version 1
+        // records defaults on fundings and tracks realized losses; fundings are
+        // converted as they are read and can be rewritten with `migrate_fundings`.
+        if from_version == 0 && !env.storage().instance().has(&DataKey::TotalLosses) {
+            env.storage().instance().set(&DataKey::TotalLosses, &0i128);
+        }
+
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        env.events().publish((symbol_short!("migrate"),), (from_version, SCHEMA_VERSION));
+
+        SCHEMA_VERSION
+    }
+
+    /// Rewrite fundings stored in an older layout (admin only).
+    /// Returns how many were rewritten; missing or current fundings are skipped.
+    pub fn migrate_fundings(env: Env, admin: Address, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
 
-        env.events().publish((symbol_short!("init"),), (admin, base_apr));
+        let mut migrated = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            if let Some((funding, true)) = legacy::read_funding(&env, invoice_id) {
+                // Write in place so settled fundings keep their remaining TTL
+                env.storage().persistent().set(&DataKey::InvoiceFunding(invoice_id), &funding);
+                migrated += 1;
+            }
+        }
+
+        migrated
+    }
+
+    /// Get the storage layout version (0 for storage written before versioning)
+    pub fn get_schema_version(env: Env) -> u32 {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::SchemaVersion)
+            .unwrap_or(0)
     }
 
     /// Stake USDC in the liquidity pool
@@ -111,6 +261,8 @@ impl LiquidityPoolContract {
         amount: i128,
         duration_days: u64,
     ) -> u64 {
+        Self::extend_instance_ttl(&env);
+
         user.require_auth();
 
         let settings: PoolSettings = env.storage().instance()
@@ -127,17 +279,18 @@ impl LiquidityPoolContract {
         }
 
         let current_time = env.ledger().timestamp();
-        let maturity_date = current_time + (duration_days * 86400); // Convert days to seconds
+        let maturity_date = current_time + time::days_to_seconds(duration_days);
 
         // Get user's stake counter
-        let mut stake_counter: u64 = env.storage().persistent()
-            .get(&DataKey::StakeCounter(user.clone()))
-            .unwrap_or(0);
+        let mut stake_counter = Self::load_stake_counter(&env, &user);
         stake_counter += 1;
 
         // Calculate APR based on duration (longer duration = higher APR)
-        let duration_bonus = (duration_days - settings.min_stake_duration) * 5; // 0.05% per extra day
-        let effective_apr = settings.base_apr + duration_bonus as u32;
+        let duration_bonus = (duration_days - settings.min_stake_duration)
+            .checked_mul(5) // 0.05% per extra day
+            .and_then(|bonus| u32::try_from(bonus).ok())
+            .expect("Arithmetic overflow");
+        let effective_apr = settings.base_apr.checked_add(duration_bonus).expect("Arithmetic overflow");
 
         // Create stake position
         let position = StakePosition {
@@ -154,17 +307,12 @@ impl LiquidityPoolContract {
         };
 
         // Store the stake position
-        env.storage().persistent().set(
-            &DataKey::StakePosition(user.clone(), stake_counter),
-            &position
-        );
-        env.storage().persistent().set(&DataKey::StakeCounter(user.clone()), &stake_counter);
+        Self::save_position(&env, &position);
+        Self::save_user_entry(&env, &DataKey::StakeCounter(user.clone()), &stake_counter);
 
         // Update user's total stake
-        let current_user_stake: i128 = env.storage().persistent()
-            .get(&DataKey::UserStake(user.clone()))
-            .unwrap_or(0);
-        env.storage().persistent().set(&DataKey::UserStake(user.clone()), &(current_user_stake + amount));
+        let current_user_stake = Self::load_user_stake(&env, &user);
+        Self::save_user_entry(&env, &DataKey::UserStake(user.clone()), &(current_user_stake + amount));
 
         // Update total liquidity
         let total_liquidity: i128 = env.storage().instance()
@@ -195,11 +343,11 @@ impl LiquidityPoolContract {
         user: Address,
         position_id: u64,
     ) -> (i128, i128) { // (principal, yield)
+        Self::extend_instance_ttl(&env);
+
         user.require_auth();
 
-        let mut position: StakePosition = env.storage().persistent()
-            .get(&DataKey::StakePosition(user.clone(), position_id))
-            .expect("Stake position not found");
+        let mut position = Self::load_position(&env, &user, position_id);
 
         if position.status != StakeStatus::Active {
             panic!("Stake position not active");
@@ -215,11 +363,10 @@ impl LiquidityPoolContract {
         position.accrued_yield += yield_amount;
 
         let is_early_withdrawal = current_time < position.maturity_date;
-        let mut penalty = 0i128;
         let mut final_principal = position.amount;
 
         if is_early_withdrawal {
-            penalty = position.amount * settings.early_withdrawal_penalty as i128 / 10000;
+            let penalty = apply_bps(position.amount, settings.early_withdrawal_penalty, Rounding::Down);
             final_principal = position.amount - penalty;
             position.status = StakeStatus::EarlyWithdrawn;
         } else {
@@ -227,16 +374,11 @@ impl LiquidityPoolContract {
         }
 
         // Update position
-        env.storage().persistent().set(
-            &DataKey::StakePosition(user.clone(), position_id),
-            &position
-        );
+        Self::save_position(&env, &position);
 
         // Update user's total stake
-        let current_user_stake: i128 = env.storage().persistent()
-            .get(&DataKey::UserStake(user.clone()))
-            .unwrap_or(0);
-        env.storage().persistent().set(&DataKey::UserStake(user.clone()), &(current_user_stake - position.amount));
+        let current_user_stake = Self::load_user_stake(&env, &user);
+        Self::save_user_entry(&env, &DataKey::UserStake(user.clone()), &(current_user_stake - position.amount));
 
         // Update total liquidity
         let total_liquidity: i128 = env.storage().instance()
@@ -261,6 +403,8 @@ impl LiquidityPoolContract {
         amount: i128,
         expected_return: i128,
     ) -> bool {
+        Self::extend_instance_ttl(&env);
+
         caller.require_auth();
 
         let settings: PoolSettings = env.storage().instance()
@@ -268,7 +412,7 @@ impl LiquidityPoolContract {
             .expect("Pool not initialized");
 
         // Only admin or marketplace contract can fund invoices
-        if caller != settings.admin {
+        if !Self::is_operator(&env, &caller, &settings) {
             panic!("Unauthorized to fund invoices");
         }
 
@@ -288,9 +432,10 @@ impl LiquidityPoolContract {
             expected_return,
             return_date: None,
             actual_return: None,
+            defaulted: false,
         };
 
-        env.storage().persistent().set(&DataKey::InvoiceFunding(invoice_id), &funding);
+        Self::save_funding(&env, &funding);
 
         // Update total liquidity (reduce available liquidity)
         env.storage().instance().set(&DataKey::TotalLiquidity, &(total_liquidity - amount));
@@ -310,6 +455,8 @@ impl LiquidityPoolContract {
         invoice_id: u64,
         repayment_amount: i128,
     ) -> bool {
+        Self::extend_instance_ttl(&env);
+
         caller.require_auth();
 
         let settings: PoolSettings = env.storage().instance()
@@ -317,19 +464,17 @@ impl LiquidityPoolContract {
             .expect("Pool not initialized");
 
         // Only admin or marketplace contract can process repayments
-        if caller != settings.admin {
+        if !Self::is_operator(&env, &caller, &settings) {
             panic!("Unauthorized to process repayments");
         }
 
-        let mut funding: InvoiceFunding = env.storage().persistent()
-            .get(&DataKey::InvoiceFunding(invoice_id))
-            .expect("Invoice funding not found");
+        let mut funding = Self::load_funding(&env, invoice_id);
 
         // Update funding record
         funding.return_date = Some(env.ledger().timestamp());
         funding.actual_return = Some(repayment_amount);
 
-        env.storage().persistent().set(&DataKey::InvoiceFunding(invoice_id), &funding);
+        Self::save_funding(&env, &funding);
 
         // Add repayment back to liquidity pool
         let total_liquidity: i128 = env.storage().instance()
@@ -354,24 +499,321 @@ impl LiquidityPoolContract {
         true
     }
 
-    /// Get user's stake positions
-    pub fn get_user_stakes(env: Env, user: Address) -> Vec<StakePosition> {
-        let stake_counter: u64 = env.storage().persistent()
-            .get(&DataKey::StakeCounter(user.clone()))
+    /// Take the pool's share of an installment paid on a funded invoice, returning it.
+    /// The pool receives its expected return in proportion to how much of the invoice has
+    /// been paid, so `invoice_paid` is the cumulative total; installments repay principal
+    /// before yield, and the funding settles once the invoice is paid in full.
+    pub fn process_installment(
+        env: Env,
+        caller: Address,
+        invoice_id: u64,
+        invoice_paid: i128,
+        invoice_amount: i128,
+    ) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        caller.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        // Only admin or marketplace contract can process repayments
+        if !Self::is_operator(&env, &caller, &settings) {
+            panic!("Unauthorized to process repayments");
+        }
+
+        if invoice_paid <= 0 || invoice_paid > invoice_amount {
+            panic!("Invalid installment");
+        }
+
+        let mut funding = Self::load_funding(&env, invoice_id);
+
+        if funding.return_date.is_some() {
+            panic!("Invoice funding already settled");
+        }
+
+        let repaid_key = DataKey::Repaid(invoice_id);
+        let repaid: i128 = env.storage().persistent().get(&repaid_key).unwrap_or(0);
+        let due = mul_div(funding.expected_return, invoice_paid, invoice_amount, Rounding::Down);
+        let share = (due - repaid).max(0);
+        let total_repaid = repaid + share;
+
+        if invoice_paid == invoice_amount {
+            funding.return_date = Some(env.ledger().timestamp());
+            funding.actual_return = Some(total_repaid);
+            Self::save_funding(&env, &funding);
+            env.storage().persistent().remove(&repaid_key);
+        } else {
+            env.storage().persistent().set(&repaid_key, &total_repaid);
+            Self::extend_persistent_ttl(&env, &repaid_key);
+        }
+
+        // Add the share back to the liquidity pool
+        let total_liquidity: i128 = env.storage().instance()
+            .get(&DataKey::TotalLiquidity)
             .unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalLiquidity, &(total_liquidity + share));
 
+        // Whatever is received beyond the funded principal is yield
+        let yield_earned = (total_repaid - funding.funded_amount).max(0) - (repaid - funding.funded_amount).max(0);
+        if yield_earned > 0 {
+            let yield_reserve: i128 = env.storage().instance()
+                .get(&DataKey::YieldReserve)
+                .unwrap_or(0);
+            env.storage().instance().set(&DataKey::YieldReserve, &(yield_reserve + yield_earned));
+        }
+
+        env.events().publish(
+            (symbol_short!("instalmnt"),),
+            (invoice_id, share, total_repaid, yield_earned)
+        );
+
+        share
+    }
+
+    /// Write off a defaulted invoice, absorbing the loss from the yield reserve first
+    pub fn record_default(
+        env: Env,
+        caller: Address,
+        invoice_id: u64,
+    ) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        caller.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        // Only admin or marketplace contract can report defaults
+        if !Self::is_operator(&env, &caller, &settings) {
+            panic!("Unauthorized to record defaults");
+        }
+
+        let mut funding = Self::load_funding(&env, invoice_id);
+
+        if funding.return_date.is_some() {
+            panic!("Invoice funding already settled");
+        }
+
+        // Installments already received reduce the principal lost
+        let repaid_key = DataKey::Repaid(invoice_id);
+        let repaid: i128 = env.storage().persistent().get(&repaid_key).unwrap_or(0);
+        env.storage().persistent().remove(&repaid_key);
+        let unrecovered = funding.funded_amount - repaid.min(funding.funded_amount);
+
+        // Update funding record
+        funding.return_date = Some(env.ledger().timestamp());
+        funding.actual_return = Some(repaid);
+        funding.defaulted = true;
+
+        Self::save_funding(&env, &funding);
+
+        // Cover as much of the loss as possible from accumulated yield
+        let yield_reserve: i128 = env.storage().instance()
+            .get(&DataKey::YieldReserve)
+            .unwrap_or(0);
+        let covered = yield_reserve.clamp(0, unrecovered);
+        env.storage().instance().set(&DataKey::YieldReserve, &(yield_reserve - covered));
+
+        // The uncovered remainder is a realized loss for stakers
+        let loss = unrecovered - covered;
+        let total_losses: i128 = env.storage().instance()
+            .get(&DataKey::TotalLosses)
+            .unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalLosses, &(total_losses + loss));
+
+        env.events().publish(
+            (symbol_short!("default"),),
+            (invoice_id, funding.funded_amount, covered, loss)
+        );
+
+        loss
+    }
+
+    /// Credit late fee income for an invoice to the yield reserve
+    pub fn record_late_fee(
+        env: Env,
+        caller: Address,
+        invoice_id: u64,
+        amount: i128,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        caller.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        // Only admin or marketplace contract can credit late fees
+        if !Self::is_operator(&env, &caller, &settings) {
+            panic!("Unauthorized to record late fees");
+        }
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let yield_reserve: i128 = env.storage().instance()
+            .get(&DataKey::YieldReserve)
+            .unwrap_or(0);
+        env.storage().instance().set(&DataKey::YieldReserve, &(yield_reserve + amount));
+
+        env.events().publish(
+            (symbol_short!("latefee"),),
+            (invoice_id, amount)
+        );
+    }
+
+    /// Return funds recovered on a defaulted invoice, such as seized collateral, to the pool.
+    /// Recoveries offset the realized loss before adding to the yield reserve.
+    pub fn record_recovery(
+        env: Env,
+        caller: Address,
+        invoice_id: u64,
+        amount: i128,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        caller.require_auth();
+
+        let settings: PoolSettings = env.storage().instance()
+            .get(&DataKey::PoolSettings)
+            .expect("Pool not initialized");
+
+        // Only admin or marketplace contract can credit recoveries
+        if !Self::is_operator(&env, &caller, &settings) {
+            panic!("Unauthorized to record recoveries");
+        }
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let funding = Self::load_funding(&env, invoice_id);
+        if !funding.defaulted {
+            panic!("Invoice has not defaulted");
+        }
+
+        // The recovered funds are liquidity again
+        let total_liquidity: i128 = env.storage().instance()
+            .get(&DataKey::TotalLiquidity)
+            .unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalLiquidity, &(total_liquidity + amount));
+
+        let total_losses: i128 = env.storage().instance()
+            .get(&DataKey::TotalLosses)
+            .unwrap_or(0);
+        let offset = amount.min(total_losses);
+        env.storage().instance().set(&DataKey::TotalLosses, &(total_losses - offset));
+
+        if amount > offset {
+            let yield_reserve: i128 = env.storage().instance()
+                .get(&DataKey::YieldReserve)
+                .unwrap_or(0);
+            env.storage().instance().set(&DataKey::YieldReserve, &(yield_reserve + amount - offset));
+        }
+
+        env.events().publish(
+            (symbol_short!("recovery"),),
+            (invoice_id, amount, offset)
+        );
+    }
+
+    /// Get funding record for an invoice
+    pub fn get_invoice_funding(env: Env, invoice_id: u64) -> Option<InvoiceFunding> {
+        Self::extend_instance_ttl(&env);
+
+        let key = DataKey::InvoiceFunding(invoice_id);
+        let funding = legacy::read_funding(&env, invoice_id).map(|(funding, _)| funding);
+        if let Some(funding) = &funding {
+            if funding.return_date.is_none() {
+                Self::extend_persistent_ttl(&env, &key);
+            }
+        }
+        funding
+    }
+
+    /// Extend the TTL of outstanding invoice fundings.
+    /// Permissionless so keepers can keep long-running fundings from being archived.
+    pub fn bump(env: Env, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        let mut bumped = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            let key = DataKey::InvoiceFunding(invoice_id);
+            if let Some((funding, _)) = legacy::read_funding(&env, invoice_id) {
+                if funding.return_date.is_none() {
+                    Self::extend_persistent_ttl(&env, &key);
+                    let repaid_key = DataKey::Repaid(invoice_id);
+                    if env.storage().persistent().has(&repaid_key) {
+                        Self::extend_persistent_ttl(&env, &repaid_key);
+                    }
+                    bumped += 1;
+                }
+            }
+        }
+
+        bumped
+    }
+
+    /// Extend the TTL of a user's active stake positions and stake totals.
+    /// Permissionless so keepers can keep long-term stakes from being archived.
+    pub fn bump_stakes(env: Env, user: Address, position_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_stake_counter(&env, &user);
+        Self::load_user_stake(&env, &user);
+
+        let mut bumped = 0u32;
+        for position_id in position_ids.iter() {
+            let key = DataKey::StakePosition(user.clone(), position_id);
+            if let Some(position) = env.storage().persistent().get::<DataKey, StakePosition>(&key) {
+                if position.status.is_live() {
+                    Self::extend_persistent_ttl(&env, &key);
+                    bumped += 1;
+                }
+            }
+        }
+
+        bumped
+    }
+
+    /// Get a page of user's stake positions, starting after position id `cursor`
+    pub fn get_user_stakes(env: Env, user: Address, cursor: Option<u64>, limit: u32) -> StakePage {
+        Self::extend_instance_ttl(&env);
+
+        if limit == 0 || limit > MAX_PAGE_SIZE {
+            panic!("Invalid page limit");
+        }
+
+        let stake_counter = Self::load_stake_counter(&env, &user);
+
+        // Position ids are assigned sequentially per user, so the counter bounds the scan
+        let mut position_id = cursor.unwrap_or(0);
         let mut positions = Vec::new(&env);
-        for i in 1..=stake_counter {
-            if let Some(position) = env.storage().persistent()
-                .get::<DataKey, StakePosition>(&DataKey::StakePosition(user.clone(), i)) {
+        while position_id < stake_counter && positions.len() < limit {
+            position_id += 1;
+            let key = DataKey::StakePosition(user.clone(), position_id);
+            if let Some(position) = env.storage().persistent().get::<DataKey, StakePosition>(&key) {
+                if position.status.is_live() {
+                    Self::extend_persistent_ttl(&env, &key);
+                }
                 positions.push_back(position);
             }
         }
-        positions
+
+        let next_cursor = if position_id < stake_counter { Some(position_id) } else { None };
+
+        StakePage { positions, next_cursor }
     }
 
     /// Get pool statistics
     pub fn get_pool_stats(env: Env) -> PoolStats {
+        Self::extend_instance_ttl(&env);
+
         let total_liquidity: i128 = env.storage().instance()
             .get(&DataKey::TotalLiquidity)
             .unwrap_or(0);
@@ -381,6 +823,9 @@ impl LiquidityPoolContract {
         let total_yield_distributed: i128 = env.storage().instance()
             .get(&DataKey::YieldReserve)
             .unwrap_or(0);
+        let total_losses: i128 = env.storage().instance()
+            .get(&DataKey::TotalLosses)
+            .unwrap_or(0);
 
         PoolStats {
             total_liquidity,
@@ -388,18 +833,30 @@ impl LiquidityPoolContract {
             total_funded_invoices: 0, // Would need to track this separately
             total_yield_distributed,
             pool_utilization: 0, // Would calculate based on funded vs available
+            total_losses,
         }
     }
 
     /// Get user's total stake amount
     pub fn get_user_stake(env: Env, user: Address) -> i128 {
-        env.storage().persistent()
-            .get(&DataKey::UserStake(user))
-            .unwrap_or(0)
+        Self::extend_instance_ttl(&env);
+
+        Self::load_user_stake(&env, &user)
+    }
+
+    /// Get the token contract this pool holds
+    pub fn get_asset(env: Env) -> Address {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::Asset)
+            .expect("Pool not initialized")
     }
 
     /// Get available liquidity for funding
     pub fn get_available_liquidity(env: Env) -> i128 {
+        Self::extend_instance_ttl(&env);
+
         env.storage().instance()
             .get(&DataKey::TotalLiquidity)
             .unwrap_or(0)
@@ -413,6 +870,8 @@ impl LiquidityPoolContract {
         early_withdrawal_penalty: Option<u32>,
         min_stake_amount: Option<i128>,
     ) {
+        Self::extend_instance_ttl(&env);
+
         admin.require_auth();
 
         let mut settings: PoolSettings = env.storage().instance()
@@ -438,17 +897,103 @@ impl LiquidityPoolContract {
         env.events().publish((symbol_short!("update"),), (admin,));
     }
 
+    fn extend_instance_ttl(env: &Env) {
+        env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    fn extend_persistent_ttl(env: &Env, key: &DataKey) {
+        env.storage().persistent().extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+    }
+
+    /// Load a stake position, extending its TTL while it is still active
+    fn load_position(env: &Env, user: &Address, position_id: u64) -> StakePosition {
+        let key = DataKey::StakePosition(user.clone(), position_id);
+        let position: StakePosition = env.storage().persistent()
+            .get(&key)
+            .expect("Stake position not found");
+        if position.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+        position
+    }
+
+    /// Store a stake position; closed positions keep their remaining TTL and are left to expire
+    fn save_position(env: &Env, position: &StakePosition) {
+        let key = DataKey::StakePosition(position.user.clone(), position.id);
+        env.storage().persistent().set(&key, position);
+        if position.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+    }
+
+    /// Load an invoice funding, extending its TTL until it is settled
+    fn load_funding(env: &Env, invoice_id: u64) -> InvoiceFunding {
+        let key = DataKey::InvoiceFunding(invoice_id);
+        let (funding, _) = legacy::read_funding(env, invoice_id).expect("Invoice funding not found");
+        if funding.return_date.is_none() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+        funding
+    }
+
+    /// Store an invoice funding; settled fundings keep their remaining TTL and are left to expire
+    fn save_funding(env: &Env, funding: &InvoiceFunding) {
+        let key = DataKey::InvoiceFunding(funding.invoice_id);
+        env.storage().persistent().set(&key, funding);
+        if funding.return_date.is_none() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+    }
+
+    /// Per-user totals back every position, so they are always kept alive
+    fn load_stake_counter(env: &Env, user: &Address) -> u64 {
+        let key = DataKey::StakeCounter(user.clone());
+        match env.storage().persistent().get(&key) {
+            Some(counter) => {
+                Self::extend_persistent_ttl(env, &key);
+                counter
+            }
+            None => 0,
+        }
+    }
+
+    fn load_user_stake(env: &Env, user: &Address) -> i128 {
+        let key = DataKey::UserStake(user.clone());
+        match env.storage().persistent().get(&key) {
+            Some(stake) => {
+                Self::extend_persistent_ttl(env, &key);
+                stake
+            }
+            None => 0,
+        }
+    }
+
+    fn save_user_entry<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+        env.storage().persistent().set(key, value);
+        Self::extend_persistent_ttl(env, key);
+    }
+
+    /// Check whether caller is the pool admin or the registered marketplace
+    fn is_operator(env: &Env, caller: &Address, settings: &PoolSettings) -> bool {
+        if *caller == settings.admin {
+            return true;
+        }
+
+        let marketplace: Option<Address> = env.storage().instance().get(&DataKey::Marketplace);
+        marketplace == Some(caller.clone())
+    }
+
     /// Calculate yield for a stake position
     fn calculate_yield(position: &StakePosition, current_time: u64) -> i128 {
         let time_elapsed = current_time - position.last_yield_calculation;
-        let annual_yield = position.amount * position.apr as i128 / 10000; // APR in basis points
-        let seconds_per_year = 365 * 24 * 60 * 60u64;
-        
-        (annual_yield * time_elapsed as i128) / seconds_per_year as i128
+
+        pricing::accrued_yield(position.amount, position.apr, time_elapsed)
     }
 
     /// Distribute yield to all active stakers (admin function)
     pub fn distribute_yield(env: Env, admin: Address) {
+        Self::extend_instance_ttl(&env);
+
         admin.require_auth();
 
         let settings: PoolSettings = env.storage().instance()
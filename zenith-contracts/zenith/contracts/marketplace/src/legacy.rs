@@ -0,0 +1,166 @@
+//! Settings, approval and payment record layouts from earlier schema versions,
+//! kept so stored records can still be read and rewritten after an upgrade.
+
+use soroban_sdk::{contracttype, Address, Env, Map, String, Symbol, TryFromVal, Val};
+
+use crate::{DataKey, InvoiceApproval, InvoiceStatus, MarketplaceSettings, PaymentMethod, PaymentRecord, RiskGrade};
+
+/// Settings as stored before versioning, without the overdue, default and late fee terms
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct MarketplaceSettingsV0 {
+    pub admin: Address,
+    pub platform_fee: u32,
+    pub max_funding_ratio: u32,
+    pub min_invoice_amount: i128,
+    pub max_invoice_amount: i128,
+    pub auto_approval_threshold: i128,
+    pub early_payment_bonus: u32,
+    pub min_days_until_due: u64,
+    pub max_days_until_due: u64,
+}
+
+/// Read the stored settings only if they are still in the unversioned layout,
+/// filling the terms added since with the defaults `initialize` uses
+pub fn read_v0_settings(env: &Env) -> Option<MarketplaceSettings> {
+    let value: Val = env.storage().instance().get(&DataKey::MarketplaceSettings)?;
+    let fields = Map::<Symbol, Val>::try_from_val(env, &value).expect("Unreadable settings");
+    if fields.contains_key(Symbol::new(env, "treasury")) {
+        return None;
+    }
+    let old = MarketplaceSettingsV0::try_from_val(env, &value).expect("Unreadable settings");
+
+    Some(MarketplaceSettings {
+        treasury: old.admin.clone(),
+        admin: old.admin,
+        platform_fee: old.platform_fee,
+        max_funding_ratio: old.max_funding_ratio,
+        min_invoice_amount: old.min_invoice_amount,
+        max_invoice_amount: old.max_invoice_amount,
+        auto_approval_threshold: old.auto_approval_threshold,
+        // The bonus is now paid out of the platform fee, so it cannot exceed it
+        early_payment_bonus: old.early_payment_bonus.min(old.platform_fee),
+        min_days_until_due: old.min_days_until_due,
+        max_days_until_due: old.max_days_until_due,
+        grace_period_days: 5,
+        default_period_days: 30,
+        default_trust_penalty: 20,
+        late_fee_rate: 10,
+        late_fee_cap: 1000,
+        late_fee_pool_share: 8000,
+    })
+}
+
+/// Approval as stored by schema version 1, with the risk grade as a string
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct InvoiceApprovalV1 {
+    pub invoice_id: u64,
+    pub creator: Address,
+    pub amount: i128,
+    pub due_date: u64,
+    pub status: InvoiceStatus,
+    pub approved_amount: Option<i128>,
+    pub approval_date: Option<u64>,
+    pub funding_date: Option<u64>,
+    pub completion_date: Option<u64>,
+    pub risk_score: String, // "A", "B", "C", "D"
+    pub discount_rate: u32,
+    pub funded_amount: Option<i128>,
+}
+
+/// Read an approval only if it is still stored in the version 1 layout
+pub fn read_v1_approval(env: &Env, invoice_id: u64) -> Option<InvoiceApproval> {
+    let value: Val = env.storage().persistent().get(&DataKey::InvoiceApproval(invoice_id))?;
+    if InvoiceApproval::try_from_val(env, &value).is_ok() {
+        return None;
+    }
+    Some(v1_approval_from_val(env, &value))
+}
+
+/// Convert a stored version 1 approval to the current layout
+pub fn v1_approval_from_val(env: &Env, value: &Val) -> InvoiceApproval {
+    let old = InvoiceApprovalV1::try_from_val(env, value).expect("Unreadable approval");
+
+    InvoiceApproval {
+        invoice_id: old.invoice_id,
+        creator: old.creator,
+        amount: old.amount,
+        due_date: old.due_date,
+        status: old.status,
+        approved_amount: old.approved_amount,
+        approval_date: old.approval_date,
+        funding_date: old.funding_date,
+        completion_date: old.completion_date,
+        risk_score: RiskGrade::from_label(&old.risk_score).expect("Invalid risk score"),
+        discount_rate: old.discount_rate,
+        funded_amount: old.funded_amount,
+    }
+}
+
+/// Payment record as stored before schema version 4, without the early discount
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PaymentRecordV3 {
+    pub invoice_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub payment_method: PaymentMethod,
+    pub payment_date: u64,
+    pub is_early_payment: bool,
+    pub cashback_amount: i128,
+    pub platform_fee: i128,
+    pub late_fee: i128,
+}
+
+/// Payment record as stored before versioning, without late fees
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct PaymentRecordV0 {
+    pub invoice_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub payment_method: PaymentMethod,
+    pub payment_date: u64,
+    pub is_early_payment: bool,
+    pub cashback_amount: i128,
+    pub platform_fee: i128,
+}
+
+/// Convert a stored payment record in any layout to the current one.
+/// Decoding a struct with a different field count traps instead of failing,
+/// so the layout is told apart from its fields before decoding.
+pub fn payment_record_from_val(env: &Env, value: &Val) -> PaymentRecord {
+    let fields = Map::<Symbol, Val>::try_from_val(env, value).expect("Unreadable payment record");
+    if !fields.contains_key(Symbol::new(env, "late_fee")) {
+        let old = PaymentRecordV0::try_from_val(env, value).expect("Unreadable payment record");
+        return PaymentRecord {
+            invoice_id: old.invoice_id,
+            payer: old.payer,
+            amount: old.amount,
+            payment_method: old.payment_method,
+            payment_date: old.payment_date,
+            is_early_payment: old.is_early_payment,
+            early_discount: 0,
+            cashback_amount: old.cashback_amount,
+            platform_fee: old.platform_fee,
+            late_fee: 0,
+        };
+    }
+    if !fields.contains_key(Symbol::new(env, "early_discount")) {
+        let old = PaymentRecordV3::try_from_val(env, value).expect("Unreadable payment record");
+        return PaymentRecord {
+            invoice_id: old.invoice_id,
+            payer: old.payer,
+            amount: old.amount,
+            payment_method: old.payment_method,
+            payment_date: old.payment_date,
+            is_early_payment: old.is_early_payment,
+            early_discount: 0,
+            cashback_amount: old.cashback_amount,
+            platform_fee: old.platform_fee,
+            late_fee: old.late_fee,
+        };
+    }
+    PaymentRecord::try_from_val(env, value).expect("Unreadable payment record")
+}
@@ -1,36 +1,57 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short,
-    Address, Env, String, Vec
+    contract, contractclient, contractimpl, contracttype, symbol_short, token,
+    Address, BytesN, Env, String, TryFromVal, Val, Vec
 };
 
+use zenith_common::math::{apply_bps, apply_percent, is_valid_bps, Rounding};
+use zenith_common::ttl::{
+    INSTANCE_BUMP_AMOUNT, INSTANCE_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD,
+};
+use zenith_common::{collateral, index, installment, pricing, time};
+
+pub use zenith_common::{
+    AssetConfig, Currency, EarlyDiscountSchedule, Installment, InvoiceAmendment, InvoicePage, InvoiceStatus, InvoiceTerms, Quote, QuoteParams, RecurringRoll,
+    RiskGrade, RiskOverride,
+};
+
+mod legacy;
+
+/// Storage layout version written by this build; bump it alongside a new `migrate` step
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// Auto-approval limit for invoices their customer has acknowledged, until the admin sets one
+pub const DEFAULT_ACKNOWLEDGED_APPROVAL_THRESHOLD: i128 = 10000_0000000;
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     Admin,
     InvoiceNftContract,
-    LiquidityPoolContract,
+    LiquidityPoolContract, // single pool used before version 3
+    AssetPool(Currency),
+    PoolFactory,
+    FundingPool(u64), // invoice_id
     MarketplaceSettings,
     PendingInvoices,
     ApprovedInvoices,
     FundedInvoices,
     CompletedInvoices,
+    OverdueInvoices,
+    DefaultedInvoices,
     InvoiceApproval(u64), // invoice_id
     PaymentVerification(u64), // invoice_id
     TotalProcessed,
     PlatformStats,
-}
-
-#[derive(Clone, PartialEq, Eq, Debug)]
-#[contracttype]
-pub enum InvoiceStatus {
-    Pending,
-    Approved,
-    Rejected,
-    Funded,
-    Completed,
-    Overdue,
-    Cancelled,
+    SchemaVersion,
+    AcknowledgedApprovalThreshold,
+    EarlyDiscountSchedule,
+    CollateralRatios,
+    InsuranceReserve,
+    Collateral(u64), // invoice_id
+    CreatorCollateral(Address),
+    InstallmentPayments(u64), // invoice_id
+    TreasuryReceivable(Address), // late fee shares owed to a treasury
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -50,9 +71,16 @@ pub struct MarketplaceSettings {
     pub min_invoice_amount: i128,
     pub max_invoice_amount: i128,
     pub auto_approval_threshold: i128, // Auto-approve invoices below this amount
-    pub early_payment_bonus: u32, // basis points (e.g., 300 = 3%)
+    pub early_payment_bonus: u32, // basis points (e.g., 300 = 3%), capped at the platform fee
     pub min_days_until_due: u64, // Minimum days until due date
     pub max_days_until_due: u64, // Maximum days until due date
+    pub grace_period_days: u64, // Days after due date before an invoice is overdue
+    pub default_period_days: u64, // Days overdue before an invoice is defaulted
+    pub default_trust_penalty: u32, // Trust score points removed on default
+    pub late_fee_rate: u32, // basis points of invoice amount per day late
+    pub late_fee_cap: u32, // basis points (maximum accrued late fee)
+    pub late_fee_pool_share: u32, // basis points of late fees routed to the pool
+    pub treasury: Address, // Receives the remaining share of late fees
 }
 
 #[derive(Clone)]
@@ -67,7 +95,7 @@ pub struct InvoiceApproval {
     pub approval_date: Option<u64>,
     pub funding_date: Option<u64>,
     pub completion_date: Option<u64>,
-    pub risk_score: String, // "A", "B", "C", "D"
+    pub risk_score: RiskGrade,
     pub discount_rate: u32, // basis points
     pub funded_amount: Option<i128>,
 }
@@ -81,8 +109,10 @@ pub struct PaymentRecord {
     pub payment_method: PaymentMethod,
     pub payment_date: u64,
     pub is_early_payment: bool,
-    pub cashback_amount: i128,
+    pub early_discount: i128, // creator's discount off the amount due, taken from their residual
+    pub cashback_amount: i128, // protocol bonus, paid out of the platform fee
     pub platform_fee: i128,
+    pub late_fee: i128,
 }
 
 #[derive(Clone)]
@@ -97,18 +127,64 @@ pub struct MarketplaceStats {
     pub total_liquidity_provided: i128,
 }
 
+#[contractclient(name = "InvoiceNftClient")]
+pub trait InvoiceNftInterface {
+    fn update_status(env: Env, invoice_id: u64, new_status: InvoiceStatus, caller: Address);
+    fn get_trust_score(env: Env, creator: Address) -> u32;
+    fn update_trust_score(env: Env, creator: Address, new_score: u32, admin: Address);
+    fn cancel_invoice(env: Env, invoice_id: u64, creator: Address);
+    fn amend_invoice(env: Env, invoice_id: u64, creator: Address, amendment: InvoiceAmendment) -> u32;
+    fn quote(env: Env, params: QuoteParams) -> Quote;
+    fn get_risk_grade(env: Env, invoice_id: u64) -> RiskGrade;
+    fn override_risk_grade(env: Env, admin: Address, invoice_id: u64, risk_override: RiskOverride) -> Quote;
+    fn get_invoice_terms(env: Env, invoice_id: u64) -> InvoiceTerms;
+    fn get_invoice_currency(env: Env, invoice_id: u64) -> Currency;
+    fn get_asset(env: Env, currency: Currency) -> Option<AssetConfig>;
+    fn is_flagged(env: Env, invoice_id: u64) -> bool;
+    fn is_acknowledged(env: Env, invoice_id: u64) -> bool;
+    fn get_financeable_amount(env: Env, invoice_id: u64) -> i128;
+    fn get_early_discount(env: Env, invoice_id: u64) -> u32;
+    fn roll_recurring(env: Env, template_id: u64) -> RecurringRoll;
+    fn get_collateral_type(env: Env, invoice_id: u64) -> Option<String>;
+    fn record_payment(env: Env, invoice_id: u64, payer: Address, amount: i128) -> InvoiceStatus;
+    fn get_installments(env: Env, invoice_id: u64) -> Vec<Installment>;
+    fn get_amount_paid(env: Env, invoice_id: u64) -> i128;
+    fn get_outstanding_balance(env: Env, invoice_id: u64) -> i128;
+}
+
+#[contractclient(name = "LiquidityPoolClient")]
+pub trait LiquidityPoolInterface {
+    fn record_default(env: Env, caller: Address, invoice_id: u64) -> i128;
+    fn record_late_fee(env: Env, caller: Address, invoice_id: u64, amount: i128);
+    fn record_recovery(env: Env, caller: Address, invoice_id: u64, amount: i128);
+    fn process_repayment(env: Env, caller: Address, invoice_id: u64, repayment_amount: i128) -> bool;
+    fn process_installment(env: Env, caller: Address, invoice_id: u64, invoice_paid: i128, invoice_amount: i128) -> i128;
+    fn fund_invoice(env: Env, caller: Address, invoice_id: u64, amount: i128, expected_return: i128) -> bool;
+    fn get_available_liquidity(env: Env) -> i128;
+    fn get_asset(env: Env) -> Address;
+}
+
+#[contractclient(name = "PoolFactoryClient")]
+pub trait PoolFactoryInterface {
+    fn find_pool(env: Env, currency: Currency, risk_grade: RiskGrade) -> Option<Address>;
+}
+
 #[contract]
 pub struct MarketplaceContract;
 
 #[contractimpl]
 impl MarketplaceContract {
-    /// Initialize the marketplace contract
+    /// Initialize the marketplace contract; `liquidity_pool_contract` becomes the USDC pool
     pub fn initialize(
         env: Env,
         admin: Address,
         invoice_nft_contract: Address,
         liquidity_pool_contract: Address,
     ) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+
         admin.require_auth();
 
         let settings = MarketplaceSettings {
@@ -121,6 +197,13 @@ impl MarketplaceContract {
             early_payment_bonus: 300, // 3% cashback for early payment
             min_days_until_due: 7,    // Minimum 7 days
             max_days_until_due: 365,  // Maximum 1 year
+            grace_period_days: 5,     // Overdue 5 days after due date
+            default_period_days: 30,  // Defaulted after 30 days overdue
+            default_trust_penalty: 20, // 20 trust score points
+            late_fee_rate: 10,         // 0.1% per day late
+            late_fee_cap: 1000,        // Capped at 10%
+            late_fee_pool_share: 8000, // 80% to the pool, 20% to the treasury
+            treasury: admin.clone(),
         };
 
         let stats = MarketplaceStats {
@@ -135,16 +218,12 @@ impl MarketplaceContract {
 
         env.storage().instance().set(&DataKey::Admin, &admin);
         env.storage().instance().set(&DataKey::InvoiceNftContract, &invoice_nft_contract);
-        env.storage().instance().set(&DataKey::LiquidityPoolContract, &liquidity_pool_contract);
+        env.storage().instance().set(&DataKey::AssetPool(Currency::Usdc), &liquidity_pool_contract);
         env.storage().instance().set(&DataKey::MarketplaceSettings, &settings);
         env.storage().instance().set(&DataKey::TotalProcessed, &0u64);
         env.storage().instance().set(&DataKey::PlatformStats, &stats);
-
-        // Initialize empty lists
-        env.storage().persistent().set(&DataKey::PendingInvoices, &Vec::<u64>::new(&env));
-        env.storage().persistent().set(&DataKey::ApprovedInvoices, &Vec::<u64>::new(&env));
-        env.storage().persistent().set(&DataKey::FundedInvoices, &Vec::<u64>::new(&env));
-        env.storage().persistent().set(&DataKey::CompletedInvoices, &Vec::<u64>::new(&env));
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Self::extend_instance_ttl(&env);
 
         env.events().publish(
             (symbol_short!("init"),),
@@ -152,38 +231,236 @@ impl MarketplaceContract {
         );
     }
 
-    /// Submit invoice for marketplace approval (called after NFT creation)
-    pub fn submit_invoice(
-        env: Env,
-        creator: Address,
-        invoice_id: u64,
-        amount: i128,
-        due_date: u64,
-    ) -> InvoiceStatus {
-        creator.require_auth();
+    /// Replace the contract code, keeping its storage (admin only)
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
 
         let settings: MarketplaceSettings = env.storage().instance()
             .get(&DataKey::MarketplaceSettings)
             .expect("Marketplace not initialized");
 
-        // Validate invoice parameters
-        if amount < settings.min_invoice_amount || amount > settings.max_invoice_amount {
-            panic!("Invoice amount outside allowed range");
+        if admin != settings.admin {
+            panic!("Unauthorized");
         }
 
-        let current_time = env.ledger().timestamp();
-        if due_date <= current_time {
-            panic!("Due date must be in the future");
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish((symbol_short!("upgrade"),), new_wasm_hash);
+    }
+
+    /// Bring stored data up to the layout this build expects (admin only)
+    pub fn migrate(env: Env, admin: Address) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        // Settings may still be in an older layout, so check against the stored admin
+        let stored_admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Marketplace not initialized");
+
+        if admin != stored_admin {
+            panic!("Unauthorized");
         }
 
-        // Check due date range
-        let days_until_due = (due_date - current_time) / 86400;
-        if days_until_due < settings.min_days_until_due || days_until_due > settings.max_days_until_due {
-            panic!("Due date outside allowed range");
+        let from_version = Self::get_schema_version(env.clone());
+        if from_version > SCHEMA_VERSION {
+            panic!("Unsupported schema version");
+        }
+
+        // Each step rewrites the layout left by the previous version.
+        // Version 1 adds the overdue, default and late fee terms to the settings,
+        // taking the defaults `initialize` uses; payment records without late fees
+        // are read as having none.
+        // Version 2 types the risk grade; approvals are converted as they are read
+        // and can be rewritten with `migrate_approvals`.
+        // Version 3 routes funding by asset; the single earlier pool held USDC.
+        // Version 4 records the creator's early discount on payments; older payment
+        // records are read as having none.
+        if from_version < 3 {
+            let legacy_pool: Option<Address> = env.storage().instance().get(&DataKey::LiquidityPoolContract);
+            if let Some(pool) = legacy_pool {
+                env.storage().instance().set(&DataKey::AssetPool(Currency::Usdc), &pool);
+                env.storage().instance().remove(&DataKey::LiquidityPoolContract);
+            }
+        }
+
+        // Unversioned storage kept each status list as one `Vec<u64>` under the same key
+        if from_version == 0 {
+            if let Some(settings) = legacy::read_v0_settings(&env) {
+                env.storage().instance().set(&DataKey::MarketplaceSettings, &settings);
+            }
+            for list_key in [
+                DataKey::PendingInvoices,
+                DataKey::ApprovedInvoices,
+                DataKey::FundedInvoices,
+                DataKey::CompletedInvoices,
+            ] {
+                index::import_list(&env, &list_key, &list_key);
+            }
+        }
+
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        env.events().publish((symbol_short!("migrate"),), (from_version, SCHEMA_VERSION));
+
+        SCHEMA_VERSION
+    }
+
+    /// Rewrite approvals stored in an older layout (admin only).
+    /// Returns how many were rewritten; missing or current approvals are skipped.
+    pub fn migrate_approvals(env: Env, admin: Address, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        let mut migrated = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            if let Some(approval) = legacy::read_v1_approval(&env, invoice_id) {
+                // Write in place so settled approvals keep their remaining TTL
+                env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), &approval);
+                migrated += 1;
+            }
+        }
+
+        migrated
+    }
+
+    /// Route a currency's invoices to a liquidity pool holding the same token (admin only).
+    /// Outstanding invoices settle against whichever pool is registered when they do.
+    pub fn set_asset_pool(env: Env, admin: Address, currency: Currency, pool: Address) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        let asset = Self::invoice_nft(&env).get_asset(&currency).expect("Unsupported currency");
+        if LiquidityPoolClient::new(&env, &pool).get_asset() != asset.token {
+            panic!("Pool asset mismatch");
+        }
+
+        env.storage().instance().set(&DataKey::AssetPool(currency.clone()), &pool);
+
+        env.events().publish((symbol_short!("pool"), currency), pool);
+    }
+
+    /// Get the liquidity pool serving invoices in a currency
+    pub fn get_asset_pool(env: Env, currency: Currency) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().get(&DataKey::AssetPool(currency))
+    }
+
+    /// Pick pools by currency and risk grade from a pool factory's registry (admin only).
+    /// Grades the factory has no pool for fall back to the currency's asset pool.
+    pub fn set_pool_factory(env: Env, admin: Address, factory: Address) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        env.storage().instance().set(&DataKey::PoolFactory, &factory);
+
+        env.events().publish((symbol_short!("factory"),), factory);
+    }
+
+    /// Get the pool factory used to pick pools, if any
+    pub fn get_pool_factory(env: Env) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().get(&DataKey::PoolFactory)
+    }
+
+    /// Get the pool that funded an invoice
+    pub fn get_funding_pool(env: Env, invoice_id: u64) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::FundingPool(invoice_id))
+    }
+
+    /// Get the storage layout version (0 for storage written before versioning)
+    pub fn get_schema_version(env: Env) -> u32 {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    /// Submit invoice for marketplace approval (called after NFT creation),
+    /// on the amount and due date its NFT records
+    pub fn submit_invoice(env: Env, creator: Address, invoice_id: u64) -> InvoiceStatus {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        Self::submit(env, creator, invoice_id)
+    }
+
+    /// Mint the next occurrence of a recurring invoice and, within the limit its
+    /// creator approved, submit it for approval (permissionless). Returns the new invoice.
+    pub fn roll_recurring(env: Env, template_id: u64) -> u64 {
+        Self::extend_instance_ttl(&env);
+
+        let roll = Self::invoice_nft(&env).roll_recurring(&template_id);
+        if roll.auto_submit {
+            Self::submit(env, roll.creator, roll.invoice_id);
+        }
+
+        roll.invoice_id
+    }
+
+    /// Submit an invoice on behalf of a creator who has already authorized it
+    fn submit(env: Env, creator: Address, invoice_id: u64) -> InvoiceStatus {
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if env.storage().persistent().has(&DataKey::InvoiceApproval(invoice_id)) {
+            panic!("Invoice already submitted");
         }
 
-        // Calculate discount rate based on days until due
-        let discount_rate = Self::calculate_discount_rate(days_until_due);
+        // Submit on the terms the NFT records, and only for the creator who minted it
+        let terms = Self::invoice_nft(&env).get_invoice_terms(&invoice_id);
+        if terms.creator != creator {
+            panic!("Unauthorized");
+        }
+        let amount = terms.amount;
+        let due_date = terms.due_date;
+
+        // Validate invoice parameters
+        let current_time = env.ledger().timestamp();
+        Self::validate_terms(&settings, amount, due_date, current_time);
+        Self::require_not_flagged(&env, invoice_id);
+
+        // Price at the grade the invoice NFT assigned when it was minted,
+        // on the part of the amount its financing rule lets pools advance against
+        let grade = Self::invoice_nft(&env).get_risk_grade(&invoice_id);
+        let financeable_amount = Self::financeable_amount(&env, invoice_id, amount);
+        let quote = Self::quote_terms(&env, &creator, financeable_amount, due_date, grade);
 
         // Create approval record
         let mut approval = InvoiceApproval {
@@ -196,37 +473,32 @@ impl MarketplaceContract {
             approval_date: None,
             funding_date: None,
             completion_date: None,
-            risk_score: String::from_str(&env, "C"), // Default risk score
-            discount_rate,
+            risk_score: grade,
+            discount_rate: quote.discount_rate,
             funded_amount: None,
         };
 
-        // Auto-approve small invoices
-        if amount <= settings.auto_approval_threshold {
+        // Auto-approve small invoices, and larger ones the customer has confirmed on-chain
+        let auto_approve = amount <= settings.auto_approval_threshold
+            || (amount <= Self::get_ack_approval_threshold(env.clone())
+                && Self::invoice_nft(&env).is_acknowledged(&invoice_id));
+        if auto_approve {
             approval.status = InvoiceStatus::Approved;
-            approval.approved_amount = Some(amount);
+            approval.approved_amount = Some(financeable_amount);
             approval.approval_date = Some(current_time);
 
             // Add to approved list
-            let mut approved_list: Vec<u64> = env.storage().persistent()
-                .get(&DataKey::ApprovedInvoices)
-                .unwrap_or(Vec::new(&env));
-            approved_list.push_back(invoice_id);
-            env.storage().persistent().set(&DataKey::ApprovedInvoices, &approved_list);
+            index::push(&env, &DataKey::ApprovedInvoices, invoice_id);
 
             // Try to fund immediately
             Self::try_fund_invoice(&env, invoice_id, &mut approval);
         } else {
             // Add to pending list for manual review
-            let mut pending_list: Vec<u64> = env.storage().persistent()
-                .get(&DataKey::PendingInvoices)
-                .unwrap_or(Vec::new(&env));
-            pending_list.push_back(invoice_id);
-            env.storage().persistent().set(&DataKey::PendingInvoices, &pending_list);
+            index::push(&env, &DataKey::PendingInvoices, invoice_id);
         }
 
         // Store approval record
-        env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), &approval);
+        Self::save_approval(&env, &approval);
 
         env.events().publish(
             (symbol_short!("submit"),),
@@ -242,8 +514,10 @@ impl MarketplaceContract {
         admin: Address,
         invoice_id: u64,
         approved_amount: i128,
-        risk_score: String,
+        risk_override: Option<RiskOverride>,
     ) -> bool {
+        Self::extend_instance_ttl(&env);
+
         admin.require_auth();
 
         let settings: MarketplaceSettings = env.storage().instance()
@@ -254,9 +528,7 @@ impl MarketplaceContract {
             panic!("Unauthorized");
         }
 
-        let mut approval: InvoiceApproval = env.storage().persistent()
-            .get(&DataKey::InvoiceApproval(invoice_id))
-            .expect("Invoice not found");
+        let mut approval = Self::load_approval(&env, invoice_id);
 
         if approval.status != InvoiceStatus::Pending {
             panic!("Invoice not in pending status");
@@ -266,18 +538,33 @@ impl MarketplaceContract {
         if approved_amount > approval.amount {
             panic!("Approved amount cannot exceed requested amount");
         }
+        if approved_amount > Self::financeable_amount(&env, invoice_id, approval.amount) {
+            panic!("Approved amount cannot exceed financeable amount");
+        }
+
+        // Keep the automated grade unless the reviewer overrides it
+        let nft = Self::invoice_nft(&env);
+        let grade = match risk_override {
+            Some(risk_override) => {
+                nft.override_risk_grade(&admin, &invoice_id, &risk_override);
+                risk_override.risk_grade
+            }
+            None => nft.get_risk_grade(&invoice_id),
+        };
+        let quote = Self::quote_terms(&env, &approval.creator, approved_amount, approval.due_date, grade);
 
         // Update approval
         approval.status = InvoiceStatus::Approved;
         approval.approved_amount = Some(approved_amount);
         approval.approval_date = Some(env.ledger().timestamp());
-        approval.risk_score = risk_score;
+        approval.risk_score = grade;
+        approval.discount_rate = quote.discount_rate;
 
         // Move from pending to approved list
         Self::move_invoice_between_lists(&env, invoice_id, &DataKey::PendingInvoices, &DataKey::ApprovedInvoices);
 
         // Store updated approval
-        env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), &approval);
+        Self::save_approval(&env, &approval);
 
         // Try to fund immediately
         Self::try_fund_invoice(&env, invoice_id, &mut approval);
@@ -297,6 +584,8 @@ impl MarketplaceContract {
         invoice_id: u64,
         reason: String,
     ) -> bool {
+        Self::extend_instance_ttl(&env);
+
         admin.require_auth();
 
         let settings: MarketplaceSettings = env.storage().instance()
@@ -307,9 +596,7 @@ impl MarketplaceContract {
             panic!("Unauthorized");
         }
 
-        let mut approval: InvoiceApproval = env.storage().persistent()
-            .get(&DataKey::InvoiceApproval(invoice_id))
-            .expect("Invoice not found");
+        let mut approval = Self::load_approval(&env, invoice_id);
 
         if approval.status != InvoiceStatus::Pending {
             panic!("Invoice not in pending status");
@@ -322,7 +609,9 @@ impl MarketplaceContract {
         Self::remove_from_list(&env, invoice_id, &DataKey::PendingInvoices);
 
         // Store updated approval
-        env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), &approval);
+        Self::save_approval(&env, &approval);
+
+        Self::release_collateral(&env, &approval);
 
         env.events().publish(
             (symbol_short!("reject"),),
@@ -332,6 +621,109 @@ impl MarketplaceContract {
         true
     }
 
+    /// Amend a pending or approved invoice and send it back for review (creator only)
+    pub fn amend_invoice(
+        env: Env,
+        creator: Address,
+        invoice_id: u64,
+        amendment: InvoiceAmendment,
+    ) -> InvoiceStatus {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        let mut approval = Self::load_approval(&env, invoice_id);
+
+        if approval.creator != creator {
+            panic!("Unauthorized");
+        }
+
+        if approval.status != InvoiceStatus::Pending && approval.status != InvoiceStatus::Approved {
+            panic!("Invoice cannot be amended in current status");
+        }
+
+        // Re-validate and re-price with the amended terms
+        let amount = amendment.amount.unwrap_or(approval.amount);
+        let due_date = amendment.due_date.unwrap_or(approval.due_date);
+        Self::validate_terms(&settings, amount, due_date, env.ledger().timestamp());
+
+        // The invoice NFT re-grades the amended terms
+        let nft = Self::invoice_nft(&env);
+        let amendment_count = nft.amend_invoice(&invoice_id, &creator, &amendment);
+        let grade = nft.get_risk_grade(&invoice_id);
+        let financeable_amount = Self::financeable_amount(&env, invoice_id, amount);
+        let quote = Self::quote_terms(&env, &creator, financeable_amount, due_date, grade);
+
+        approval.amount = amount;
+        approval.due_date = due_date;
+        approval.risk_score = grade;
+        approval.discount_rate = quote.discount_rate;
+
+        // Invalidate any existing approval so the invoice gets re-reviewed
+        if approval.status == InvoiceStatus::Approved {
+            Self::move_invoice_between_lists(&env, invoice_id, &DataKey::ApprovedInvoices, &DataKey::PendingInvoices);
+        }
+        approval.status = InvoiceStatus::Pending;
+        approval.approved_amount = None;
+        approval.approval_date = None;
+
+        // Store updated approval
+        Self::save_approval(&env, &approval);
+
+        env.events().publish(
+            (symbol_short!("amend"),),
+            (invoice_id, amendment_count)
+        );
+
+        approval.status
+    }
+
+    /// Cancel a pending or approved invoice before funding (creator only)
+    pub fn cancel_invoice(
+        env: Env,
+        creator: Address,
+        invoice_id: u64,
+    ) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let mut approval = Self::load_approval(&env, invoice_id);
+
+        if approval.creator != creator {
+            panic!("Unauthorized");
+        }
+
+        // Remove from whichever review list currently holds the invoice
+        match approval.status {
+            InvoiceStatus::Pending => Self::remove_from_list(&env, invoice_id, &DataKey::PendingInvoices),
+            InvoiceStatus::Approved => Self::remove_from_list(&env, invoice_id, &DataKey::ApprovedInvoices),
+            _ => panic!("Invoice cannot be cancelled in current status"),
+        }
+
+        // Update approval
+        approval.status = InvoiceStatus::Cancelled;
+
+        // Store updated approval
+        Self::save_approval(&env, &approval);
+
+        // Archive the NFT as well
+        Self::invoice_nft(&env).cancel_invoice(&invoice_id, &creator);
+
+        Self::release_collateral(&env, &approval);
+
+        env.events().publish(
+            (symbol_short!("cancel"),),
+            (invoice_id, creator)
+        );
+
+        true
+    }
+
     /// Process payment for an invoice
     pub fn process_payment(
         env: Env,
@@ -340,16 +732,20 @@ impl MarketplaceContract {
         payment_amount: i128,
         payment_method: PaymentMethod,
     ) -> PaymentRecord {
+        Self::extend_instance_ttl(&env);
+
         payer.require_auth();
 
-        let mut approval: InvoiceApproval = env.storage().persistent()
-            .get(&DataKey::InvoiceApproval(invoice_id))
-            .expect("Invoice not found");
+        let mut approval = Self::load_approval(&env, invoice_id);
 
-        if approval.status != InvoiceStatus::Funded {
+        if !approval.status.is_outstanding() {
             panic!("Invoice not in funded status");
         }
 
+        if Self::invoice_nft(&env).get_amount_paid(&invoice_id) > 0 {
+            panic!("Invoice is being paid in installments");
+        }
+
         let current_time = env.ledger().timestamp();
         let is_early_payment = current_time < approval.due_date;
 
@@ -358,15 +754,23 @@ impl MarketplaceContract {
             .get(&DataKey::MarketplaceSettings)
             .expect("Marketplace not initialized");
 
+        let (early_discount, late_fee) = Self::full_payment_terms(&env, &settings, &approval, current_time);
+
+        // Whatever the timing, the payment settles the invoice only if it covers what is due
+        if payment_amount < approval.amount - early_discount + late_fee {
+            panic!("Payment below amount due");
+        }
+
+        // Calculate platform fee
+        let platform_fee = apply_bps(payment_amount, settings.platform_fee, Rounding::Down);
+
+        // The protocol's early payment bonus is paid out of the platform fee
         let cashback_amount = if is_early_payment {
-            payment_amount * settings.early_payment_bonus as i128 / 10000
+            apply_bps(payment_amount, settings.early_payment_bonus, Rounding::Down).min(platform_fee)
         } else {
             0
         };
 
-        // Calculate platform fee
-        let platform_fee = payment_amount * settings.platform_fee as i128 / 10000;
-
         // Create payment record
         let payment_record = PaymentRecord {
             invoice_id,
@@ -375,108 +779,341 @@ impl MarketplaceContract {
             payment_method,
             payment_date: current_time,
             is_early_payment,
+            early_discount,
             cashback_amount,
             platform_fee,
+            late_fee,
+        };
+
+        // Move from funded (or overdue) to completed list
+        let from_key = if approval.status == InvoiceStatus::Overdue {
+            DataKey::OverdueInvoices
+        } else {
+            DataKey::FundedInvoices
         };
+        Self::move_invoice_between_lists(&env, invoice_id, &from_key, &DataKey::CompletedInvoices);
 
         // Update invoice status
         approval.status = InvoiceStatus::Completed;
         approval.completion_date = Some(current_time);
 
-        // Move from funded to completed list
-        Self::move_invoice_between_lists(&env, invoice_id, &DataKey::FundedInvoices, &DataKey::CompletedInvoices);
-
         // Store updated approval and payment record
-        env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), &approval);
+        Self::save_approval(&env, &approval);
         env.storage().persistent().set(&DataKey::PaymentVerification(invoice_id), &payment_record);
 
+        // Settle the NFT and return the pool its expected return, as the last installment would
+        let this = env.current_contract_address();
+        Self::invoice_nft(&env).update_status(&invoice_id, &InvoiceStatus::Completed, &this);
+        let funded_amount = approval.funded_amount.expect("Invoice not funded");
+        let expected_return = pricing::expected_return(funded_amount, approval.discount_rate);
+        Self::liquidity_pool(&env, &approval).process_repayment(&this, &invoice_id, &expected_return);
+
+        Self::release_collateral(&env, &approval);
+
+        Self::split_late_fee(&env, &settings, &approval, late_fee);
+
         // Update platform stats
-        Self::update_platform_stats(&env, payment_amount, platform_fee);
+        Self::update_platform_stats(&env, payment_amount, platform_fee, true);
 
         env.events().publish(
             (symbol_short!("payment"),),
-            (invoice_id, payer, payment_amount, is_early_payment, cashback_amount)
+            (invoice_id, payer, payment_amount, is_early_payment, early_discount, cashback_amount)
         );
 
         payment_record
     }
 
-    /// Request funding for approved invoice
-    pub fn request_funding(
+    /// Pay part of a funded invoice's outstanding balance, plus late fees on any installments
+    /// past due. The pool takes its proportional share of each installment as it lands, and an
+    /// overdue invoice whose installments are back on schedule returns to `PartiallyPaid`.
+    /// Installments earn neither the early discount nor the early payment bonus.
+    pub fn pay_installment(
         env: Env,
-        creator: Address,
+        payer: Address,
         invoice_id: u64,
-    ) -> bool {
-        creator.require_auth();
+        payment_amount: i128,
+        payment_method: PaymentMethod,
+    ) -> PaymentRecord {
+        Self::extend_instance_ttl(&env);
 
-        let mut approval: InvoiceApproval = env.storage().persistent()
-            .get(&DataKey::InvoiceApproval(invoice_id))
-            .expect("Invoice not found");
+        payer.require_auth();
 
-        if approval.status != InvoiceStatus::Approved {
-            panic!("Invoice not approved");
-        }
+        let mut approval = Self::load_approval(&env, invoice_id);
 
-        if approval.creator != creator {
-            panic!("Unauthorized");
+        if !approval.status.is_outstanding() {
+            panic!("Invoice not in funded status");
         }
 
-        Self::try_fund_invoice(&env, invoice_id, &mut approval);
-
-        true
-    }
-
-    /// Get invoice approval details
-    pub fn get_invoice_approval(env: Env, invoice_id: u64) -> Option<InvoiceApproval> {
-        env.storage().persistent().get(&DataKey::InvoiceApproval(invoice_id))
-    }
-
-    /// Get payment record
-    pub fn get_payment_record(env: Env, invoice_id: u64) -> Option<PaymentRecord> {
-        env.storage().persistent().get(&DataKey::PaymentVerification(invoice_id))
-    }
-
-    /// Get pending invoices (admin only)
-    pub fn get_pending_invoices(env: Env, admin: Address) -> Vec<u64> {
-        admin.require_auth();
-
         let settings: MarketplaceSettings = env.storage().instance()
             .get(&DataKey::MarketplaceSettings)
             .expect("Marketplace not initialized");
 
-        if admin != settings.admin {
-            panic!("Unauthorized");
+        let current_time = env.ledger().timestamp();
+        let nft = Self::invoice_nft(&env);
+        let schedule = Self::installments(&env, &approval);
+        let paid = nft.get_amount_paid(&invoice_id);
+
+        // Late installments must cover the penalty accrued on them first
+        let late_fee = Self::installment_late_fee(&settings, &schedule, paid, current_time);
+        let amount = payment_amount - late_fee;
+        if amount <= 0 {
+            panic!("Payment does not cover late fees");
         }
 
-        env.storage().persistent()
-            .get(&DataKey::PendingInvoices)
-            .unwrap_or(Vec::new(&env))
-    }
+        // The invoice NFT keeps the outstanding balance and refuses overpayment
+        let nft_status = nft.record_payment(&invoice_id, &payer, &amount);
+        let paid = paid + amount;
+        let invoice_amount = paid + nft.get_outstanding_balance(&invoice_id);
 
-    /// Get approved invoices
-    pub fn get_approved_invoices(env: Env) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&DataKey::ApprovedInvoices)
-            .unwrap_or(Vec::new(&env))
-    }
+        let this = env.current_contract_address();
+        Self::liquidity_pool(&env, &approval).process_installment(&this, &invoice_id, &paid, &invoice_amount);
 
-    /// Get funded invoices
-    pub fn get_funded_invoices(env: Env) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&DataKey::FundedInvoices)
-            .unwrap_or(Vec::new(&env))
+        let platform_fee = apply_bps(payment_amount, settings.platform_fee, Rounding::Down);
+        let payment_record = PaymentRecord {
+            invoice_id,
+            payer: payer.clone(),
+            amount: payment_amount,
+            payment_method,
+            payment_date: current_time,
+            is_early_payment: current_time < approval.due_date,
+            early_discount: 0,
+            cashback_amount: 0,
+            platform_fee,
+            late_fee,
+        };
+
+        let list_key = Self::list_key(&approval.status).expect("Invoice not in funded status");
+        let settled = nft_status == InvoiceStatus::Completed;
+        if settled {
+            Self::move_invoice_between_lists(&env, invoice_id, &list_key, &DataKey::CompletedInvoices);
+            approval.status = InvoiceStatus::Completed;
+            approval.completion_date = Some(current_time);
+            env.storage().persistent().set(&DataKey::PaymentVerification(invoice_id), &payment_record);
+        } else if approval.status == InvoiceStatus::Overdue {
+            let next = installment::next_unpaid(&schedule, paid).expect("Installment not found");
+            if current_time <= next.due_date + time::days_to_seconds(settings.grace_period_days) {
+                approval.status = InvoiceStatus::PartiallyPaid;
+                Self::move_invoice_between_lists(&env, invoice_id, &list_key, &DataKey::FundedInvoices);
+                nft.update_status(&invoice_id, &InvoiceStatus::PartiallyPaid, &this);
+            }
+        } else {
+            approval.status = InvoiceStatus::PartiallyPaid;
+        }
+
+        Self::save_approval(&env, &approval);
+
+        let payments_key = DataKey::InstallmentPayments(invoice_id);
+        let mut payments = Self::get_installment_payments(env.clone(), invoice_id);
+        payments.push_back(payment_record.clone());
+        env.storage().persistent().set(&payments_key, &payments);
+        if approval.status.is_live() {
+            Self::extend_persistent_ttl(&env, &payments_key);
+        } else {
+            Self::release_collateral(&env, &approval);
+        }
+
+        Self::split_late_fee(&env, &settings, &approval, late_fee);
+
+        Self::update_platform_stats(&env, payment_amount, platform_fee, settled);
+
+        env.events().publish(
+            (symbol_short!("install"), invoice_id),
+            (payer, payment_amount, late_fee, approval.status.clone())
+        );
+
+        payment_record
     }
 
-    /// Get completed invoices
-    pub fn get_completed_invoices(env: Env) -> Vec<u64> {
+    /// Get the installments paid on an invoice through the marketplace, oldest first
+    pub fn get_installment_payments(env: Env, invoice_id: u64) -> Vec<PaymentRecord> {
+        Self::extend_instance_ttl(&env);
+
         env.storage().persistent()
-            .get(&DataKey::CompletedInvoices)
+            .get(&DataKey::InstallmentPayments(invoice_id))
             .unwrap_or(Vec::new(&env))
     }
 
+    /// Move a past-due invoice to overdue, and later to defaulted (permissionless)
+    pub fn check_overdue(env: Env, invoice_id: u64) -> InvoiceStatus {
+        Self::extend_instance_ttl(&env);
+
+        Self::advance_lifecycle(&env, invoice_id)
+    }
+
+    /// Run the overdue check for several invoices at once (permissionless)
+    pub fn check_overdue_batch(env: Env, invoice_ids: Vec<u64>) -> Vec<InvoiceStatus> {
+        Self::extend_instance_ttl(&env);
+
+        let mut statuses = Vec::new(&env);
+        for invoice_id in invoice_ids.iter() {
+            statuses.push_back(Self::advance_lifecycle(&env, invoice_id));
+        }
+        statuses
+    }
+
+    /// Get the amount due for an invoice at a given time, less any early discount
+    /// and including late fees accrued on installments past due
+    pub fn amount_due(env: Env, invoice_id: u64, at: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        let approval = Self::load_approval(&env, invoice_id);
+
+        if !approval.status.is_outstanding() {
+            return 0;
+        }
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        let nft = Self::invoice_nft(&env);
+        let paid = nft.get_amount_paid(&invoice_id);
+
+        // Only an invoice settled in one payment earns the early discount
+        if paid == 0 {
+            let (early_discount, late_fee) = Self::full_payment_terms(&env, &settings, &approval, at);
+            approval.amount - early_discount + late_fee
+        } else {
+            let schedule = Self::installments(&env, &approval);
+            nft.get_outstanding_balance(&invoice_id) + Self::installment_late_fee(&settings, &schedule, paid, at)
+        }
+    }
+
+    /// Request funding for approved invoice, returning whether a pool funded it
+    pub fn request_funding(
+        env: Env,
+        creator: Address,
+        invoice_id: u64,
+    ) -> bool {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let mut approval = Self::load_approval(&env, invoice_id);
+
+        if approval.status != InvoiceStatus::Approved {
+            panic!("Invoice not approved");
+        }
+
+        if approval.creator != creator {
+            panic!("Unauthorized");
+        }
+
+        Self::try_fund_invoice(&env, invoice_id, &mut approval);
+
+        approval.status == InvoiceStatus::Funded
+    }
+
+    /// Get invoice approval details
+    pub fn get_invoice_approval(env: Env, invoice_id: u64) -> Option<InvoiceApproval> {
+        Self::extend_instance_ttl(&env);
+
+        let key = DataKey::InvoiceApproval(invoice_id);
+        let approval = Self::read_approval(&env, invoice_id);
+        if let Some(approval) = &approval {
+            if approval.status.is_live() {
+                Self::extend_persistent_ttl(&env, &key);
+            }
+        }
+        approval
+    }
+
+    /// Extend the TTL of live invoice approvals and their list entries.
+    /// Permissionless so keepers can keep long-running invoices from being archived.
+    pub fn bump(env: Env, invoice_ids: Vec<u64>) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        let mut bumped = 0u32;
+        for invoice_id in invoice_ids.iter() {
+            let key = DataKey::InvoiceApproval(invoice_id);
+            let approval = match Self::read_approval(&env, invoice_id) {
+                Some(approval) => approval,
+                None => continue,
+            };
+            if !approval.status.is_live() {
+                continue;
+            }
+
+            Self::extend_persistent_ttl(&env, &key);
+            if let Some(list_key) = Self::list_key(&approval.status) {
+                index::extend(&env, &list_key, invoice_id);
+            }
+            for key in [DataKey::FundingPool(invoice_id), DataKey::InstallmentPayments(invoice_id)] {
+                if env.storage().persistent().has(&key) {
+                    Self::extend_persistent_ttl(&env, &key);
+                }
+            }
+            bumped += 1;
+        }
+
+        bumped
+    }
+
+    /// Get payment record
+    pub fn get_payment_record(env: Env, invoice_id: u64) -> Option<PaymentRecord> {
+        Self::extend_instance_ttl(&env);
+
+        let value: Val = env.storage().persistent().get(&DataKey::PaymentVerification(invoice_id))?;
+        Some(legacy::payment_record_from_val(&env, &value))
+    }
+
+    /// Get a page of pending invoices, starting after `cursor` (admin only)
+    pub fn get_pending_invoices(env: Env, admin: Address, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        index::page(&env, &DataKey::PendingInvoices, cursor, limit)
+    }
+
+    /// Get a page of approved invoices, starting after `cursor`
+    pub fn get_approved_invoices(env: Env, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::ApprovedInvoices, cursor, limit)
+    }
+
+    /// Get a page of funded invoices, starting after `cursor`
+    pub fn get_funded_invoices(env: Env, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::FundedInvoices, cursor, limit)
+    }
+
+    /// Get a page of completed invoices, starting after `cursor`
+    pub fn get_completed_invoices(env: Env, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::CompletedInvoices, cursor, limit)
+    }
+
+    /// Get a page of overdue invoices, starting after `cursor`
+    pub fn get_overdue_invoices(env: Env, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::OverdueInvoices, cursor, limit)
+    }
+
+    /// Get a page of defaulted invoices, starting after `cursor`
+    pub fn get_defaulted_invoices(env: Env, cursor: Option<u64>, limit: u32) -> InvoicePage {
+        Self::extend_instance_ttl(&env);
+
+        index::page(&env, &DataKey::DefaultedInvoices, cursor, limit)
+    }
+
     /// Get marketplace statistics
     pub fn get_marketplace_stats(env: Env) -> MarketplaceStats {
-        env.storage().instance()
+        Self::extend_instance_ttl(&env);
+
+        let mut stats: MarketplaceStats = env.storage().instance()
             .get(&DataKey::PlatformStats)
             .unwrap_or(MarketplaceStats {
                 total_invoices_processed: 0,
@@ -486,11 +1123,19 @@ impl MarketplaceContract {
                 success_rate: 0,
                 average_funding_time: 0,
                 total_liquidity_provided: 0,
-            })
+            });
+
+        // Funded and overdue invoices are still awaiting repayment
+        stats.active_invoices = (index::len(&env, &DataKey::FundedInvoices)
+            + index::len(&env, &DataKey::OverdueInvoices)) as u64;
+
+        stats
     }
 
     /// Get marketplace settings
     pub fn get_settings(env: Env) -> MarketplaceSettings {
+        Self::extend_instance_ttl(&env);
+
         env.storage().instance()
             .get(&DataKey::MarketplaceSettings)
             .expect("Marketplace not initialized")
@@ -505,6 +1150,8 @@ impl MarketplaceContract {
         early_payment_bonus: Option<u32>,
         auto_approval_threshold: Option<i128>,
     ) {
+        Self::extend_instance_ttl(&env);
+
         admin.require_auth();
 
         let mut settings: MarketplaceSettings = env.storage().instance()
@@ -542,37 +1189,511 @@ impl MarketplaceContract {
         env.events().publish((symbol_short!("update"),), (admin,));
     }
 
-    /// Calculate discount rate based on days until due date
-    fn calculate_discount_rate(days_until_due: u64) -> u32 {
-        // Base rate of 5% APR, decreasing with longer terms
-        let base_rate = 500u32; // 5% in basis points
-        
-        if days_until_due <= 30 {
-            base_rate + 200 // 7% for short term
-        } else if days_until_due <= 90 {
-            base_rate + 100 // 6% for medium term
+    /// Update overdue and default lifecycle settings (admin only)
+    pub fn update_lifecycle_settings(
+        env: Env,
+        admin: Address,
+        grace_period_days: Option<u64>,
+        default_period_days: Option<u64>,
+        default_trust_penalty: Option<u32>,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let mut settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        if let Some(days) = grace_period_days {
+            settings.grace_period_days = days;
+        }
+        if let Some(days) = default_period_days {
+            settings.default_period_days = days;
+        }
+        if let Some(penalty) = default_trust_penalty {
+            if penalty > 100 {
+                panic!("Trust penalty cannot exceed 100");
+            }
+            settings.default_trust_penalty = penalty;
+        }
+
+        env.storage().instance().set(&DataKey::MarketplaceSettings, &settings);
+
+        env.events().publish((symbol_short!("update"),), (admin,));
+    }
+
+    /// Set the auto-approval limit for invoices their customer has acknowledged (admin only)
+    pub fn set_ack_approval_threshold(env: Env, admin: Address, threshold: i128) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        if threshold < 0 {
+            panic!("Invalid approval threshold");
+        }
+
+        env.storage().instance().set(&DataKey::AcknowledgedApprovalThreshold, &threshold);
+
+        env.events().publish((symbol_short!("ack_limit"),), (admin, threshold));
+    }
+
+    /// Get the auto-approval limit for invoices their customer has acknowledged
+    pub fn get_ack_approval_threshold(env: Env) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::AcknowledgedApprovalThreshold)
+            .unwrap_or(DEFAULT_ACKNOWLEDGED_APPROVAL_THRESHOLD)
+    }
+
+    /// Choose how creators' early payment discounts shrink towards the due date (admin only)
+    pub fn set_early_discount_schedule(env: Env, admin: Address, schedule: EarlyDiscountSchedule) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        env.storage().instance().set(&DataKey::EarlyDiscountSchedule, &schedule);
+
+        env.events().publish((symbol_short!("disc_sch"),), (admin, schedule));
+    }
+
+    /// Get the early discount schedule, defaulting to a flat discount until the due date
+    pub fn get_early_discount_schedule(env: Env) -> EarlyDiscountSchedule {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::EarlyDiscountSchedule)
+            .unwrap_or(EarlyDiscountSchedule::Flat)
+    }
+
+    /// Set the share of an invoice to lock as collateral at each risk grade (admin only).
+    /// Only invoices whose creator declared a collateral type need to lock any.
+    pub fn set_collateral_ratios(env: Env, admin: Address, ratios: Vec<u32>) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        if !collateral::is_valid_ratios(&ratios) {
+            panic!("Invalid collateral ratios");
+        }
+
+        env.storage().instance().set(&DataKey::CollateralRatios, &ratios);
+
+        env.events().publish((symbol_short!("collratio"),), (admin, ratios));
+    }
+
+    /// Get the collateral ratios for grades A..D, in basis points
+    pub fn get_collateral_ratios(env: Env) -> Vec<u32> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance()
+            .get(&DataKey::CollateralRatios)
+            .unwrap_or_else(|| collateral::default_ratios(&env))
+    }
+
+    /// Send collateral seized on default to an insurance reserve instead of the funding pool (admin only)
+    pub fn set_insurance_reserve(env: Env, admin: Address, reserve: Option<Address>) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        match &reserve {
+            Some(reserve) => env.storage().instance().set(&DataKey::InsuranceReserve, reserve),
+            None => env.storage().instance().remove(&DataKey::InsuranceReserve),
+        }
+
+        env.events().publish((symbol_short!("insurance"),), (admin, reserve));
+    }
+
+    /// Get the insurance reserve that receives seized collateral, if one is set
+    pub fn get_insurance_reserve(env: Env) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().instance().get(&DataKey::InsuranceReserve)
+    }
+
+    /// Get the late fee income owed to a treasury
+    pub fn get_treasury_receivable(env: Env, treasury: Address) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::TreasuryReceivable(treasury)).unwrap_or(0)
+    }
+
+    /// Get the collateral an invoice must have locked before it is funded
+    pub fn required_collateral(env: Env, invoice_id: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        let approval = Self::load_approval(&env, invoice_id);
+        Self::required_collateral_for(&env, &approval)
+    }
+
+    /// Lock collateral, in the invoice's asset, against an invoice before it is funded (creator only).
+    /// It is returned when the invoice is paid, rejected or cancelled and seized if it defaults.
+    pub fn lock_collateral(env: Env, creator: Address, invoice_id: u64, amount: i128) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        creator.require_auth();
+
+        let approval = Self::load_approval(&env, invoice_id);
+
+        if approval.creator != creator {
+            panic!("Unauthorized");
+        }
+        if approval.status != InvoiceStatus::Pending && approval.status != InvoiceStatus::Approved {
+            panic!("Collateral can only be locked before funding");
+        }
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let token = Self::collateral_token(&env, invoice_id);
+        token::Client::new(&env, &token).transfer(&creator, &env.current_contract_address(), &amount);
+
+        let locked = Self::get_invoice_collateral(env.clone(), invoice_id) + amount;
+        let key = DataKey::Collateral(invoice_id);
+        env.storage().persistent().set(&key, &locked);
+        Self::extend_persistent_ttl(&env, &key);
+
+        let creator_key = DataKey::CreatorCollateral(creator.clone());
+        let creator_locked = Self::get_creator_collateral(env.clone(), creator.clone()) + amount;
+        env.storage().persistent().set(&creator_key, &creator_locked);
+        Self::extend_persistent_ttl(&env, &creator_key);
+
+        env.events().publish((symbol_short!("coll_lock"), invoice_id), (creator, amount, locked));
+
+        locked
+    }
+
+    /// Get the collateral locked against an invoice
+    pub fn get_invoice_collateral(env: Env, invoice_id: u64) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::Collateral(invoice_id)).unwrap_or(0)
+    }
+
+    /// Get the collateral a creator has locked across all their invoices
+    pub fn get_creator_collateral(env: Env, creator: Address) -> i128 {
+        Self::extend_instance_ttl(&env);
+
+        env.storage().persistent().get(&DataKey::CreatorCollateral(creator)).unwrap_or(0)
+    }
+
+    /// Update late payment fee settings (admin only)
+    pub fn update_late_fee_settings(
+        env: Env,
+        admin: Address,
+        late_fee_rate: Option<u32>,
+        late_fee_cap: Option<u32>,
+        late_fee_pool_share: Option<u32>,
+        treasury: Option<Address>,
+    ) {
+        Self::extend_instance_ttl(&env);
+
+        admin.require_auth();
+
+        let mut settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if admin != settings.admin {
+            panic!("Unauthorized");
+        }
+
+        if let Some(rate) = late_fee_rate {
+            if rate > 100 { // Max 1% per day
+                panic!("Late fee rate too high");
+            }
+            settings.late_fee_rate = rate;
+        }
+        if let Some(cap) = late_fee_cap {
+            if cap > 5000 { // Max 50%
+                panic!("Late fee cap too high");
+            }
+            settings.late_fee_cap = cap;
+        }
+        if let Some(share) = late_fee_pool_share {
+            if !is_valid_bps(share) {
+                panic!("Pool share cannot exceed 100%");
+            }
+            settings.late_fee_pool_share = share;
+        }
+        if let Some(treasury) = treasury {
+            settings.treasury = treasury;
+        }
+
+        env.storage().instance().set(&DataKey::MarketplaceSettings, &settings);
+
+        env.events().publish((symbol_short!("update"),), (admin,));
+    }
+
+    /// Validate invoice amount and due date against the marketplace limits
+    fn validate_terms(settings: &MarketplaceSettings, amount: i128, due_date: u64, current_time: u64) {
+        if amount < settings.min_invoice_amount || amount > settings.max_invoice_amount {
+            panic!("Invoice amount outside allowed range");
+        }
+
+        if due_date <= current_time {
+            panic!("Due date must be in the future");
+        }
+
+        // Check due date range
+        let days_until_due = time::days_between(current_time, due_date);
+        if days_until_due < settings.min_days_until_due || days_until_due > settings.max_days_until_due {
+            panic!("Due date outside allowed range");
+        }
+    }
+
+    /// Calculate late fee accrued on an invoice amount at a given time
+    fn calculate_late_fee(settings: &MarketplaceSettings, amount: i128, due_date: u64, at: u64) -> i128 {
+        if at <= due_date {
+            return 0;
+        }
+
+        // Penalty accrues per full day past the due date, up to the cap
+        let days_late = time::days_between(due_date, at);
+        let rate = pricing::late_fee_rate(days_late, settings.late_fee_rate, settings.late_fee_cap);
+
+        apply_bps(amount, rate, Rounding::Down)
+    }
+
+    /// Late fee accrued at `at` on installments past due, from the oldest one unpaid
+    fn installment_late_fee(settings: &MarketplaceSettings, schedule: &Vec<Installment>, paid: i128, at: u64) -> i128 {
+        match installment::next_unpaid(schedule, paid) {
+            Some(next) => Self::calculate_late_fee(settings, installment::arrears(schedule, paid, at), next.due_date, at),
+            None => 0,
+        }
+    }
+
+    /// Split late fee income between the pool and the treasury
+    fn split_late_fee(env: &Env, settings: &MarketplaceSettings, approval: &InvoiceApproval, late_fee: i128) {
+        if late_fee == 0 {
+            return;
+        }
+
+        let pool_share = apply_bps(late_fee, settings.late_fee_pool_share, Rounding::Down);
+        let treasury_share = late_fee - pool_share;
+
+        if pool_share > 0 {
+            Self::liquidity_pool(env, approval).record_late_fee(&env.current_contract_address(), &approval.invoice_id, &pool_share);
+        }
+
+        // The treasury's share accrues against it until collected off-chain
+        if treasury_share > 0 {
+            let key = DataKey::TreasuryReceivable(settings.treasury.clone());
+            let receivable: i128 = env.storage().persistent().get(&key).unwrap_or(0);
+            env.storage().persistent().set(&key, &(receivable + treasury_share));
+            Self::extend_persistent_ttl(env, &key);
+        }
+
+        env.events().publish(
+            (symbol_short!("latefee"),),
+            (approval.invoice_id, pool_share, settings.treasury.clone(), treasury_share)
+        );
+    }
+
+    /// An invoice's installment schedule, or a single installment due on the date its NFT records
+    fn installments(env: &Env, approval: &InvoiceApproval) -> Vec<Installment> {
+        let nft = Self::invoice_nft(env);
+        let schedule = nft.get_installments(&approval.invoice_id);
+        let due_date = nft.get_invoice_terms(&approval.invoice_id).due_date;
+        installment::or_single(env, schedule, approval.amount, due_date)
+    }
+
+    /// Creator's discount for paying a funded invoice at `at`, capped at what the
+    /// creator would receive after the pool's expected return and the platform fee
+    fn early_discount(env: &Env, settings: &MarketplaceSettings, approval: &InvoiceApproval, at: u64) -> i128 {
+        let (Some(funding_date), Some(funded_amount)) = (approval.funding_date, approval.funded_amount) else {
+            return 0;
+        };
+
+        let early_discount = Self::invoice_nft(env).get_early_discount(&approval.invoice_id);
+        let schedule = Self::get_early_discount_schedule(env.clone());
+        let rate = pricing::early_discount_rate(early_discount, schedule, funding_date, at, approval.due_date);
+        if rate == 0 {
+            return 0;
+        }
+
+        let residual = approval.amount
+            - pricing::expected_return(funded_amount, approval.discount_rate)
+            - apply_bps(approval.amount, settings.platform_fee, Rounding::Down);
+        apply_bps(approval.amount, rate, Rounding::Down).min(residual.max(0))
+    }
+
+    /// Early discount and late fee on an invoice settled in one payment at `at`;
+    /// `process_payment` and `amount_due` both price a full payment from these
+    fn full_payment_terms(env: &Env, settings: &MarketplaceSettings, approval: &InvoiceApproval, at: u64) -> (i128, i128) {
+        let schedule = Self::installments(env, approval);
+        let late_fee = Self::installment_late_fee(settings, &schedule, 0, at);
+        (Self::early_discount(env, settings, approval, at), late_fee)
+    }
+
+    fn required_collateral_for(env: &Env, approval: &InvoiceApproval) -> i128 {
+        if Self::invoice_nft(env).get_collateral_type(&approval.invoice_id).is_none() {
+            return 0;
+        }
+
+        let ratios = Self::get_collateral_ratios(env.clone());
+        let amount = approval.approved_amount.unwrap_or(approval.amount);
+        collateral::required_collateral(&ratios, approval.risk_score, amount)
+    }
+
+    /// Token collateral is locked in: the one registered for the invoice's currency
+    fn collateral_token(env: &Env, invoice_id: u64) -> Address {
+        let nft = Self::invoice_nft(env);
+        let currency = nft.get_invoice_currency(&invoice_id);
+        nft.get_asset(&currency).expect("Unsupported currency").token
+    }
+
+    /// Remove an invoice's collateral from escrow records, returning the amount held
+    fn take_collateral(env: &Env, approval: &InvoiceApproval) -> i128 {
+        let key = DataKey::Collateral(approval.invoice_id);
+        let locked: i128 = env.storage().persistent().get(&key).unwrap_or(0);
+        if locked == 0 {
+            return 0;
+        }
+        env.storage().persistent().remove(&key);
+
+        let creator_key = DataKey::CreatorCollateral(approval.creator.clone());
+        let creator_locked: i128 = env.storage().persistent().get(&creator_key).unwrap_or(0);
+        if creator_locked > locked {
+            env.storage().persistent().set(&creator_key, &(creator_locked - locked));
         } else {
-            base_rate // 5% for long term
+            env.storage().persistent().remove(&creator_key);
+        }
+
+        locked
+    }
+
+    /// Return an invoice's collateral to its creator
+    fn release_collateral(env: &Env, approval: &InvoiceApproval) {
+        let amount = Self::take_collateral(env, approval);
+        if amount == 0 {
+            return;
+        }
+
+        let token = Self::collateral_token(env, approval.invoice_id);
+        token::Client::new(env, &token).transfer(&env.current_contract_address(), &approval.creator, &amount);
+
+        env.events().publish((symbol_short!("coll_rel"), approval.invoice_id), (approval.creator.clone(), amount));
+    }
+
+    /// Move a defaulted invoice's collateral to the insurance reserve, or else to the pool that funded it
+    fn seize_collateral(env: &Env, approval: &InvoiceApproval) {
+        let amount = Self::take_collateral(env, approval);
+        if amount == 0 {
+            return;
+        }
+
+        let this = env.current_contract_address();
+        let token = token::Client::new(env, &Self::collateral_token(env, approval.invoice_id));
+        let recipient = match Self::get_insurance_reserve(env.clone()) {
+            Some(reserve) => {
+                token.transfer(&this, &reserve, &amount);
+                reserve
+            }
+            None => {
+                let pool = Self::liquidity_pool(env, approval);
+                token.transfer(&this, &pool.address, &amount);
+                pool.record_recovery(&this, &approval.invoice_id, &amount);
+                pool.address
+            }
+        };
+
+        env.events().publish((symbol_short!("coll_seiz"), approval.invoice_id), (recipient, amount));
+    }
+
+    /// Part of a requested amount that can be financed, e.g. without the invoice's tax
+    fn financeable_amount(env: &Env, invoice_id: u64, requested_amount: i128) -> i128 {
+        let financeable_amount = Self::invoice_nft(env).get_financeable_amount(&invoice_id);
+        requested_amount.min(financeable_amount)
+    }
+
+    /// Price an invoice with the invoice NFT's pricing grid
+    fn quote_terms(env: &Env, creator: &Address, amount: i128, due_date: u64, risk_grade: RiskGrade) -> Quote {
+        let params = QuoteParams { creator: creator.clone(), amount, due_date, risk_grade };
+        Self::invoice_nft(env).quote(&params)
+    }
+
+    /// Refuse invoices a verifier has flagged as suspected fraud until they are cleared
+    fn require_not_flagged(env: &Env, invoice_id: u64) {
+        if Self::invoice_nft(env).is_flagged(&invoice_id) {
+            panic!("Invoice flagged for fraud");
         }
     }
 
-    /// Helper function to try funding an approved invoice
+    /// Helper function to fund an approved invoice from its pool, once its collateral
+    /// is locked and the pool has the liquidity
     fn try_fund_invoice(env: &Env, invoice_id: u64, approval: &mut InvoiceApproval) {
+        Self::require_not_flagged(env, invoice_id);
+
         let settings: MarketplaceSettings = env.storage().instance()
             .get(&DataKey::MarketplaceSettings)
             .expect("Marketplace not initialized");
 
         if let Some(approved_amount) = approval.approved_amount {
-            let funding_amount = approved_amount * settings.max_funding_ratio as i128 / 100;
+            // Invoices backed by collateral wait until the creator has locked enough of it
+            let locked = Self::get_invoice_collateral(env.clone(), invoice_id);
+            if locked < Self::required_collateral_for(env, approval) {
+                return;
+            }
+
+            let funding_amount = apply_percent(approved_amount, settings.max_funding_ratio, Rounding::Down);
             
             // Calculate expected return based on discount rate
-            let days_until_due = (approval.due_date - env.ledger().timestamp()) / 86400;
-            let expected_return = funding_amount + (funding_amount * approval.discount_rate as i128 * days_until_due as i128 / (365 * 10000));
+            let expected_return = pricing::expected_return(funding_amount, approval.discount_rate);
+
+            // Funding comes from a pool holding the invoice's asset and covering its grade;
+            // repayment, late fees and defaults later settle against the same pool
+            let pool = Self::select_pool(env, approval);
+            let pool_client = LiquidityPoolClient::new(env, &pool);
+
+            // The invoice stays approved until the pool has the liquidity to advance it
+            if pool_client.get_available_liquidity() < funding_amount {
+                return;
+            }
+            pool_client.fund_invoice(&env.current_contract_address(), &invoice_id, &funding_amount, &expected_return);
+
+            let pool_key = DataKey::FundingPool(invoice_id);
+            env.storage().persistent().set(&pool_key, &pool);
+            Self::extend_persistent_ttl(env, &pool_key);
 
-            // TODO: In a real implementation, this would call the liquidity pool contract
-            // to check availability and execute the funding
-            // For now, we simulate successful funding
-            
             approval.status = InvoiceStatus::Funded;
             approval.funding_date = Some(env.ledger().timestamp());
             approval.funded_amount = Some(funding_amount);
@@ -581,42 +1702,176 @@ impl MarketplaceContract {
             Self::move_invoice_between_lists(env, invoice_id, &DataKey::ApprovedInvoices, &DataKey::FundedInvoices);
 
             // Store updated approval
-            env.storage().persistent().set(&DataKey::InvoiceApproval(invoice_id), approval);
+            Self::save_approval(env, approval);
+
+            // The invoice NFT takes payments against its balance once funded
+            Self::invoice_nft(env).update_status(&invoice_id, &InvoiceStatus::Funded, &env.current_contract_address());
 
             env.events().publish(
                 (symbol_short!("funded"),),
-                (invoice_id, funding_amount, expected_return)
+                (invoice_id, funding_amount, expected_return, pool)
             );
         }
     }
 
+    /// Helper function to apply overdue and default transitions that are due
+    fn advance_lifecycle(env: &Env, invoice_id: u64) -> InvoiceStatus {
+        let mut approval = Self::load_approval(env, invoice_id);
+
+        let settings: MarketplaceSettings = env.storage().instance()
+            .get(&DataKey::MarketplaceSettings)
+            .expect("Marketplace not initialized");
+
+        if !approval.status.is_outstanding() {
+            return approval.status;
+        }
+
+        // Invoices paid in installments fall overdue on the oldest installment still unpaid
+        let schedule = Self::installments(env, &approval);
+        let paid = Self::invoice_nft(env).get_amount_paid(&invoice_id);
+        let Some(next) = installment::next_unpaid(&schedule, paid) else {
+            return approval.status;
+        };
+        let due_date = next.due_date;
+
+        let current_time = env.ledger().timestamp();
+        let overdue_at = due_date + time::days_to_seconds(settings.grace_period_days);
+        let default_at = overdue_at + time::days_to_seconds(settings.default_period_days);
+        let this = env.current_contract_address();
+
+        if approval.status != InvoiceStatus::Overdue && current_time > overdue_at {
+            approval.status = InvoiceStatus::Overdue;
+            Self::move_invoice_between_lists(env, invoice_id, &DataKey::FundedInvoices, &DataKey::OverdueInvoices);
+            Self::save_approval(env, &approval);
+
+            Self::invoice_nft(env).update_status(&invoice_id, &InvoiceStatus::Overdue, &this);
+
+            env.events().publish((symbol_short!("overdue"),), (invoice_id, due_date));
+        }
+
+        if approval.status == InvoiceStatus::Overdue && current_time > default_at {
+            approval.status = InvoiceStatus::Defaulted;
+            Self::move_invoice_between_lists(env, invoice_id, &DataKey::OverdueInvoices, &DataKey::DefaultedInvoices);
+            Self::save_approval(env, &approval);
+
+            let nft = Self::invoice_nft(env);
+            nft.update_status(&invoice_id, &InvoiceStatus::Defaulted, &this);
+
+            // Penalize the creator's trust score; the NFT admin registers the marketplace as a trust oracle
+            let trust_score = nft.get_trust_score(&approval.creator);
+            let new_score = trust_score.saturating_sub(settings.default_trust_penalty);
+            nft.update_trust_score(&approval.creator, &new_score, &this);
+
+            // Let the pool write off the funded amount, then recover what collateral it can
+            let loss = Self::liquidity_pool(env, &approval).record_default(&this, &invoice_id);
+            Self::seize_collateral(env, &approval);
+
+            env.events().publish(
+                (symbol_short!("default"),),
+                (invoice_id, approval.creator.clone(), loss)
+            );
+        }
+
+        approval.status
+    }
+
+    fn extend_instance_ttl(env: &Env) {
+        env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    fn extend_persistent_ttl(env: &Env, key: &DataKey) {
+        env.storage().persistent().extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+    }
+
+    /// Read an approval in the current layout, converting one left in an older layout
+    fn read_approval(env: &Env, invoice_id: u64) -> Option<InvoiceApproval> {
+        let value: Val = env.storage().persistent().get(&DataKey::InvoiceApproval(invoice_id))?;
+        match InvoiceApproval::try_from_val(env, &value) {
+            Ok(approval) => Some(approval),
+            Err(_) => Some(legacy::v1_approval_from_val(env, &value)),
+        }
+    }
+
+    /// Helper function to load an approval, extending its TTL while it is still live
+    fn load_approval(env: &Env, invoice_id: u64) -> InvoiceApproval {
+        let key = DataKey::InvoiceApproval(invoice_id);
+        let approval = Self::read_approval(env, invoice_id).expect("Invoice not found");
+        if approval.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+        approval
+    }
+
+    /// Helper function to store an approval; settled approvals keep their remaining TTL and expire
+    fn save_approval(env: &Env, approval: &InvoiceApproval) {
+        let key = DataKey::InvoiceApproval(approval.invoice_id);
+        env.storage().persistent().set(&key, approval);
+        if approval.status.is_live() {
+            Self::extend_persistent_ttl(env, &key);
+        }
+    }
+
+    /// Helper function to find the list an invoice in a given status belongs to
+    fn list_key(status: &InvoiceStatus) -> Option<DataKey> {
+        match status {
+            InvoiceStatus::Pending => Some(DataKey::PendingInvoices),
+            InvoiceStatus::Approved => Some(DataKey::ApprovedInvoices),
+            InvoiceStatus::Funded | InvoiceStatus::PartiallyPaid => Some(DataKey::FundedInvoices),
+            InvoiceStatus::Completed => Some(DataKey::CompletedInvoices),
+            InvoiceStatus::Overdue => Some(DataKey::OverdueInvoices),
+            InvoiceStatus::Defaulted => Some(DataKey::DefaultedInvoices),
+            InvoiceStatus::Rejected | InvoiceStatus::Cancelled => None,
+        }
+    }
+
+    /// Helper function to build a client for the invoice NFT contract
+    fn invoice_nft(env: &Env) -> InvoiceNftClient<'_> {
+        let address: Address = env.storage().instance()
+            .get(&DataKey::InvoiceNftContract)
+            .expect("Marketplace not initialized");
+        InvoiceNftClient::new(env, &address)
+    }
+
+    /// Helper function to pick the pool for an invoice's asset and risk grade
+    fn select_pool(env: &Env, approval: &InvoiceApproval) -> Address {
+        let currency = Self::invoice_nft(env).get_invoice_currency(&approval.invoice_id);
+
+        let factory: Option<Address> = env.storage().instance().get(&DataKey::PoolFactory);
+        if let Some(factory) = factory {
+            if let Some(pool) = PoolFactoryClient::new(env, &factory).find_pool(&currency, &approval.risk_score) {
+                return pool;
+            }
+        }
+
+        env.storage().instance()
+            .get(&DataKey::AssetPool(currency))
+            .expect("No pool for asset")
+    }
+
+    /// Helper function to build a client for the pool that funded an invoice
+    fn liquidity_pool<'a>(env: &'a Env, approval: &InvoiceApproval) -> LiquidityPoolClient<'a> {
+        let pool: Address = env.storage().persistent()
+            .get(&DataKey::FundingPool(approval.invoice_id))
+            .unwrap_or_else(|| Self::select_pool(env, approval));
+        LiquidityPoolClient::new(env, &pool)
+    }
+
     /// Helper function to move invoice between lists
     fn move_invoice_between_lists(env: &Env, invoice_id: u64, from_key: &DataKey, to_key: &DataKey) {
         // Remove from source list
         Self::remove_from_list(env, invoice_id, from_key);
 
         // Add to destination list
-        let mut to_list: Vec<u64> = env.storage().persistent()
-            .get(to_key)
-            .unwrap_or(Vec::new(env));
-        to_list.push_back(invoice_id);
-        env.storage().persistent().set(to_key, &to_list);
+        index::push(env, to_key, invoice_id);
     }
 
     /// Helper function to remove invoice from a list
     fn remove_from_list(env: &Env, invoice_id: u64, list_key: &DataKey) {
-        let mut list: Vec<u64> = env.storage().persistent()
-            .get(list_key)
-            .unwrap_or(Vec::new(env));
-
-        if let Some(index) = list.iter().position(|x| x == invoice_id) {
-            list.remove(index as u32);
-            env.storage().persistent().set(list_key, &list);
-        }
+        index::remove(env, list_key, invoice_id);
     }
 
     /// Helper function to update platform statistics
-    fn update_platform_stats(env: &Env, payment_amount: i128, platform_fee: i128) {
+    fn update_platform_stats(env: &Env, payment_amount: i128, platform_fee: i128, settled: bool) {
         let mut stats: MarketplaceStats = env.storage().instance()
             .get(&DataKey::PlatformStats)
             .unwrap_or(MarketplaceStats {
@@ -629,7 +1884,9 @@ impl MarketplaceContract {
                 total_liquidity_provided: 0,
             });
 
-        stats.total_invoices_processed += 1;
+        if settled {
+            stats.total_invoices_processed += 1;
+        }
         stats.total_volume_processed += payment_amount;
         stats.total_fees_collected += platform_fee;
 
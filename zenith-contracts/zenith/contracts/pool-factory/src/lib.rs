@@ -0,0 +1,291 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype, symbol_short,
+    Address, BytesN, Env, Vec
+};
+
+use zenith_common::math::is_valid_bps;
+use zenith_common::ttl::{INSTANCE_BUMP_AMOUNT, INSTANCE_LIFETIME_THRESHOLD};
+
+pub use zenith_common::{AssetConfig, Currency, RiskBand, RiskGrade};
+
+/// Storage layout version written by this build; bump it alongside a new `migrate` step
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    SchemaVersion,
+    InvoiceNftContract,
+    Marketplace,
+    PoolWasmHash,
+    PoolCounter,
+    Pools(Currency),
+}
+
+/// A registered pool and the invoices it funds
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct PoolInfo {
+    pub pool: Address,
+    pub currency: Currency,
+    pub risk_band: RiskBand,
+}
+
+/// Configuration for a newly deployed pool
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolParams {
+    pub currency: Currency,
+    pub risk_band: RiskBand,
+    pub base_apr: u32, // basis points
+    pub early_withdrawal_penalty: u32, // basis points
+    pub min_stake_amount: i128,
+}
+
+#[contractclient(name = "InvoiceNftClient")]
+pub trait InvoiceNftInterface {
+    fn get_asset(env: Env, currency: Currency) -> Option<AssetConfig>;
+}
+
+#[contractclient(name = "LiquidityPoolClient")]
+pub trait LiquidityPoolInterface {
+    fn initialize(env: Env, admin: Address, asset: Address, base_apr: u32, min_stake_amount: i128);
+    fn update_settings(
+        env: Env,
+        admin: Address,
+        base_apr: Option<u32>,
+        early_withdrawal_penalty: Option<u32>,
+        min_stake_amount: Option<i128>,
+    );
+    fn set_marketplace(env: Env, admin: Address, marketplace: Address);
+    fn get_asset(env: Env) -> Address;
+}
+
+#[contract]
+pub struct PoolFactoryContract;
+
+#[contractimpl]
+impl PoolFactoryContract {
+    /// Initialize the factory with the pool code to deploy and the contracts pools work with
+    pub fn initialize(
+        env: Env,
+        admin: Address,
+        invoice_nft_contract: Address,
+        marketplace: Address,
+        pool_wasm_hash: BytesN<32>,
+    ) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::InvoiceNftContract, &invoice_nft_contract);
+        env.storage().instance().set(&DataKey::Marketplace, &marketplace);
+        env.storage().instance().set(&DataKey::PoolWasmHash, &pool_wasm_hash);
+        env.storage().instance().set(&DataKey::PoolCounter, &0u64);
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Self::extend_instance_ttl(&env);
+
+        env.events().publish((symbol_short!("init"),), (admin, pool_wasm_hash));
+    }
+
+    /// Replace the contract code, keeping its storage (admin only)
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish((symbol_short!("upgrade"),), new_wasm_hash);
+    }
+
+    /// Bring stored data up to the layout this build expects (admin only)
+    pub fn migrate(env: Env, admin: Address) -> u32 {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let from_version = Self::get_schema_version(env.clone());
+        if from_version > SCHEMA_VERSION {
+            panic!("Unsupported schema version");
+        }
+
+        // Version 1 is the first layout, so there is nothing to rewrite yet
+        env.storage().instance().set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+
+        env.events().publish((symbol_short!("migrate"),), (from_version, SCHEMA_VERSION));
+
+        SCHEMA_VERSION
+    }
+
+    /// Get the storage layout version (0 for storage written before versioning)
+    pub fn get_schema_version(env: Env) -> u32 {
+        Self::extend_instance_ttl(&env);
+        env.storage().instance()
+            .get(&DataKey::SchemaVersion)
+            .unwrap_or(0)
+    }
+
+    /// Set the pool code used for future deployments; existing pools are unaffected (admin only)
+    pub fn set_pool_wasm(env: Env, admin: Address, pool_wasm_hash: BytesN<32>) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        env.storage().instance().set(&DataKey::PoolWasmHash, &pool_wasm_hash);
+
+        env.events().publish((symbol_short!("pool_wasm"),), pool_wasm_hash);
+    }
+
+    /// Deploy and register a pool for a currency and risk band (admin only).
+    /// The admin becomes the pool's admin and the marketplace its operator.
+    pub fn deploy_pool(env: Env, admin: Address, params: PoolParams) -> Address {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        if params.min_stake_amount <= 0 || !is_valid_bps(params.early_withdrawal_penalty) {
+            panic!("Invalid pool settings");
+        }
+
+        let asset = Self::registered_asset(&env, &params.currency);
+        Self::check_band_available(&env, &params.currency, &params.risk_band);
+
+        // Derive the pool address from a per-factory counter
+        let mut counter: u64 = env.storage().instance()
+            .get(&DataKey::PoolCounter)
+            .unwrap_or(0);
+        counter += 1;
+        env.storage().instance().set(&DataKey::PoolCounter, &counter);
+
+        let mut salt = [0u8; 32];
+        salt[24..].copy_from_slice(&counter.to_be_bytes());
+
+        let wasm_hash: BytesN<32> = env.storage().instance()
+            .get(&DataKey::PoolWasmHash)
+            .expect("Factory not initialized");
+        let pool = env.deployer()
+            .with_current_contract(BytesN::from_array(&env, &salt))
+            .deploy_v2(wasm_hash, ());
+
+        let marketplace: Address = env.storage().instance()
+            .get(&DataKey::Marketplace)
+            .expect("Factory not initialized");
+
+        let client = LiquidityPoolClient::new(&env, &pool);
+        client.initialize(&admin, &asset.token, &params.base_apr, &params.min_stake_amount);
+        client.update_settings(&admin, &None, &Some(params.early_withdrawal_penalty), &None);
+        client.set_marketplace(&admin, &marketplace);
+
+        Self::add_pool(&env, PoolInfo { pool: pool.clone(), currency: params.currency, risk_band: params.risk_band });
+
+        pool
+    }
+
+    /// Register a pool deployed outside the factory, such as one that predates it (admin only)
+    pub fn register_pool(env: Env, admin: Address, currency: Currency, risk_band: RiskBand, pool: Address) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let asset = Self::registered_asset(&env, &currency);
+        if LiquidityPoolClient::new(&env, &pool).get_asset() != asset.token {
+            panic!("Pool asset mismatch");
+        }
+        Self::check_band_available(&env, &currency, &risk_band);
+
+        Self::add_pool(&env, PoolInfo { pool, currency, risk_band });
+    }
+
+    /// Stop routing new invoices to a pool; invoices it already funded stay with it (admin only)
+    pub fn remove_pool(env: Env, admin: Address, currency: Currency, pool: Address) {
+        Self::extend_instance_ttl(&env);
+
+        Self::require_admin(&env, &admin);
+
+        let key = DataKey::Pools(currency.clone());
+        let mut pools = Self::load_pools(&env, &currency);
+        let index = pools.iter()
+            .position(|info| info.pool == pool)
+            .expect("Pool not found");
+        pools.remove(index as u32);
+        env.storage().instance().set(&key, &pools);
+
+        env.events().publish((symbol_short!("pool_rm"), currency), pool);
+    }
+
+    /// Find the pool that funds invoices of a currency and grade
+    pub fn find_pool(env: Env, currency: Currency, risk_grade: RiskGrade) -> Option<Address> {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_pools(&env, &currency)
+            .iter()
+            .find(|info| info.risk_band.contains(risk_grade))
+            .map(|info| info.pool)
+    }
+
+    /// Get the pools registered for a currency
+    pub fn get_pools(env: Env, currency: Currency) -> Vec<PoolInfo> {
+        Self::extend_instance_ttl(&env);
+
+        Self::load_pools(&env, &currency)
+    }
+
+    fn require_admin(env: &Env, admin: &Address) {
+        admin.require_auth();
+
+        let stored_admin: Address = env.storage().instance()
+            .get(&DataKey::Admin)
+            .expect("Factory not initialized");
+
+        if *admin != stored_admin {
+            panic!("Unauthorized");
+        }
+    }
+
+    fn extend_instance_ttl(env: &Env) {
+        env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+    }
+
+    /// Look up a currency in the invoice NFT's asset registry
+    fn registered_asset(env: &Env, currency: &Currency) -> AssetConfig {
+        let address: Address = env.storage().instance()
+            .get(&DataKey::InvoiceNftContract)
+            .expect("Factory not initialized");
+        InvoiceNftClient::new(env, &address)
+            .get_asset(currency)
+            .expect("Unsupported currency")
+    }
+
+    /// Require a well-formed band that no other pool for the currency covers
+    fn check_band_available(env: &Env, currency: &Currency, risk_band: &RiskBand) {
+        if !risk_band.is_valid() {
+            panic!("Invalid risk band");
+        }
+
+        if Self::load_pools(env, currency).iter().any(|info| info.risk_band.overlaps(risk_band)) {
+            panic!("Risk band overlaps existing pool");
+        }
+    }
+
+    fn load_pools(env: &Env, currency: &Currency) -> Vec<PoolInfo> {
+        env.storage().instance()
+            .get(&DataKey::Pools(currency.clone()))
+            .unwrap_or(Vec::new(env))
+    }
+
+    fn add_pool(env: &Env, info: PoolInfo) {
+        let mut pools = Self::load_pools(env, &info.currency);
+        pools.push_back(info.clone());
+        env.storage().instance().set(&DataKey::Pools(info.currency.clone()), &pools);
+
+        env.events().publish((symbol_short!("pool"), info.currency), (info.pool, info.risk_band));
+    }
+}
+
+mod test;
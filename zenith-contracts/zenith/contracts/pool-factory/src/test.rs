@@ -0,0 +1,291 @@
+#![cfg(test)]
+
+use super::*;
+use soroban_sdk::{testutils::Address as _, Address, Env};
+use invoice_nft::{InvoiceNFTContract, InvoiceNFTContractClient};
+use liquidity_pool::{LiquidityPoolContract, LiquidityPoolContractClient};
+
+/// Release build of the liquidity pool the factory deploys, see `scripts/build-test-fixtures.sh`
+const LIQUIDITY_POOL_WASM: &[u8] = include_bytes!("../testdata/liquidity_pool.wasm");
+
+/// Release build of this contract from the current sources, see `scripts/build-test-fixtures.sh`
+const POOL_FACTORY_WASM: &[u8] = include_bytes!("../testdata/pool_factory.wasm");
+
+struct Setup<'a> {
+    factory: PoolFactoryContractClient<'a>,
+    invoice_nft: InvoiceNFTContractClient<'a>,
+    admin: Address,
+    marketplace: Address,
+    usdc: Address,
+}
+
+fn setup<'a>(env: &Env) -> Setup<'a> {
+    // Deploy a real invoice NFT holding the asset registry, with USDC registered
+    let admin = Address::generate(env);
+    let invoice_nft = InvoiceNFTContractClient::new(env, &env.register(InvoiceNFTContract, ()));
+    invoice_nft.initialize(&admin);
+
+    let usdc = Address::generate(env);
+    let config = AssetConfig {
+        token: usdc.clone(),
+        decimals: 7,
+        min_amount: 1_0000000,
+        max_amount: 1000000_0000000,
+    };
+    invoice_nft.set_asset(&admin, &Currency::Usdc, &config);
+
+    let marketplace = Address::generate(env);
+    let pool_wasm_hash = env.deployer().upload_contract_wasm(LIQUIDITY_POOL_WASM);
+    let factory = PoolFactoryContractClient::new(env, &env.register(PoolFactoryContract, ()));
+    factory.initialize(&admin, &invoice_nft.address, &marketplace, &pool_wasm_hash);
+
+    Setup { factory, invoice_nft, admin, marketplace, usdc }
+}
+
+fn pool_params(currency: Currency, best: RiskGrade, worst: RiskGrade) -> PoolParams {
+    PoolParams {
+        currency,
+        risk_band: RiskBand { best, worst },
+        base_apr: 800,
+        early_withdrawal_penalty: 500,
+        min_stake_amount: 50_0000000,
+    }
+}
+
+#[test]
+#[should_panic(expected = "Already initialized")]
+fn test_initialize_twice() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let pool_wasm_hash = env.deployer().upload_contract_wasm(LIQUIDITY_POOL_WASM);
+    setup.factory.initialize(&Address::generate(&env), &setup.invoice_nft.address, &Address::generate(&env), &pool_wasm_hash);
+}
+
+#[test]
+fn test_deploy_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let params = pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::B);
+    let pool_address = setup.factory.deploy_pool(&setup.admin, &params);
+
+    let pools = setup.factory.get_pools(&Currency::Usdc);
+    assert_eq!(pools.len(), 1);
+    assert_eq!(
+        pools.get(0).unwrap(),
+        PoolInfo { pool: pool_address.clone(), currency: Currency::Usdc, risk_band: params.risk_band.clone() }
+    );
+
+    // The pool holds the registered token and uses the requested settings
+    let pool = LiquidityPoolContractClient::new(&env, &pool_address);
+    assert_eq!(pool.get_asset(), setup.usdc);
+
+    let staker = Address::generate(&env);
+    let position_id = pool.stake(&staker, &50_0000000i128, &7u64);
+    let position = pool.get_user_stakes(&staker, &None, &10u32).positions.get(0).unwrap();
+    assert_eq!(position.apr, 800);
+
+    let (principal, _) = pool.withdraw(&staker, &position_id);
+    assert_eq!(principal, 47_5000000); // 5% early withdrawal penalty
+
+    // The marketplace may fund invoices from it
+    pool.stake(&staker, &1000_0000000i128, &30u64);
+    pool.fund_invoice(&setup.marketplace, &1u64, &100_0000000i128, &105_0000000i128);
+    assert_eq!(pool.get_invoice_funding(&1u64).unwrap().funded_amount, 100_0000000);
+}
+
+#[test]
+fn test_find_pool_by_grade() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let prime = setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::B));
+    let high_yield = setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::C, RiskGrade::C));
+    assert_ne!(prime, high_yield);
+
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::A), Some(prime.clone()));
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::B), Some(prime));
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::C), Some(high_yield));
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::D), None);
+    assert_eq!(setup.factory.find_pool(&Currency::Eurc, &RiskGrade::A), None);
+}
+
+#[test]
+#[should_panic(expected = "Risk band overlaps existing pool")]
+fn test_deploy_pool_overlapping_band() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::B));
+    setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::B, RiskGrade::D));
+}
+
+#[test]
+#[should_panic(expected = "Invalid risk band")]
+fn test_deploy_pool_invalid_band() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::D, RiskGrade::A));
+}
+
+#[test]
+#[should_panic(expected = "Invalid pool settings")]
+fn test_deploy_pool_invalid_settings() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let mut params = pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::D);
+    params.early_withdrawal_penalty = 10001;
+    setup.factory.deploy_pool(&setup.admin, &params);
+}
+
+#[test]
+#[should_panic(expected = "Unsupported currency")]
+fn test_deploy_pool_unregistered_currency() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Eurc, RiskGrade::A, RiskGrade::D));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_deploy_pool_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    setup.factory.deploy_pool(&Address::generate(&env), &pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::D));
+}
+
+#[test]
+fn test_register_and_remove_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+
+    // Adopt a pool deployed before the factory existed
+    let existing = LiquidityPoolContractClient::new(&env, &env.register(LiquidityPoolContract, ()));
+    existing.initialize(&setup.admin, &setup.usdc, &1000u32, &100_0000000i128);
+
+    let band = RiskBand { best: RiskGrade::A, worst: RiskGrade::D };
+    setup.factory.register_pool(&setup.admin, &Currency::Usdc, &band, &existing.address);
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::C), Some(existing.address.clone()));
+
+    setup.factory.remove_pool(&setup.admin, &Currency::Usdc, &existing.address);
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::C), None);
+    assert_eq!(setup.factory.get_pools(&Currency::Usdc).len(), 0);
+}
+
+#[test]
+#[should_panic(expected = "Pool asset mismatch")]
+fn test_register_pool_asset_mismatch() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+
+    let eurc = Address::generate(&env);
+    let config = AssetConfig {
+        token: eurc,
+        decimals: 7,
+        min_amount: 1_0000000,
+        max_amount: 1000000_0000000,
+    };
+    setup.invoice_nft.set_asset(&setup.admin, &Currency::Eurc, &config);
+
+    // A USDC pool cannot serve EURC invoices
+    let usdc_pool = LiquidityPoolContractClient::new(&env, &env.register(LiquidityPoolContract, ()));
+    usdc_pool.initialize(&setup.admin, &setup.usdc, &1000u32, &100_0000000i128);
+
+    let band = RiskBand { best: RiskGrade::A, worst: RiskGrade::D };
+    setup.factory.register_pool(&setup.admin, &Currency::Eurc, &band, &usdc_pool.address);
+}
+
+#[test]
+#[should_panic(expected = "Pool not found")]
+fn test_remove_unknown_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    setup.factory.remove_pool(&setup.admin, &Currency::Usdc, &Address::generate(&env));
+}
+
+#[test]
+fn test_set_pool_wasm() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let pool_wasm_hash = env.deployer().upload_contract_wasm(LIQUIDITY_POOL_WASM);
+    setup.factory.set_pool_wasm(&setup.admin, &pool_wasm_hash);
+
+    let pool = setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::D));
+    assert_eq!(LiquidityPoolContractClient::new(&env, &pool).get_asset(), setup.usdc);
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_set_pool_wasm_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let pool_wasm_hash = env.deployer().upload_contract_wasm(LIQUIDITY_POOL_WASM);
+    setup.factory.set_pool_wasm(&Address::generate(&env), &pool_wasm_hash);
+}
+
+#[test]
+fn test_upgrade_keeps_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let pool = setup.factory.deploy_pool(&setup.admin, &pool_params(Currency::Usdc, RiskGrade::A, RiskGrade::D));
+
+    let wasm_hash = env.deployer().upload_contract_wasm(POOL_FACTORY_WASM);
+    setup.factory.upgrade(&setup.admin, &wasm_hash);
+
+    // Calls are now served by the uploaded wasm against the existing storage
+    assert_eq!(setup.factory.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(setup.factory.find_pool(&Currency::Usdc, &RiskGrade::B), Some(pool));
+}
+
+#[test]
+#[should_panic(expected = "Unauthorized")]
+fn test_upgrade_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+    let wasm_hash = env.deployer().upload_contract_wasm(POOL_FACTORY_WASM);
+    setup.factory.upgrade(&Address::generate(&env), &wasm_hash);
+}
+
+#[test]
+fn test_migrate_stamps_unversioned_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let setup = setup(&env);
+
+    // Simulate storage written before the schema version was tracked
+    env.as_contract(&setup.factory.address, || {
+        env.storage().instance().remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(setup.factory.get_schema_version(), 0);
+
+    assert_eq!(setup.factory.migrate(&setup.admin), SCHEMA_VERSION);
+    assert_eq!(setup.factory.get_schema_version(), SCHEMA_VERSION);
+}
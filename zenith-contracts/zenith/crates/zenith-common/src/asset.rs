@@ -0,0 +1,27 @@
+//! Supported invoice assets and their limits
+
+use soroban_sdk::{contracttype, Address};
+
+/// Most decimal places a registered token may use
+pub const MAX_DECIMALS: u32 = 18;
+
+/// Token contract and invoice limits for a supported currency.
+/// Amounts are in the token's smallest unit.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct AssetConfig {
+    pub token: Address,
+    pub decimals: u32,
+    pub min_amount: i128,
+    pub max_amount: i128,
+}
+
+/// Whether an asset config has a positive, ordered amount range and sane decimals
+pub fn is_valid_config(config: &AssetConfig) -> bool {
+    config.decimals <= MAX_DECIMALS && config.min_amount > 0 && config.min_amount <= config.max_amount
+}
+
+/// Whether an invoice amount falls inside the asset's limits
+pub fn is_within_limits(config: &AssetConfig, amount: i128) -> bool {
+    amount >= config.min_amount && amount <= config.max_amount
+}
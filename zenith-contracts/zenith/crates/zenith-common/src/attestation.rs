@@ -0,0 +1,49 @@
+//! Claims registered verifiers make about an invoice after matching it against
+//! an external system, such as an accounting ledger or a payment processor
+//!
+//! An attestation counts until it expires or is revoked. One that matches the
+//! invoice's full amount with enough confidence marks the invoice's grade as
+//! integrated and its creator as verified.
+
+use soroban_sdk::{contracttype, Address, String, Symbol};
+
+/// Most attestations an invoice may carry, counting revoked ones
+pub const MAX_ATTESTATIONS: u32 = 10;
+
+/// Confidence, in basis points, an attestation needs to verify an invoice
+pub const MIN_CONFIDENCE: u32 = 8000;
+
+/// What a verifier submits about an invoice
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct AttestationClaim {
+    pub source: Symbol, // external system, e.g. "quickbooks", "stripe"
+    pub external_id: String, // the invoice's id in the source system
+    pub matched_amount: i128,
+    pub confidence: u32, // basis points
+    pub expires_at: u64,
+}
+
+/// A verifier's claim that an invoice matches a record in an external system
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct Attestation {
+    pub verifier: Address,
+    pub source: Symbol, // external system, e.g. "quickbooks", "stripe"
+    pub external_id: String, // the invoice's id in the source system
+    pub matched_amount: i128,
+    pub confidence: u32, // basis points
+    pub attested_at: u64,
+    pub expires_at: u64,
+    pub revoked: bool,
+}
+
+/// Whether an attestation is neither expired nor revoked at `now`
+pub fn is_current(attestation: &Attestation, now: u64) -> bool {
+    !attestation.revoked && attestation.expires_at > now
+}
+
+/// Whether an attestation confirms an invoice of `amount` in full
+pub fn is_conclusive(attestation: &Attestation, amount: i128) -> bool {
+    attestation.confidence >= MIN_CONFIDENCE && attestation.matched_amount == amount
+}
@@ -0,0 +1,25 @@
+//! Collateral creators lock against invoices that declare a `collateral_type`
+//!
+//! The share of the invoice amount to lock grows with the invoice's risk grade.
+//! Collateral is returned when the invoice is settled and seized if it defaults.
+
+use soroban_sdk::{vec, Env, Vec};
+
+use crate::math::{apply_bps, is_valid_bps, Rounding};
+use crate::risk::{RiskGrade, RISK_GRADE_COUNT};
+
+/// Ratios used until an admin configures them, in basis points for grades A..D
+pub fn default_ratios(env: &Env) -> Vec<u32> {
+    vec![env, 0, 500, 1000, 2000]
+}
+
+/// Whether ratios cover every grade, each at most the full invoice amount
+pub fn is_valid_ratios(ratios: &Vec<u32>) -> bool {
+    ratios.len() == RISK_GRADE_COUNT && ratios.iter().all(is_valid_bps)
+}
+
+/// Collateral to lock against `amount` at `grade`
+pub fn required_collateral(ratios: &Vec<u32>, grade: RiskGrade, amount: i128) -> i128 {
+    let ratio = ratios.get(grade.index()).expect("Invalid collateral ratios");
+    apply_bps(amount, ratio, Rounding::Down)
+}
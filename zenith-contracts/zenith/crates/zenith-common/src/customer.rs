@@ -0,0 +1,85 @@
+//! Customer identity kept off-chain behind salted hash commitments
+//!
+//! Invoices only store hashes of the customer's name and email. The creator
+//! keeps the plaintext and a per-invoice salt, and can disclose both to anyone
+//! who needs to check them against the stored commitment. The debtor id is
+//! salted too, with a secret the customer holds and gives to the creators who
+//! invoice them.
+
+use soroban_sdk::{contracttype, Bytes, BytesN, Env, String};
+
+use crate::fingerprint::normalize;
+
+/// Longest customer name or email, in bytes, that can be committed to
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// Salted commitments to an invoice's customer; the plaintext stays off-chain
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct CustomerCommitment {
+    pub name_hash: BytesN<32>, // sha256(salt || name)
+    pub email_hash: BytesN<32>, // sha256(salt || email)
+    pub debtor_id: BytesN<32>, // sha256(debtor_salt || normalized email), see `debtor_id`
+    pub encrypted_data: Option<String>, // reference to an encrypted copy of the details
+}
+
+/// Customer detail covered by a commitment
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum CustomerField {
+    Name,
+    Email,
+}
+
+/// Salted hash of a customer detail, over its exact bytes
+pub fn commit(env: &Env, salt: &BytesN<32>, value: &String) -> BytesN<32> {
+    let mut raw = [0u8; MAX_VALUE_LEN];
+    let value = copy_value(value, &mut raw);
+
+    let mut data = Bytes::from(salt);
+    data.extend_from_slice(value);
+
+    env.crypto().sha256(&data).into()
+}
+
+/// Pseudonymous id for a debtor: the hash of their email, lowercased and without whitespace,
+/// under a salt the debtor keeps off-chain. Creators the debtor shares the salt with derive the
+/// same id, so repayment history follows the debtor, but nobody can confirm a guessed email.
+pub fn debtor_id(env: &Env, debtor_salt: &BytesN<32>, email: &String) -> BytesN<32> {
+    let mut raw = [0u8; MAX_VALUE_LEN];
+    let email = copy_value(email, &mut raw);
+
+    let mut normalized = [0u8; MAX_VALUE_LEN];
+    let len = normalize(email, &mut normalized);
+
+    let mut data = Bytes::from(debtor_salt);
+    data.extend_from_slice(&normalized[..len]);
+
+    env.crypto().sha256(&data).into()
+}
+
+/// Build the commitment for a customer, as a creator would off-chain
+pub fn commitment(
+    env: &Env,
+    salt: &BytesN<32>,
+    debtor_salt: &BytesN<32>,
+    name: &String,
+    email: &String,
+    encrypted_data: Option<String>,
+) -> CustomerCommitment {
+    CustomerCommitment {
+        name_hash: commit(env, salt, name),
+        email_hash: commit(env, salt, email),
+        debtor_id: debtor_id(env, debtor_salt, email),
+        encrypted_data,
+    }
+}
+
+fn copy_value<'a>(value: &String, buffer: &'a mut [u8; MAX_VALUE_LEN]) -> &'a [u8] {
+    let len = value.len() as usize;
+    if len > MAX_VALUE_LEN {
+        panic!("Customer field too long");
+    }
+    value.copy_into_slice(&mut buffer[..len]);
+    &buffer[..len]
+}
@@ -0,0 +1,54 @@
+//! Normalized invoice fingerprints used to catch the same invoice minted twice
+
+use soroban_sdk::{xdr::ToXdr, Address, Bytes, BytesN, Env, String};
+
+/// Longest invoice number, in bytes, that can be fingerprinted
+pub const MAX_FIELD_LEN: usize = 128;
+
+/// Normalize a free-text field into `out`, returning the normalized length.
+/// ASCII letters are lowercased and whitespace is dropped, so "INV 001" and
+/// "inv-001" differ but "INV 001" and "inv001" do not.
+pub fn normalize(input: &[u8], out: &mut [u8]) -> usize {
+    let mut len = 0;
+    for byte in input {
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+        out[len] = byte.to_ascii_lowercase();
+        len += 1;
+    }
+    len
+}
+
+/// Hash identifying an invoice by creator, debtor, amount and invoice number
+pub fn invoice_fingerprint(
+    env: &Env,
+    creator: &Address,
+    debtor_id: &BytesN<32>,
+    amount: i128,
+    invoice_number: &String,
+) -> BytesN<32> {
+    let mut data = creator.clone().to_xdr(env);
+    data.append(&Bytes::from(debtor_id));
+    append_field(env, &mut data, invoice_number);
+    data.extend_from_slice(&amount.to_be_bytes());
+
+    env.crypto().sha256(&data).into()
+}
+
+/// Append a normalized field, length-prefixed so adjacent fields cannot run together
+fn append_field(env: &Env, data: &mut Bytes, field: &String) {
+    let len = field.len() as usize;
+    if len > MAX_FIELD_LEN {
+        panic!("Invoice field too long");
+    }
+
+    let mut raw = [0u8; MAX_FIELD_LEN];
+    field.copy_into_slice(&mut raw[..len]);
+
+    let mut normalized = [0u8; MAX_FIELD_LEN];
+    let normalized_len = normalize(&raw[..len], &mut normalized);
+
+    data.push_back(normalized_len as u8);
+    data.append(&Bytes::from_slice(env, &normalized[..normalized_len]));
+}
@@ -0,0 +1,282 @@
+//! Doubly linked list indexes of invoice ids kept in persistent storage.
+//!
+//! Every index is identified by a storage key of the owning contract. The
+//! list head/tail/length live in one entry and each member gets its own entry,
+//! so inserts and removals touch a constant number of entries no matter how
+//! large the index grows, and reads are paginated by cursor.
+//!
+//! Members are keyed by the sequence number they were appended under, which
+//! is also the page cursor. Removing a member leaves a short-lived marker
+//! pointing at the member that followed it, so a page started before the
+//! removal still continues where it left off.
+
+use soroban_sdk::{contracttype, symbol_short, Env, IntoVal, Symbol, Val, Vec};
+
+use crate::ttl::{DAY_IN_LEDGERS, PERSISTENT_BUMP_AMOUNT, PERSISTENT_LIFETIME_THRESHOLD};
+
+const INDEX: Symbol = symbol_short!("idx_meta");
+const ENTRY: Symbol = symbol_short!("idx_entry");
+const POSITION: Symbol = symbol_short!("idx_pos");
+const REMOVED: Symbol = symbol_short!("idx_gone");
+
+/// Maximum number of ids returned in a single page
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Cursors into removed positions can be resumed for ~30 days
+pub const CURSOR_TTL: u32 = 30 * DAY_IN_LEDGERS;
+
+#[derive(Clone)]
+#[contracttype]
+pub struct IndexMeta {
+    pub head: Option<u64>,
+    pub tail: Option<u64>,
+    pub len: u32,
+    /// Sequence number the next appended id gets
+    pub next_seq: u64,
+}
+
+/// An id at one position of an index, linked to its neighbours' positions
+#[derive(Clone)]
+#[contracttype]
+pub struct IndexEntry {
+    pub id: u64,
+    pub prev: Option<u64>,
+    pub next: Option<u64>,
+}
+
+/// A page of invoice ids plus the cursor to pass for the next page
+#[derive(Clone)]
+#[contracttype]
+pub struct InvoicePage {
+    pub invoice_ids: Vec<u64>,
+    pub next_cursor: Option<u64>,
+}
+
+fn extend_entry(env: &Env, key: &(Symbol, Val, u64)) {
+    env.storage().persistent().extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+}
+
+fn store_meta(env: &Env, index: &Val, meta: &IndexMeta) {
+    let key = (INDEX, *index);
+    env.storage().persistent().set(&key, meta);
+    extend_meta(env, &key);
+}
+
+fn extend_meta(env: &Env, key: &(Symbol, Val)) {
+    env.storage().persistent().extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+}
+
+fn load_meta(env: &Env, index: &Val) -> IndexMeta {
+    let key = (INDEX, *index);
+    match env.storage().persistent().get(&key) {
+        Some(meta) => {
+            extend_meta(env, &key);
+            meta
+        }
+        None => IndexMeta { head: None, tail: None, len: 0, next_seq: 1 },
+    }
+}
+
+fn load_entry(env: &Env, index: &Val, seq: u64) -> Option<IndexEntry> {
+    env.storage().persistent().get(&(ENTRY, *index, seq))
+}
+
+fn store_entry(env: &Env, index: &Val, seq: u64, entry: &IndexEntry) {
+    let key = (ENTRY, *index, seq);
+    env.storage().persistent().set(&key, entry);
+    extend_entry(env, &key);
+}
+
+fn position(env: &Env, index: &Val, id: u64) -> Option<u64> {
+    env.storage().persistent().get(&(POSITION, *index, id))
+}
+
+/// Append an id to the end of an index (no-op if already present)
+pub fn push<K: IntoVal<Env, Val>>(env: &Env, index: &K, id: u64) {
+    let index: &Val = &index.into_val(env);
+
+    if position(env, index, id).is_some() {
+        return;
+    }
+
+    let mut meta = load_meta(env, index);
+    let seq = meta.next_seq;
+
+    if let Some(tail) = meta.tail {
+        let mut tail_entry = load_entry(env, index, tail).expect("Corrupt index");
+        tail_entry.next = Some(seq);
+        store_entry(env, index, tail, &tail_entry);
+    } else {
+        meta.head = Some(seq);
+    }
+
+    store_entry(env, index, seq, &IndexEntry { id, prev: meta.tail, next: None });
+    let position_key = (POSITION, *index, id);
+    env.storage().persistent().set(&position_key, &seq);
+    extend_entry(env, &position_key);
+
+    meta.tail = Some(seq);
+    meta.len += 1;
+    meta.next_seq += 1;
+
+    store_meta(env, index, &meta);
+}
+
+/// Unlink an id from an index, returning whether it was present
+pub fn remove<K: IntoVal<Env, Val>>(env: &Env, index: &K, id: u64) -> bool {
+    let index: &Val = &index.into_val(env);
+
+    let seq = match position(env, index, id) {
+        Some(seq) => seq,
+        None => return false,
+    };
+    let entry = load_entry(env, index, seq).expect("Corrupt index");
+
+    let mut meta = load_meta(env, index);
+
+    match entry.prev {
+        Some(prev) => {
+            let mut prev_entry = load_entry(env, index, prev).expect("Corrupt index");
+            prev_entry.next = entry.next;
+            store_entry(env, index, prev, &prev_entry);
+        }
+        None => meta.head = entry.next,
+    }
+
+    match entry.next {
+        Some(next) => {
+            let mut next_entry = load_entry(env, index, next).expect("Corrupt index");
+            next_entry.prev = entry.prev;
+            store_entry(env, index, next, &next_entry);
+        }
+        None => meta.tail = entry.prev,
+    }
+
+    env.storage().persistent().remove(&(ENTRY, *index, seq));
+    env.storage().persistent().remove(&(POSITION, *index, id));
+
+    // Pages holding this position as their cursor resume from its successor,
+    // or from whatever is appended next if it was the tail
+    let removed_key = (REMOVED, *index, seq);
+    env.storage().temporary().set(&removed_key, &entry.next.unwrap_or(meta.next_seq));
+    env.storage().temporary().extend_ttl(&removed_key, CURSOR_TTL, CURSOR_TTL);
+
+    meta.len -= 1;
+    store_meta(env, index, &meta);
+
+    true
+}
+
+/// Check whether an id is in an index
+pub fn contains<K: IntoVal<Env, Val>>(env: &Env, index: &K, id: u64) -> bool {
+    let index: &Val = &index.into_val(env);
+
+    position(env, index, id).is_some()
+}
+
+/// Extend the TTL of an index and of the entries for `id`, if it is a member
+pub fn extend<K: IntoVal<Env, Val>>(env: &Env, index: &K, id: u64) {
+    let index: &Val = &index.into_val(env);
+
+    if let Some(seq) = position(env, index, id) {
+        extend_entry(env, &(POSITION, *index, id));
+        extend_entry(env, &(ENTRY, *index, seq));
+        extend_meta(env, &(INDEX, *index));
+    }
+}
+
+/// Number of ids in an index
+pub fn len<K: IntoVal<Env, Val>>(env: &Env, index: &K) -> u32 {
+    let index: &Val = &index.into_val(env);
+
+    load_meta(env, index).len
+}
+
+/// Append the ids of a plain `Vec<u64>` list stored under `list_key`, the
+/// layout used before these indexes, to `index` and delete the list.
+/// Returns how many ids were moved.
+pub fn import_list<L, K>(env: &Env, list_key: &L, index: &K) -> u32
+where
+    L: IntoVal<Env, Val>,
+    K: IntoVal<Env, Val>,
+{
+    let list_key: Val = list_key.into_val(env);
+    let index: Val = index.into_val(env);
+
+    let ids: Vec<u64> = match env.storage().persistent().get(&list_key) {
+        Some(ids) => ids,
+        None => return 0,
+    };
+    env.storage().persistent().remove(&list_key);
+
+    for id in ids.iter() {
+        push(env, &index, id);
+    }
+    ids.len()
+}
+
+/// Read up to `limit` ids following `cursor` (or from the start when `None`).
+/// A cursor whose id has since left the index resumes from the id that
+/// followed it; once that record has expired the cursor panics as expired.
+/// Following removed positions counts against `limit`, so a page may come
+/// back empty with a cursor to continue from.
+pub fn page<K: IntoVal<Env, Val>>(env: &Env, index: &K, cursor: Option<u64>, limit: u32) -> InvoicePage {
+    let index: &Val = &index.into_val(env);
+
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        panic!("Invalid page limit");
+    }
+
+    let meta = load_meta(env, index);
+    let mut next = match cursor {
+        Some(seq) => match load_entry(env, index, seq) {
+            Some(entry) => entry.next,
+            None => {
+                if seq == 0 || seq >= meta.next_seq {
+                    panic!("Invalid cursor");
+                }
+                match resume_after(env, index, &meta, seq, limit) {
+                    Ok(next) => next,
+                    Err(seq) => return InvoicePage { invoice_ids: Vec::new(env), next_cursor: Some(seq) },
+                }
+            }
+        },
+        None => meta.head,
+    };
+
+    let mut invoice_ids = Vec::new(env);
+    let mut last = None;
+    while let Some(seq) = next {
+        if invoice_ids.len() == limit {
+            break;
+        }
+        let entry = load_entry(env, index, seq).expect("Corrupt index");
+        extend_entry(env, &(ENTRY, *index, seq));
+        invoice_ids.push_back(entry.id);
+        last = Some(seq);
+        next = entry.next;
+    }
+
+    // Only hand out a cursor when there is something left to read
+    let next_cursor = if next.is_some() { last } else { None };
+
+    InvoicePage { invoice_ids, next_cursor }
+}
+
+/// First live position after the removed position `seq`, following removal
+/// markers for at most `budget` hops. `Err` carries the removed position to
+/// resume from once the budget runs out.
+fn resume_after(env: &Env, index: &Val, meta: &IndexMeta, mut seq: u64, budget: u32) -> Result<Option<u64>, u64> {
+    for _ in 0..budget {
+        let target: u64 = env.storage().temporary().get(&(REMOVED, *index, seq)).expect("Cursor expired");
+        if target >= meta.next_seq {
+            // The removed id was the tail and nothing has been appended since
+            return Ok(None);
+        }
+        if env.storage().persistent().has(&(ENTRY, *index, target)) {
+            return Ok(Some(target));
+        }
+        seq = target;
+    }
+    Err(seq)
+}
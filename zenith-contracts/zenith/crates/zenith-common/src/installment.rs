@@ -0,0 +1,72 @@
+//! Installment schedules for invoices paid in parts
+//!
+//! Payments are applied to installments in due-date order, so an invoice's
+//! schedule and the total paid so far are enough to tell which installment is
+//! next and how much has fallen due unpaid. An invoice without a schedule is
+//! a single installment of its full amount.
+
+use soroban_sdk::{contracttype, vec, Env, Vec};
+
+/// Most installments a schedule may have
+pub const MAX_INSTALLMENTS: u32 = 24;
+
+/// Part of an invoice falling due on a given date
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct Installment {
+    pub amount: i128,
+    pub due_date: u64,
+}
+
+/// Whether a schedule splits `amount` into positive installments in due-date
+/// order, the last of them due on the invoice's own `due_date`
+pub fn is_valid_schedule(schedule: &Vec<Installment>, amount: i128, due_date: u64) -> bool {
+    if schedule.is_empty() || schedule.len() > MAX_INSTALLMENTS {
+        return false;
+    }
+
+    let mut total = 0i128;
+    let mut previous_due_date = 0u64;
+    for installment in schedule.iter() {
+        if installment.amount <= 0 || installment.due_date <= previous_due_date {
+            return false;
+        }
+        total = match total.checked_add(installment.amount) {
+            Some(total) => total,
+            None => return false,
+        };
+        previous_due_date = installment.due_date;
+    }
+
+    total == amount && previous_due_date == due_date
+}
+
+/// An invoice's schedule, or a single installment for the whole amount if it has none
+pub fn or_single(env: &Env, schedule: Vec<Installment>, amount: i128, due_date: u64) -> Vec<Installment> {
+    if schedule.is_empty() {
+        vec![env, Installment { amount, due_date }]
+    } else {
+        schedule
+    }
+}
+
+/// First installment not yet covered by `paid`, with what remains of it
+pub fn next_unpaid(schedule: &Vec<Installment>, paid: i128) -> Option<Installment> {
+    let mut scheduled = 0i128;
+    for installment in schedule.iter() {
+        scheduled += installment.amount;
+        if scheduled > paid {
+            return Some(Installment { amount: scheduled - paid, due_date: installment.due_date });
+        }
+    }
+    None
+}
+
+/// Amount fallen due by `at` and not covered by `paid`
+pub fn arrears(schedule: &Vec<Installment>, paid: i128, at: u64) -> i128 {
+    let scheduled: i128 = schedule.iter()
+        .filter(|installment| installment.due_date <= at)
+        .map(|installment| installment.amount)
+        .sum();
+    (scheduled - paid).max(0)
+}
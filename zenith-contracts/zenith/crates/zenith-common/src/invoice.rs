@@ -0,0 +1,52 @@
+//! Invoice types exchanged between the invoice NFT and the marketplace
+
+use soroban_sdk::{contracttype, Address, String, Symbol};
+
+/// Currency an invoice is denominated in
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum Currency {
+    Usdc,
+    Eurc,
+    Anchor(Symbol), // Asset issued by a local anchor, by asset code
+}
+
+/// How far a creator's identity has been checked
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum VerificationLevel {
+    Unverified,
+    Pending,
+    Verified,
+}
+
+/// How an invoice's risk grade was assigned
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum VerificationMethod {
+    Manual,     // Set by an admin
+    Automated,  // Graded on-chain from risk factors
+    Integrated, // Reported by an integrated data provider
+}
+
+/// Fields a creator may change while an invoice is pending review.
+/// The customer is changed separately, as a whole commitment.
+#[derive(Clone)]
+#[contracttype]
+pub struct InvoiceAmendment {
+    pub title: Option<String>,
+    pub amount: Option<i128>,
+    pub due_date: Option<u64>,
+    pub description: Option<String>,
+    pub tax_rate: Option<u32>,
+    pub early_discount: Option<u32>,
+}
+
+/// The terms the marketplace submits an invoice on, as the invoice NFT records them
+#[derive(Clone)]
+#[contracttype]
+pub struct InvoiceTerms {
+    pub creator: Address,
+    pub amount: i128,
+    pub due_date: u64,
+}
@@ -0,0 +1,37 @@
+#![no_std]
+//! Types, math and pricing shared by the Zenith contracts.
+//!
+//! `math` and `time` are plain integer functions with no `Env`, and `pricing`
+//! only needs one to build its default grid, so off-chain tools can link the
+//! exact pricing the contracts use.
+
+pub mod asset;
+pub mod attestation;
+pub mod collateral;
+pub mod customer;
+pub mod fingerprint;
+pub mod index;
+pub mod installment;
+pub mod invoice;
+pub mod math;
+pub mod pricing;
+pub mod recurring;
+pub mod risk;
+pub mod status;
+pub mod tax;
+pub mod time;
+pub mod ttl;
+
+pub use asset::AssetConfig;
+pub use attestation::{Attestation, AttestationClaim};
+pub use customer::{CustomerCommitment, CustomerField};
+pub use index::InvoicePage;
+pub use installment::Installment;
+pub use invoice::{Currency, InvoiceAmendment, InvoiceTerms, VerificationLevel, VerificationMethod};
+pub use pricing::{EarlyDiscountSchedule, PricingConfig, Quote, QuoteParams, TrustTier};
+pub use recurring::{RecurringRoll, RecurringSchedule};
+pub use risk::{RiskBand, RiskFactors, RiskGrade, RiskOverride};
+pub use status::InvoiceStatus;
+pub use tax::{FinancingRule, TaxBasis, TaxBreakdown};
+
+mod test;
@@ -0,0 +1,60 @@
+//! Checked basis-point and fixed-point helpers
+//!
+//! Every division takes an explicit [`Rounding`] so each call site states who
+//! keeps the remainder. Amounts the protocol pays out round down so the
+//! protocol never pays more than it owes; fees the protocol charges also round
+//! down so users are never charged more than the stated rate. Overflow panics
+//! instead of wrapping.
+
+/// 100% expressed in basis points
+pub const BPS_DENOMINATOR: u32 = 10_000;
+
+/// Direction to round a quotient that is not exact
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    /// Toward negative infinity
+    Down,
+    /// Toward positive infinity
+    Up,
+}
+
+/// Compute `value * numerator / denominator` rounded as requested, or `None`
+/// on overflow or a zero denominator
+pub fn checked_mul_div(value: i128, numerator: i128, denominator: i128, rounding: Rounding) -> Option<i128> {
+    let product = value.checked_mul(numerator)?;
+    let quotient = product.checked_div(denominator)?;
+
+    // Integer division truncates toward zero; nudge inexact results
+    if product.checked_rem(denominator)? == 0 {
+        return Some(quotient);
+    }
+    let negative = (product < 0) != (denominator < 0);
+    match rounding {
+        Rounding::Down if negative => quotient.checked_sub(1),
+        Rounding::Up if !negative => quotient.checked_add(1),
+        _ => Some(quotient),
+    }
+}
+
+/// Compute `value * numerator / denominator` rounded as requested
+pub fn mul_div(value: i128, numerator: i128, denominator: i128, rounding: Rounding) -> i128 {
+    if denominator == 0 {
+        panic!("Division by zero");
+    }
+    checked_mul_div(value, numerator, denominator, rounding).expect("Arithmetic overflow")
+}
+
+/// Take `bps` basis points of `amount`
+pub fn apply_bps(amount: i128, bps: u32, rounding: Rounding) -> i128 {
+    mul_div(amount, bps as i128, BPS_DENOMINATOR as i128, rounding)
+}
+
+/// Take `percent` percent of `amount`
+pub fn apply_percent(amount: i128, percent: u32, rounding: Rounding) -> i128 {
+    mul_div(amount, percent as i128, 100, rounding)
+}
+
+/// Whether `bps` is at most 100%
+pub fn is_valid_bps(bps: u32) -> bool {
+    bps <= BPS_DENOMINATOR
+}
@@ -0,0 +1,212 @@
+//! Invoice pricing
+//!
+//! Invoices are bought at a discount to face value. The discount comes from an
+//! admin-configurable grid of risk grade by tenor bucket, shifted by the
+//! creator's trust score and clamped to a floor and ceiling. The same rate is
+//! what a funder earns on the amount it advances.
+//!
+//! Offers and yield are payouts and round down; returns and fees are charges
+//! and also round down, so rounding never takes more than the stated rate.
+
+use soroban_sdk::{contracttype, vec, Address, Env, Vec};
+
+use crate::math::{apply_bps, mul_div, Rounding, BPS_DENOMINATOR};
+use crate::risk::{RiskGrade, RISK_GRADE_COUNT};
+use crate::time::{DAYS_PER_YEAR, SECONDS_PER_YEAR};
+
+/// Discount adjustment for creators whose trust score is at least `min_score`
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct TrustTier {
+    pub min_score: u32,
+    pub adjustment: i32, // basis points, negative for a smaller discount
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct PricingConfig {
+    pub tenor_buckets: Vec<u64>, // upper bound in days of each bucket, ascending
+    pub grade_rates: Vec<Vec<u32>>, // basis points for grades A..D, one entry per tenor bucket
+    pub trust_tiers: Vec<TrustTier>, // ascending by min_score
+    pub min_rate: u32, // basis points
+    pub max_rate: u32, // basis points
+}
+
+/// Inputs to a price quote
+#[derive(Clone)]
+#[contracttype]
+pub struct QuoteParams {
+    pub creator: Address,
+    pub amount: i128,
+    pub due_date: u64,
+    pub risk_grade: RiskGrade,
+}
+
+/// Financial terms derived from the pricing grid
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct Quote {
+    pub discount_rate: u32, // basis points
+    pub offer_amount: i128,
+    pub expected_yield: u32, // basis points (APR)
+}
+
+/// Grid used until an admin configures one. Grade C matches the original
+/// 2% + 0.1% per day curve at each bucket's upper bound.
+pub fn default_config(env: &Env) -> PricingConfig {
+    PricingConfig {
+        tenor_buckets: vec![env, 30, 60, 90, 180, 365],
+        grade_rates: vec![
+            env,
+            vec![env, 300, 500, 700, 1000, 1200],   // A
+            vec![env, 400, 650, 900, 1250, 1400],   // B
+            vec![env, 500, 800, 1100, 1500, 1500],  // C
+            vec![env, 700, 1100, 1500, 2000, 2500], // D
+        ],
+        trust_tiers: vec![
+            env,
+            TrustTier { min_score: 0, adjustment: 300 },
+            TrustTier { min_score: 30, adjustment: 100 },
+            TrustTier { min_score: 50, adjustment: 0 },
+            TrustTier { min_score: 70, adjustment: -100 },
+            TrustTier { min_score: 90, adjustment: -200 },
+        ],
+        min_rate: 100,
+        max_rate: 3000,
+    }
+}
+
+/// Whether a grid is well formed: ascending buckets, one row of rates per
+/// grade, ascending trust tiers and rates within 100%
+pub fn is_valid_config(config: &PricingConfig) -> bool {
+    let buckets = config.tenor_buckets.len();
+    if buckets == 0 || config.grade_rates.len() != RISK_GRADE_COUNT {
+        return false;
+    }
+    if config.min_rate > config.max_rate || config.max_rate >= BPS_DENOMINATOR {
+        return false;
+    }
+
+    let mut previous_bucket = 0u64;
+    for bucket in config.tenor_buckets.iter() {
+        if bucket <= previous_bucket {
+            return false;
+        }
+        previous_bucket = bucket;
+    }
+
+    for rates in config.grade_rates.iter() {
+        if rates.len() != buckets || rates.iter().any(|rate| rate >= BPS_DENOMINATOR) {
+            return false;
+        }
+    }
+
+    let mut previous_score: Option<u32> = None;
+    for tier in config.trust_tiers.iter() {
+        if previous_score.is_some_and(|score| tier.min_score <= score) {
+            return false;
+        }
+        previous_score = Some(tier.min_score);
+    }
+
+    true
+}
+
+/// Discount rate in basis points for a grade, tenor and trust score
+pub fn discount_rate(config: &PricingConfig, grade: RiskGrade, days_until_due: u64, trust_score: u32) -> u32 {
+    // Tenors past the last bucket are priced as the last bucket
+    let last = config.tenor_buckets.len() - 1;
+    let bucket = config.tenor_buckets
+        .iter()
+        .position(|max_days| days_until_due <= max_days)
+        .map_or(last, |bucket| bucket as u32);
+    let base = config.grade_rates
+        .get(grade.index())
+        .and_then(|rates| rates.get(bucket))
+        .expect("Invalid pricing config");
+
+    // The highest tier the score reaches applies
+    let adjustment = config.trust_tiers
+        .iter()
+        .filter(|tier| trust_score >= tier.min_score)
+        .last()
+        .map_or(0, |tier| tier.adjustment);
+
+    let rate = (base as i64 + adjustment as i64).clamp(config.min_rate as i64, config.max_rate as i64);
+    rate as u32
+}
+
+/// Price an invoice of face value `amount` due in `days_until_due` days
+pub fn quote(config: &PricingConfig, grade: RiskGrade, trust_score: u32, amount: i128, days_until_due: u64) -> Quote {
+    let discount_rate = discount_rate(config, grade, days_until_due, trust_score);
+    Quote {
+        discount_rate,
+        offer_amount: offer_amount(amount, discount_rate),
+        expected_yield: annualized_yield(discount_rate, days_until_due),
+    }
+}
+
+/// Annual rate in basis points equivalent to earning `discount_rate` over `days`
+pub fn annualized_yield(discount_rate: u32, days: u64) -> u32 {
+    let annualized = (discount_rate as u64)
+        .saturating_mul(DAYS_PER_YEAR)
+        / days.max(1);
+    annualized.min(u32::MAX as u64) as u32
+}
+
+/// Upfront amount offered for an invoice with face value `amount`
+pub fn offer_amount(amount: i128, discount_rate: u32) -> i128 {
+    let kept = BPS_DENOMINATOR.checked_sub(discount_rate).expect("Invalid basis points");
+    mul_div(amount, kept as i128, BPS_DENOMINATOR as i128, Rounding::Down)
+}
+
+/// Amount a funder expects back on `advance` priced at `discount_rate`
+pub fn expected_return(advance: i128, discount_rate: u32) -> i128 {
+    advance
+        .checked_add(apply_bps(advance, discount_rate, Rounding::Down))
+        .expect("Arithmetic overflow")
+}
+
+/// How a creator's early payment discount changes as the due date approaches
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum EarlyDiscountSchedule {
+    Flat,    // the full discount for any payment before the due date
+    Sliding, // the full discount at funding, shrinking linearly to nothing at the due date
+}
+
+/// Early payment discount rate in basis points for a payment at `paid_at`
+/// on an invoice financed at `start` and due at `due_date`
+pub fn early_discount_rate(
+    early_discount: u32,
+    schedule: EarlyDiscountSchedule,
+    start: u64,
+    paid_at: u64,
+    due_date: u64,
+) -> u32 {
+    if paid_at >= due_date {
+        return 0;
+    }
+
+    match schedule {
+        EarlyDiscountSchedule::Flat => early_discount,
+        EarlyDiscountSchedule::Sliding => {
+            let term = due_date.saturating_sub(start).max(1);
+            let remaining = (due_date - paid_at).min(term);
+            (early_discount as u64 * remaining / term) as u32
+        }
+    }
+}
+
+/// Late fee rate in basis points after `days_late` days at `daily_rate`, capped at `cap`
+pub fn late_fee_rate(days_late: u64, daily_rate: u32, cap: u32) -> u32 {
+    days_late.saturating_mul(daily_rate as u64).min(cap as u64) as u32
+}
+
+/// Simple interest earned on `principal` at `apr` basis points over `elapsed` seconds
+pub fn accrued_yield(principal: i128, apr: u32, elapsed: u64) -> i128 {
+    // Single division so the only truncation is the final one
+    let rate_time = (apr as i128) * (elapsed as i128);
+    let denominator = (BPS_DENOMINATOR as i128) * (SECONDS_PER_YEAR as i128);
+    mul_div(principal, rate_time, denominator, Rounding::Down)
+}
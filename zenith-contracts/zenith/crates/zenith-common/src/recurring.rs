@@ -0,0 +1,58 @@
+//! Schedules for invoices a creator issues every period, such as retainers
+//!
+//! Each occurrence is issued one period before it falls due, so a monthly
+//! invoice due on the 1st is minted on the 1st of the month before.
+
+use soroban_sdk::{contracttype, Address};
+
+use crate::time::days_to_seconds;
+
+/// How often a recurring invoice is issued and when it stops
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct RecurringSchedule {
+    pub period_days: u64,
+    pub end_date: u64, // no occurrence falls due after this timestamp
+    pub max_occurrences: u32,
+    pub auto_submit_limit: Option<i128>, // occurrences up to this amount go to the marketplace unreviewed by the creator
+}
+
+/// An occurrence minted from a recurring invoice
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct RecurringRoll {
+    pub template_id: u64,
+    pub invoice_id: u64,
+    pub creator: Address,
+    pub amount: i128,
+    pub due_date: u64,
+    pub auto_submit: bool, // within the creator's auto-submit limit
+}
+
+/// Whether a schedule can produce at least one occurrence due at `first_due_date`
+pub fn is_valid_schedule(schedule: &RecurringSchedule, first_due_date: u64) -> bool {
+    schedule.period_days > 0
+        && schedule.max_occurrences > 0
+        && schedule.end_date >= first_due_date
+        && schedule.auto_submit_limit.is_none_or(|limit| limit > 0)
+}
+
+/// Timestamp from which the occurrence due at `due_date` can be issued
+pub fn issue_date(due_date: u64, period_days: u64) -> u64 {
+    due_date.saturating_sub(days_to_seconds(period_days))
+}
+
+/// First due date in the schedule that is still after `now`, skipping missed periods
+pub fn next_due_date(due_date: u64, period_days: u64, now: u64) -> u64 {
+    if due_date > now {
+        return due_date;
+    }
+    let period = days_to_seconds(period_days);
+    let missed = (now - due_date) / period + 1;
+    due_date + missed * period
+}
+
+/// Whether an occurrence of `amount` is within the creator's auto-submit limit
+pub fn is_auto_submitted(schedule: &RecurringSchedule, amount: i128) -> bool {
+    schedule.auto_submit_limit.is_some_and(|limit| amount <= limit)
+}
@@ -0,0 +1,163 @@
+//! Invoice risk grades and automated grading
+//!
+//! Each invoice is graded from a points score out of 100. The creator's trust
+//! score sets the baseline; repayment history, tenor, invoice size, the
+//! debtor's record and acknowledgement, collateral and verification move it
+//! up or down.
+
+use soroban_sdk::{contracttype, String};
+
+/// Credit quality of an invoice, from A (lowest risk) to D (highest)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum RiskGrade {
+    A,
+    B,
+    C,
+    D,
+}
+
+/// Number of risk grades, i.e. rows in a pricing grid
+pub const RISK_GRADE_COUNT: u32 = 4;
+
+impl RiskGrade {
+    /// Parse a single-letter label ("A" to "D") as stored before grades were typed
+    pub fn from_label(label: &String) -> Option<RiskGrade> {
+        if label.len() != 1 {
+            return None;
+        }
+        let mut byte = [0u8; 1];
+        label.copy_into_slice(&mut byte);
+        match byte[0] {
+            b'A' => Some(RiskGrade::A),
+            b'B' => Some(RiskGrade::B),
+            b'C' => Some(RiskGrade::C),
+            b'D' => Some(RiskGrade::D),
+            _ => None,
+        }
+    }
+
+    /// Row of this grade in a pricing grid
+    pub fn index(&self) -> u32 {
+        match self {
+            RiskGrade::A => 0,
+            RiskGrade::B => 1,
+            RiskGrade::C => 2,
+            RiskGrade::D => 3,
+        }
+    }
+}
+
+/// Contiguous range of grades a liquidity pool accepts, `best` to `worst` inclusive
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct RiskBand {
+    pub best: RiskGrade,
+    pub worst: RiskGrade,
+}
+
+impl RiskBand {
+    /// Whether the band runs from a better grade to an equal or worse one
+    pub fn is_valid(&self) -> bool {
+        self.best.index() <= self.worst.index()
+    }
+
+    pub fn contains(&self, grade: RiskGrade) -> bool {
+        self.best.index() <= grade.index() && grade.index() <= self.worst.index()
+    }
+
+    pub fn overlaps(&self, other: &RiskBand) -> bool {
+        self.best.index() <= other.worst.index() && other.best.index() <= self.worst.index()
+    }
+}
+
+/// Inputs to automated grading, published with every grade so it can be audited
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct RiskFactors {
+    pub trust_score: u32,
+    pub completed_invoices: u32, // creator's repaid invoices
+    pub defaulted_invoices: u32, // creator's defaulted invoices
+    pub days_until_due: u64,
+    pub amount: i128,
+    pub average_amount: i128, // creator's average invoice amount, 0 without history
+    // The debtor's record is keyed by the invoice's debtor id, which the creator supplies
+    // and any creator can copy from a public commitment; only an acknowledgement shows the
+    // debtor actually stands behind the invoice
+    pub debtor_paid: u32, // invoices the debtor has paid
+    pub debtor_defaulted: u32, // invoices the debtor has defaulted on
+    pub has_proof: bool, // supporting document hash attached
+    pub is_verified: bool, // creator identity verified
+    pub debtor_acknowledged: bool, // customer confirmed the amount and due date on-chain
+}
+
+/// Admin-assigned grade replacing the automated one
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct RiskOverride {
+    pub risk_grade: RiskGrade,
+    pub reason: String,
+}
+
+/// Minimum points for grades A, B and C; anything lower is D
+pub const GRADE_A_POINTS: u32 = 70;
+pub const GRADE_B_POINTS: u32 = 50;
+pub const GRADE_C_POINTS: u32 = 25;
+
+/// Points out of 100 for a set of risk factors
+pub fn risk_points(factors: &RiskFactors) -> u32 {
+    // Trust score contributes up to half the total
+    let mut points = (factors.trust_score.min(100) / 2) as i64;
+
+    points += (factors.completed_invoices.min(5) * 2) as i64;
+    points -= factors.defaulted_invoices as i64 * 15;
+
+    points -= match factors.days_until_due {
+        0..=90 => 0,
+        91..=180 => 5,
+        _ => 10,
+    };
+
+    // Compare the amount with what the creator usually invoices
+    if factors.average_amount > 0 {
+        let average = factors.average_amount;
+        if factors.amount <= average.saturating_mul(2) {
+            points += 5;
+        } else if factors.amount > average.saturating_mul(5) {
+            points -= 10;
+        }
+    }
+
+    points += (factors.debtor_paid.min(5) * 2) as i64;
+    points -= factors.debtor_defaulted as i64 * 15;
+
+    if factors.has_proof {
+        points += 5;
+    }
+    if factors.is_verified {
+        points += 5;
+    }
+    if factors.debtor_acknowledged {
+        points += 10;
+    }
+
+    points.clamp(0, 100) as u32
+}
+
+/// Grade for a points score
+pub fn grade_for_points(points: u32) -> RiskGrade {
+    if points >= GRADE_A_POINTS {
+        RiskGrade::A
+    } else if points >= GRADE_B_POINTS {
+        RiskGrade::B
+    } else if points >= GRADE_C_POINTS {
+        RiskGrade::C
+    } else {
+        RiskGrade::D
+    }
+}
+
+/// Grade a set of risk factors
+pub fn grade(factors: &RiskFactors) -> RiskGrade {
+    grade_for_points(risk_points(factors))
+}
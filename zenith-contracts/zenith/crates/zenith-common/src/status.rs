@@ -0,0 +1,36 @@
+//! Canonical invoice lifecycle shared by the invoice NFT and the marketplace
+
+use soroban_sdk::contracttype;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum InvoiceStatus {
+    Pending,   // Submitted, awaiting review
+    Approved,  // Cleared for funding
+    Rejected,  // Turned down at review
+    Funded,    // Advanced by the pool, awaiting repayment
+    Completed, // Repaid
+    Overdue,   // Unpaid past the grace period
+    Defaulted, // Written off
+    Cancelled, // Withdrawn by the creator before funding
+    PartiallyPaid, // Funded and repaid in part, with no installment overdue
+}
+
+impl InvoiceStatus {
+    /// Whether the invoice can still change state; settled invoices are left to expire
+    pub fn is_live(&self) -> bool {
+        matches!(
+            self,
+            InvoiceStatus::Pending
+                | InvoiceStatus::Approved
+                | InvoiceStatus::Funded
+                | InvoiceStatus::Overdue
+                | InvoiceStatus::PartiallyPaid
+        )
+    }
+
+    /// Whether the invoice is waiting on repayment
+    pub fn is_outstanding(&self) -> bool {
+        matches!(self, InvoiceStatus::Funded | InvoiceStatus::Overdue | InvoiceStatus::PartiallyPaid)
+    }
+}
@@ -0,0 +1,65 @@
+//! Tax on invoice amounts and how much of a taxed invoice can be financed
+
+use soroban_sdk::contracttype;
+
+use crate::math::{apply_bps, mul_div, Rounding, BPS_DENOMINATOR};
+
+/// Highest tax rate an invoice may carry, in basis points
+pub const MAX_TAX_RATE: u32 = 5_000;
+
+/// Whether an invoice's `amount` is stated before or after tax
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum TaxBasis {
+    Net,
+    Gross,
+}
+
+/// Which part of a taxed invoice liquidity pools advance against
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[contracttype]
+pub enum FinancingRule {
+    Gross, // the full amount the debtor pays
+    Net,   // hold back the tax portion, which is owed to the tax authority
+}
+
+/// An invoice amount split into its net and tax portions
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[contracttype]
+pub struct TaxBreakdown {
+    pub net_amount: i128,
+    pub tax_amount: i128,
+    pub gross_amount: i128,
+}
+
+/// Whether a tax rate is within the supported range
+pub fn is_valid_rate(tax_rate: u32) -> bool {
+    tax_rate <= MAX_TAX_RATE
+}
+
+/// Split an amount stated on `basis` into net, tax and gross.
+/// Tax rounds down either way, so the debtor is never charged more than the rate.
+pub fn breakdown(amount: i128, tax_rate: Option<u32>, basis: TaxBasis) -> TaxBreakdown {
+    let rate = tax_rate.unwrap_or(0);
+    if !is_valid_rate(rate) {
+        panic!("Invalid tax rate");
+    }
+
+    let (net_amount, gross_amount) = match basis {
+        TaxBasis::Net => (amount, amount + apply_bps(amount, rate, Rounding::Down)),
+        TaxBasis::Gross => {
+            let net = mul_div(amount, BPS_DENOMINATOR as i128, (BPS_DENOMINATOR + rate) as i128, Rounding::Up);
+            (net, amount)
+        }
+    };
+
+    TaxBreakdown { net_amount, tax_amount: gross_amount - net_amount, gross_amount }
+}
+
+/// Amount of an invoice that can be financed under `rule`
+pub fn financeable_amount(breakdown: &TaxBreakdown, rule: FinancingRule) -> i128 {
+    match rule {
+        FinancingRule::Gross => breakdown.gross_amount,
+        FinancingRule::Net => breakdown.net_amount,
+    }
+}
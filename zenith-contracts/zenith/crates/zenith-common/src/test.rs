@@ -0,0 +1,642 @@
+#![cfg(test)]
+
+use crate::asset::{is_valid_config as is_valid_asset_config, is_within_limits, MAX_DECIMALS};
+use crate::attestation::{is_conclusive, is_current, MIN_CONFIDENCE};
+use crate::collateral::{default_ratios, is_valid_ratios, required_collateral};
+use crate::customer::{commit, commitment as customer_commitment, debtor_id, MAX_VALUE_LEN};
+use crate::fingerprint::{invoice_fingerprint, normalize, MAX_FIELD_LEN};
+use crate::installment::{arrears, is_valid_schedule as is_valid_installments, next_unpaid, or_single, MAX_INSTALLMENTS};
+use crate::math::{apply_bps, apply_percent, checked_mul_div, is_valid_bps, mul_div, Rounding, BPS_DENOMINATOR};
+use crate::pricing::{
+    accrued_yield, annualized_yield, default_config, discount_rate, early_discount_rate, expected_return, is_valid_config,
+    late_fee_rate, offer_amount, quote, EarlyDiscountSchedule,
+};
+use crate::recurring::{is_auto_submitted, is_valid_schedule, issue_date, next_due_date};
+use crate::tax::{breakdown, financeable_amount, is_valid_rate, MAX_TAX_RATE};
+use crate::time::{days_between, days_to_seconds, SECONDS_PER_DAY, SECONDS_PER_YEAR};
+use crate::risk::{grade, grade_for_points, risk_points};
+use crate::{AssetConfig, Attestation, FinancingRule, Installment, InvoiceStatus, RecurringSchedule, TaxBasis, TaxBreakdown, RiskBand, RiskFactors, RiskGrade, TrustTier};
+use soroban_sdk::{symbol_short, testutils::Address as _, vec, Address, Bytes, BytesN, Env, String, Vec};
+
+const GRADES: [RiskGrade; 4] = [RiskGrade::A, RiskGrade::B, RiskGrade::C, RiskGrade::D];
+
+#[test]
+fn test_grid_rate_by_grade_and_tenor() {
+    let env = Env::default();
+    let config = default_config(&env);
+
+    // Neutral trust score leaves the grid rate untouched
+    assert_eq!(discount_rate(&config, RiskGrade::C, 0, 50), 500);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 50), 500);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 31, 50), 800);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 90, 50), 1100);
+    assert_eq!(discount_rate(&config, RiskGrade::A, 90, 50), 700);
+    assert_eq!(discount_rate(&config, RiskGrade::D, 365, 50), 2500);
+
+    // Tenors beyond the last bucket use the last bucket
+    assert_eq!(discount_rate(&config, RiskGrade::D, u64::MAX, 50), 2500);
+}
+
+#[test]
+fn test_trust_tiers_adjust_rate() {
+    let env = Env::default();
+    let config = default_config(&env);
+
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 0), 800);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 30), 600);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 69), 500);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 70), 400);
+    assert_eq!(discount_rate(&config, RiskGrade::C, 30, 100), 300);
+
+    // Adjustments are clamped to the configured floor
+    assert_eq!(discount_rate(&config, RiskGrade::A, 30, 100), 100);
+}
+
+fn new_creator_factors() -> RiskFactors {
+    RiskFactors {
+        trust_score: 50,
+        completed_invoices: 0,
+        defaulted_invoices: 0,
+        days_until_due: 30,
+        amount: 1000_0000000,
+        average_amount: 0,
+        debtor_paid: 0,
+        debtor_defaulted: 0,
+        has_proof: false,
+        is_verified: false,
+        debtor_acknowledged: false,
+    }
+}
+
+#[test]
+fn test_risk_points_by_factor() {
+    let base = new_creator_factors();
+    assert_eq!(risk_points(&base), 25);
+    assert_eq!(grade(&base), RiskGrade::C);
+
+    let seasoned = RiskFactors { completed_invoices: 10, debtor_paid: 3, average_amount: 800_0000000, ..base.clone() };
+    assert_eq!(risk_points(&seasoned), 25 + 10 + 6 + 5);
+
+    let long_tenor = RiskFactors { days_until_due: 200, ..base.clone() };
+    assert_eq!(grade(&long_tenor), RiskGrade::D);
+
+    let oversized = RiskFactors { average_amount: 100_0000000, ..base.clone() };
+    assert_eq!(risk_points(&oversized), 15);
+
+    let secured = RiskFactors { trust_score: 100, has_proof: true, is_verified: true, debtor_acknowledged: true, ..base.clone() };
+    assert_eq!(risk_points(&secured), 70);
+    assert_eq!(grade(&secured), RiskGrade::A);
+
+    let acknowledged = RiskFactors { debtor_acknowledged: true, ..base.clone() };
+    assert_eq!(risk_points(&acknowledged), 35);
+
+    // Points never go below zero or above 100
+    let defaulter = RiskFactors { defaulted_invoices: 10, debtor_defaulted: 10, ..base.clone() };
+    assert_eq!(risk_points(&defaulter), 0);
+    let perfect = RiskFactors { completed_invoices: 50, debtor_paid: 50, average_amount: 1000_0000000, ..secured };
+    assert_eq!(risk_points(&perfect), 95);
+}
+
+#[test]
+fn test_grade_thresholds() {
+    assert_eq!(grade_for_points(100), RiskGrade::A);
+    assert_eq!(grade_for_points(70), RiskGrade::A);
+    assert_eq!(grade_for_points(69), RiskGrade::B);
+    assert_eq!(grade_for_points(50), RiskGrade::B);
+    assert_eq!(grade_for_points(49), RiskGrade::C);
+    assert_eq!(grade_for_points(25), RiskGrade::C);
+    assert_eq!(grade_for_points(24), RiskGrade::D);
+    assert_eq!(grade_for_points(0), RiskGrade::D);
+}
+
+#[test]
+fn test_risk_band_membership() {
+    let prime = RiskBand { best: RiskGrade::A, worst: RiskGrade::B };
+    let mid = RiskBand { best: RiskGrade::B, worst: RiskGrade::C };
+    let high_yield = RiskBand { best: RiskGrade::C, worst: RiskGrade::D };
+
+    assert!(prime.is_valid());
+    assert!(!RiskBand { best: RiskGrade::D, worst: RiskGrade::C }.is_valid());
+
+    assert!(prime.contains(RiskGrade::A));
+    assert!(prime.contains(RiskGrade::B));
+    assert!(!prime.contains(RiskGrade::C));
+
+    assert!(prime.overlaps(&mid));
+    assert!(mid.overlaps(&high_yield));
+    assert!(!prime.overlaps(&high_yield));
+    assert!(!high_yield.overlaps(&prime));
+}
+
+#[test]
+fn test_quote_derives_consistent_terms() {
+    let env = Env::default();
+    let config = default_config(&env);
+
+    let terms = quote(&config, RiskGrade::B, 50, 1000_0000000, 60);
+    assert_eq!(terms.discount_rate, 650);
+    assert_eq!(terms.offer_amount, 935_0000000);
+    assert_eq!(terms.expected_yield, annualized_yield(650, 60));
+    assert_eq!(annualized_yield(500, 365), 500);
+    assert_eq!(annualized_yield(500, 0), 500 * 365);
+}
+
+#[test]
+fn test_config_validation() {
+    let env = Env::default();
+    let config = default_config(&env);
+    assert!(is_valid_config(&config));
+
+    let mut unsorted = config.clone();
+    unsorted.tenor_buckets = vec![&env, 30, 30, 90, 180, 365];
+    assert!(!is_valid_config(&unsorted));
+
+    let mut missing_grade = config.clone();
+    missing_grade.grade_rates.pop_back();
+    assert!(!is_valid_config(&missing_grade));
+
+    let mut short_row = config.clone();
+    short_row.grade_rates.set(0, vec![&env, 300]);
+    assert!(!is_valid_config(&short_row));
+
+    let mut inverted = config.clone();
+    inverted.min_rate = inverted.max_rate + 1;
+    assert!(!is_valid_config(&inverted));
+
+    let mut tiers = config.clone();
+    tiers.trust_tiers.push_back(TrustTier { min_score: 10, adjustment: 0 });
+    assert!(!is_valid_config(&tiers));
+}
+
+#[test]
+fn test_risk_grade_labels() {
+    let env = Env::default();
+
+    assert_eq!(RiskGrade::from_label(&String::from_str(&env, "A")), Some(RiskGrade::A));
+    assert_eq!(RiskGrade::from_label(&String::from_str(&env, "D")), Some(RiskGrade::D));
+    assert_eq!(RiskGrade::from_label(&String::from_str(&env, "Z")), None);
+    assert_eq!(RiskGrade::from_label(&String::from_str(&env, "AA")), None);
+    assert_eq!(RiskGrade::from_label(&String::from_str(&env, "")), None);
+}
+
+#[test]
+fn test_asset_config_validation() {
+    let env = Env::default();
+    let config = AssetConfig {
+        token: Address::generate(&env),
+        decimals: 7,
+        min_amount: 100,
+        max_amount: 1000,
+    };
+    assert!(is_valid_asset_config(&config));
+    assert!(is_within_limits(&config, 100));
+    assert!(is_within_limits(&config, 1000));
+    assert!(!is_within_limits(&config, 99));
+    assert!(!is_within_limits(&config, 1001));
+
+    let single_amount = AssetConfig { max_amount: 100, ..config.clone() };
+    assert!(is_valid_asset_config(&single_amount));
+
+    assert!(!is_valid_asset_config(&AssetConfig { min_amount: 0, ..config.clone() }));
+    assert!(!is_valid_asset_config(&AssetConfig { max_amount: 99, ..config.clone() }));
+    assert!(!is_valid_asset_config(&AssetConfig { decimals: MAX_DECIMALS + 1, ..config }));
+}
+
+#[test]
+fn test_fingerprint_normalization() {
+    let mut out = [0u8; MAX_FIELD_LEN];
+    let len = normalize(b" INV 0042\t", &mut out);
+    assert_eq!(&out[..len], b"inv0042");
+
+    let env = Env::default();
+    let creator = Address::generate(&env);
+    let debtor_salt = BytesN::from_array(&env, &[5u8; 32]);
+    let debtor = debtor_id(&env, &debtor_salt, &String::from_str(&env, "billing@acme.com"));
+    let number = String::from_str(&env, "INV-0042");
+    let fingerprint = invoice_fingerprint(&env, &creator, &debtor, 1000, &number);
+
+    // Case and whitespace do not change the fingerprint
+    assert_eq!(invoice_fingerprint(&env, &creator, &debtor, 1000, &String::from_str(&env, "inv-0042 ")), fingerprint);
+
+    // Any other creator, debtor, amount or number does
+    let other_debtor = debtor_id(&env, &debtor_salt, &String::from_str(&env, "ap@acme.com"));
+    assert_ne!(invoice_fingerprint(&env, &Address::generate(&env), &debtor, 1000, &number), fingerprint);
+    assert_ne!(invoice_fingerprint(&env, &creator, &other_debtor, 1000, &number), fingerprint);
+    assert_ne!(invoice_fingerprint(&env, &creator, &debtor, 1001, &number), fingerprint);
+    assert_ne!(invoice_fingerprint(&env, &creator, &debtor, 1000, &String::from_str(&env, "INV-0043")), fingerprint);
+}
+
+#[test]
+#[should_panic(expected = "Invoice field too long")]
+fn test_fingerprint_field_too_long() {
+    let env = Env::default();
+    let long = String::from_bytes(&env, &[b'a'; MAX_FIELD_LEN + 1]);
+    let debtor = debtor_id(&env, &BytesN::from_array(&env, &[5u8; 32]), &String::from_str(&env, "billing@acme.com"));
+    invoice_fingerprint(&env, &Address::generate(&env), &debtor, 1000, &long);
+}
+
+#[test]
+fn test_customer_commitment() {
+    let env = Env::default();
+    let salt = BytesN::from_array(&env, &[3u8; 32]);
+    let debtor_salt = BytesN::from_array(&env, &[5u8; 32]);
+    let name = String::from_str(&env, "Globex Corporation");
+    let email = String::from_str(&env, "AP@Globex.com");
+    let commitment = customer_commitment(&env, &salt, &debtor_salt, &name, &email, None);
+
+    assert_eq!(commitment.name_hash, commit(&env, &salt, &name));
+    assert_eq!(commitment.email_hash, commit(&env, &salt, &email));
+
+    // Commitments cover the exact value under the salt
+    assert_ne!(commit(&env, &salt, &String::from_str(&env, "ap@globex.com")), commitment.email_hash);
+    assert_ne!(commit(&env, &BytesN::from_array(&env, &[4u8; 32]), &email), commitment.email_hash);
+
+    // The debtor id ignores case and whitespace so history follows the debtor
+    assert_eq!(commitment.debtor_id, debtor_id(&env, &debtor_salt, &String::from_str(&env, " ap@globex.com")));
+    assert_ne!(commitment.debtor_id, debtor_id(&env, &debtor_salt, &String::from_str(&env, "ar@globex.com")));
+}
+
+#[test]
+fn test_debtor_id_salted() {
+    let env = Env::default();
+    let email = String::from_str(&env, "ap@globex.com");
+    let debtor = debtor_id(&env, &BytesN::from_array(&env, &[5u8; 32]), &email);
+
+    // Without the debtor's salt, the id can't be recomputed from a guessed email
+    assert_ne!(debtor_id(&env, &BytesN::from_array(&env, &[6u8; 32]), &email), debtor);
+    assert_ne!(env.crypto().sha256(&Bytes::from_slice(&env, b"ap@globex.com")).to_bytes(), debtor);
+}
+
+#[test]
+#[should_panic(expected = "Customer field too long")]
+fn test_customer_commitment_field_too_long() {
+    let env = Env::default();
+    let long = String::from_bytes(&env, &[b'a'; MAX_VALUE_LEN + 1]);
+    commit(&env, &BytesN::from_array(&env, &[0u8; 32]), &long);
+}
+
+#[test]
+fn test_tax_breakdown() {
+    // 10% on top of a net amount
+    let net = breakdown(1000_0000000, Some(1000), TaxBasis::Net);
+    assert_eq!(net, TaxBreakdown { net_amount: 1000_0000000, tax_amount: 100_0000000, gross_amount: 1100_0000000 });
+
+    // The same invoice stated gross splits back to the same parts
+    assert_eq!(breakdown(1100_0000000, Some(1000), TaxBasis::Gross), net);
+
+    // Inexact splits round the tax down
+    let odd = breakdown(100, Some(1900), TaxBasis::Gross);
+    assert_eq!(odd, TaxBreakdown { net_amount: 85, tax_amount: 15, gross_amount: 100 });
+    assert_eq!(breakdown(99, Some(1000), TaxBasis::Net).tax_amount, 9);
+
+    // Untaxed invoices are the same on either basis
+    let untaxed = TaxBreakdown { net_amount: 500, tax_amount: 0, gross_amount: 500 };
+    assert_eq!(breakdown(500, None, TaxBasis::Net), untaxed);
+    assert_eq!(breakdown(500, Some(0), TaxBasis::Gross), untaxed);
+
+    assert_eq!(financeable_amount(&net, FinancingRule::Gross), 1100_0000000);
+    assert_eq!(financeable_amount(&net, FinancingRule::Net), 1000_0000000);
+
+    assert!(is_valid_rate(MAX_TAX_RATE));
+    assert!(!is_valid_rate(MAX_TAX_RATE + 1));
+}
+
+#[test]
+#[should_panic(expected = "Invalid tax rate")]
+fn test_tax_breakdown_rate_too_high() {
+    breakdown(1000, Some(MAX_TAX_RATE + 1), TaxBasis::Net);
+}
+
+#[test]
+fn test_offer_and_expected_return() {
+    let amount = 1000_0000000i128;
+
+    assert_eq!(offer_amount(amount, 500), 950_0000000);
+    assert_eq!(offer_amount(amount, 0), amount);
+    assert_eq!(expected_return(800_0000000, 500), 840_0000000);
+}
+
+#[test]
+fn test_late_fee_rate_is_capped() {
+    assert_eq!(late_fee_rate(0, 10, 500), 0);
+    assert_eq!(late_fee_rate(20, 10, 500), 200);
+    assert_eq!(late_fee_rate(100, 10, 500), 500);
+}
+
+#[test]
+fn test_early_discount_rate() {
+    let start = 1_000_000;
+    let due_date = start + 30 * SECONDS_PER_DAY;
+
+    // A flat discount applies in full until the due date
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Flat, start, start, due_date), 300);
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Flat, start, due_date - 1, due_date), 300);
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Flat, start, due_date, due_date), 0);
+
+    // A sliding discount shrinks with the time left
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Sliding, start, start, due_date), 300);
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Sliding, start, start + 10 * SECONDS_PER_DAY, due_date), 200);
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Sliding, start, due_date, due_date), 0);
+
+    // Paying before financing starts earns no more than the full discount
+    assert_eq!(early_discount_rate(300, EarlyDiscountSchedule::Sliding, start, start - 1, due_date), 300);
+}
+
+#[test]
+fn test_recurring_schedule() {
+    let due_date = 100 * SECONDS_PER_DAY;
+    let schedule = RecurringSchedule {
+        period_days: 30,
+        end_date: due_date + 60 * SECONDS_PER_DAY,
+        max_occurrences: 3,
+        auto_submit_limit: Some(1000),
+    };
+    assert!(is_valid_schedule(&schedule, due_date));
+    assert!(!is_valid_schedule(&schedule, schedule.end_date + 1));
+    assert!(!is_valid_schedule(&RecurringSchedule { period_days: 0, ..schedule.clone() }, due_date));
+    assert!(!is_valid_schedule(&RecurringSchedule { max_occurrences: 0, ..schedule.clone() }, due_date));
+    assert!(!is_valid_schedule(&RecurringSchedule { auto_submit_limit: Some(0), ..schedule.clone() }, due_date));
+
+    // Each occurrence is issued a period before it falls due
+    assert_eq!(issue_date(due_date, 30), 70 * SECONDS_PER_DAY);
+    assert_eq!(issue_date(10, 30), 0);
+
+    // Missed periods are skipped rather than minted already due
+    assert_eq!(next_due_date(due_date, 30, due_date - 1), due_date);
+    assert_eq!(next_due_date(due_date, 30, due_date), due_date + 30 * SECONDS_PER_DAY);
+    assert_eq!(next_due_date(due_date, 30, due_date + 45 * SECONDS_PER_DAY), due_date + 60 * SECONDS_PER_DAY);
+
+    assert!(is_auto_submitted(&schedule, 1000));
+    assert!(!is_auto_submitted(&schedule, 1001));
+    assert!(!is_auto_submitted(&RecurringSchedule { auto_submit_limit: None, ..schedule }, 1));
+}
+
+#[test]
+fn test_installment_schedule() {
+    let env = Env::default();
+    let first = 30 * SECONDS_PER_DAY;
+    let second = 60 * SECONDS_PER_DAY;
+    let schedule = vec![
+        &env,
+        Installment { amount: 400, due_date: first },
+        Installment { amount: 600, due_date: second },
+    ];
+    assert!(is_valid_installments(&schedule, 1000, second));
+    assert!(!is_valid_installments(&schedule, 999, second));
+    assert!(!is_valid_installments(&schedule, 1000, first));
+    assert!(!is_valid_installments(&vec![&env], 0, second));
+    assert!(!is_valid_installments(&vec![&env, schedule.get_unchecked(1), schedule.get_unchecked(0)], 1000, first));
+    assert!(!is_valid_installments(&vec![&env, Installment { amount: 0, due_date: first }, schedule.get_unchecked(1)], 600, second));
+
+    let mut long = Vec::new(&env);
+    for n in 1..=MAX_INSTALLMENTS as u64 + 1 {
+        long.push_back(Installment { amount: 1, due_date: n * SECONDS_PER_DAY });
+    }
+    assert!(!is_valid_installments(&long, MAX_INSTALLMENTS as i128 + 1, (MAX_INSTALLMENTS as u64 + 1) * SECONDS_PER_DAY));
+
+    // Payments cover installments in due-date order
+    assert_eq!(next_unpaid(&schedule, 0), Some(Installment { amount: 400, due_date: first }));
+    assert_eq!(next_unpaid(&schedule, 250), Some(Installment { amount: 150, due_date: first }));
+    assert_eq!(next_unpaid(&schedule, 400), Some(Installment { amount: 600, due_date: second }));
+    assert_eq!(next_unpaid(&schedule, 1000), None);
+
+    assert_eq!(arrears(&schedule, 0, first - 1), 0);
+    assert_eq!(arrears(&schedule, 100, first), 300);
+    assert_eq!(arrears(&schedule, 500, first), 0);
+    assert_eq!(arrears(&schedule, 500, second), 500);
+
+    // An invoice without a schedule is one installment
+    let single = or_single(&env, Vec::new(&env), 1000, second);
+    assert_eq!(single, vec![&env, Installment { amount: 1000, due_date: second }]);
+    assert_eq!(or_single(&env, schedule.clone(), 1000, second), schedule);
+}
+
+#[test]
+fn test_required_collateral() {
+    let env = Env::default();
+    let ratios = default_ratios(&env);
+    assert!(is_valid_ratios(&ratios));
+    assert!(!is_valid_ratios(&vec![&env, 0, 500, 1000]));
+    assert!(!is_valid_ratios(&vec![&env, 0, 500, 1000, 10001]));
+
+    // Riskier grades lock a larger share of the invoice
+    assert_eq!(required_collateral(&ratios, RiskGrade::A, 1000_0000000), 0);
+    assert_eq!(required_collateral(&ratios, RiskGrade::C, 1000_0000000), 100_0000000);
+    assert_eq!(required_collateral(&ratios, RiskGrade::D, 1000_0000000), 200_0000000);
+    assert_eq!(required_collateral(&ratios, RiskGrade::B, 19), 0);
+}
+
+#[test]
+fn test_accrued_yield() {
+    let principal = 1000_0000000i128;
+
+    assert_eq!(accrued_yield(principal, 1000, SECONDS_PER_YEAR), 100_0000000);
+    assert_eq!(accrued_yield(principal, 1000, 0), 0);
+    assert_eq!(accrued_yield(principal, 1000, SECONDS_PER_YEAR / 2), 50_0000000);
+}
+
+#[test]
+fn test_bps_helpers() {
+    assert_eq!(apply_bps(1000, 250, Rounding::Down), 25);
+    assert_eq!(apply_bps(999, 1, Rounding::Down), 0);
+    assert_eq!(apply_bps(999, 1, Rounding::Up), 1);
+    assert_eq!(apply_percent(1000, 80, Rounding::Down), 800);
+    assert!(is_valid_bps(10_000));
+    assert!(!is_valid_bps(10_001));
+}
+
+#[test]
+fn test_mul_div_rounding() {
+    assert_eq!(mul_div(10, 1, 3, Rounding::Down), 3);
+    assert_eq!(mul_div(10, 1, 3, Rounding::Up), 4);
+    assert_eq!(mul_div(-10, 1, 3, Rounding::Down), -4);
+    assert_eq!(mul_div(-10, 1, 3, Rounding::Up), -3);
+    assert_eq!(mul_div(10, 1, -3, Rounding::Down), -4);
+    assert_eq!(mul_div(9, 1, 3, Rounding::Up), 3);
+}
+
+#[test]
+fn test_checked_mul_div_rejects_overflow() {
+    assert_eq!(checked_mul_div(i128::MAX, 2, 2, Rounding::Down), None);
+    assert_eq!(checked_mul_div(i128::MIN, 1, -1, Rounding::Down), None);
+    assert_eq!(checked_mul_div(1, 1, 0, Rounding::Down), None);
+}
+
+#[test]
+#[should_panic(expected = "Arithmetic overflow")]
+fn test_mul_div_overflow_panics() {
+    mul_div(i128::MAX, 10_000, 10_000, Rounding::Down);
+}
+
+#[test]
+#[should_panic(expected = "Division by zero")]
+fn test_mul_div_zero_denominator_panics() {
+    mul_div(1, 1, 0, Rounding::Down);
+}
+
+#[test]
+#[should_panic(expected = "Arithmetic overflow")]
+fn test_days_to_seconds_overflow_panics() {
+    days_to_seconds(u64::MAX);
+}
+
+/// Deterministic xorshift generator so property runs are reproducible
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// A token amount up to ~10^12 whole units at 7 decimals
+    fn amount(&mut self) -> i128 {
+        (self.next() as i128) % 10_000_000_000_000_000_000i128
+    }
+}
+
+const CASES: usize = 10_000;
+
+#[test]
+fn prop_rounding_brackets_exact_quotient() {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..CASES {
+        let value = rng.amount() - rng.amount();
+        let numerator = rng.below(1_000_000) as i128;
+        let denominator = rng.below(1_000_000) as i128 + 1;
+        let exact = value * numerator;
+
+        let down = mul_div(value, numerator, denominator, Rounding::Down);
+        let up = mul_div(value, numerator, denominator, Rounding::Up);
+
+        assert!(down * denominator <= exact);
+        assert!(up * denominator >= exact);
+        assert!(up - down <= 1);
+        assert_eq!(up == down, exact % denominator == 0);
+    }
+}
+
+#[test]
+fn prop_fee_splits_never_exceed_total() {
+    let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+    for _ in 0..CASES {
+        let total = rng.amount();
+        let share = rng.below(BPS_DENOMINATOR as u64 + 1) as u32;
+
+        let part = apply_bps(total, share, Rounding::Down);
+        let rest = apply_bps(total, BPS_DENOMINATOR - share, Rounding::Down);
+
+        assert!(part >= 0 && part <= total);
+        assert!(part + rest <= total);
+        assert!(total - part - rest <= 1);
+    }
+}
+
+#[test]
+fn prop_offer_never_exceeds_discounted_value() {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for _ in 0..CASES {
+        let amount = rng.amount();
+        let rate = rng.below(BPS_DENOMINATOR as u64) as u32;
+        let offer = offer_amount(amount, rate);
+
+        // The creator never receives more than the face value less the discount
+        assert!(offer * BPS_DENOMINATOR as i128 <= amount * (BPS_DENOMINATOR - rate) as i128);
+        assert!(offer <= amount);
+        assert!(offer >= 0);
+    }
+}
+
+#[test]
+fn prop_returns_and_yield_never_exceed_rate() {
+    let mut rng = Rng(0x8CB9_2BA7_2F3D_8DD7);
+    for _ in 0..CASES {
+        let principal = rng.amount();
+        let rate = rng.below(5_000) as u32;
+        let elapsed = rng.below(SECONDS_PER_YEAR * 3);
+
+        let owed = expected_return(principal, rate);
+        assert!((owed - principal) * BPS_DENOMINATOR as i128 <= principal * rate as i128);
+        assert!(owed >= principal);
+
+        let earned = accrued_yield(principal, rate, elapsed);
+        let exact = principal * rate as i128 * elapsed as i128;
+        assert!(earned * (BPS_DENOMINATOR as i128 * SECONDS_PER_YEAR as i128) <= exact);
+        assert!(earned >= 0);
+    }
+}
+
+#[test]
+fn prop_default_grid_is_monotonic_and_bounded() {
+    let env = Env::default();
+    env.cost_estimate().budget().reset_unlimited();
+    let config = default_config(&env);
+    let mut rng = Rng(0x6A09_E667_F3BC_C908);
+    for _ in 0..CASES {
+        let days = rng.below(1_000);
+        let later = days + rng.below(400);
+        let score = rng.below(101) as u32;
+        let better_score = (score + rng.below(101) as u32).min(100);
+
+        for (row, grade) in GRADES.iter().enumerate() {
+            let rate = discount_rate(&config, *grade, days, score);
+
+            // Longer tenors, riskier grades and lower trust never get a smaller discount
+            assert!(rate <= discount_rate(&config, *grade, later, score));
+            assert!(rate >= discount_rate(&config, *grade, days, better_score));
+            if let Some(riskier) = GRADES.get(row + 1) {
+                assert!(rate <= discount_rate(&config, *riskier, days, score));
+            }
+            assert!(rate >= config.min_rate && rate <= config.max_rate);
+        }
+    }
+}
+
+#[test]
+fn test_day_counting() {
+    assert_eq!(days_between(0, SECONDS_PER_DAY - 1), 0);
+    assert_eq!(days_between(0, SECONDS_PER_DAY * 30 + 5), 30);
+    assert_eq!(days_between(SECONDS_PER_DAY * 10, 0), 0);
+    assert_eq!(days_to_seconds(7), 7 * SECONDS_PER_DAY);
+}
+
+#[test]
+fn test_status_classification() {
+    assert!(InvoiceStatus::Pending.is_live());
+    assert!(InvoiceStatus::Overdue.is_live());
+    assert!(!InvoiceStatus::Completed.is_live());
+    assert!(!InvoiceStatus::Cancelled.is_live());
+    assert!(InvoiceStatus::Funded.is_outstanding());
+    assert!(InvoiceStatus::PartiallyPaid.is_live());
+    assert!(InvoiceStatus::PartiallyPaid.is_outstanding());
+    assert!(!InvoiceStatus::Approved.is_outstanding());
+}
+
+#[test]
+fn test_attestation_validity() {
+    let env = Env::default();
+    let attestation = Attestation {
+        verifier: Address::generate(&env),
+        source: symbol_short!("stripe"),
+        external_id: String::from_str(&env, "in_1001"),
+        matched_amount: 1000,
+        confidence: MIN_CONFIDENCE,
+        attested_at: 100,
+        expires_at: 200,
+        revoked: false,
+    };
+
+    assert!(is_current(&attestation, 199));
+    assert!(!is_current(&attestation, 200));
+    assert!(!is_current(&Attestation { revoked: true, ..attestation.clone() }, 150));
+
+    assert!(is_conclusive(&attestation, 1000));
+    assert!(!is_conclusive(&attestation, 999));
+    assert!(!is_conclusive(&Attestation { confidence: MIN_CONFIDENCE - 1, ..attestation }, 1000));
+}
@@ -0,0 +1,18 @@
+//! Day-count conventions
+//!
+//! Durations are counted in whole days of 86,400 seconds, rounded down, on an
+//! actual/365 basis.
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+pub const DAYS_PER_YEAR: u64 = 365;
+pub const SECONDS_PER_YEAR: u64 = SECONDS_PER_DAY * DAYS_PER_YEAR;
+
+/// Whole days from `from` to `to`, or 0 if `to` is not after `from`
+pub fn days_between(from: u64, to: u64) -> u64 {
+    to.saturating_sub(from) / SECONDS_PER_DAY
+}
+
+/// Length of `days` whole days in seconds
+pub fn days_to_seconds(days: u64) -> u64 {
+    days.checked_mul(SECONDS_PER_DAY).expect("Arithmetic overflow")
+}
@@ -0,0 +1,16 @@
+//! Storage TTL policy shared by all contracts
+//!
+//! Instance storage is extended on every call. Live persistent records are
+//! extended whenever they are read or written; settled records keep their
+//! remaining TTL and are left to expire.
+
+// Ledgers close roughly every 5 seconds
+pub const DAY_IN_LEDGERS: u32 = 17280;
+
+/// Instance storage is extended to ~30 days whenever it drops below ~29
+pub const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+/// Live persistent records are extended to ~120 days whenever they drop below ~113
+pub const PERSISTENT_BUMP_AMOUNT: u32 = 120 * DAY_IN_LEDGERS;
+pub const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - 7 * DAY_IN_LEDGERS;
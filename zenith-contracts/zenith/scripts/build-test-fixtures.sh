@@ -0,0 +1,15 @@
+#!/usr/bin/env sh
+# Rebuild the release wasm the upgrade and factory tests load from `testdata/`.
+# Run from anywhere; the fixtures are always built from the current sources.
+set -e
+
+cd "$(dirname "$0")/.."
+
+cargo build --target wasm32v1-none --release
+
+release=target/wasm32v1-none/release
+cp "$release/invoice_nft.wasm" contracts/invoice-nft/testdata/invoice_nft.wasm
+cp "$release/liquidity_pool.wasm" contracts/liquidity-pool/testdata/liquidity_pool.wasm
+cp "$release/marketplace.wasm" contracts/marketplace/testdata/marketplace.wasm
+cp "$release/pool_factory.wasm" contracts/pool-factory/testdata/pool_factory.wasm
+cp "$release/liquidity_pool.wasm" contracts/pool-factory/testdata/liquidity_pool.wasm